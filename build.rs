@@ -0,0 +1,61 @@
+/// Canistorage
+///
+/// Copyright© 2025 toshio
+///
+/// Embeds build information (see `buildInfo`)
+use std::fs;
+use std::process::Command;
+
+fn main() {
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+    println!("cargo:rerun-if-changed=Cargo.lock");
+
+    let git_hash = command_output("git", &["rev-parse", "--short", "HEAD"]);
+    println!("cargo:rustc-env=CANISTORAGE_GIT_HASH={}", git_hash);
+
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let rustc_version = command_output(&rustc, &["--version"]);
+    println!("cargo:rustc-env=CANISTORAGE_RUSTC_VERSION={}", rustc_version);
+
+    println!("cargo:rustc-env=CANISTORAGE_IC_CDK_VERSION={}", locked_version("ic-cdk"));
+}
+
+/// returns the first line of the output, or "unknown" if the command fails
+fn command_output(program:&str, args:&[&str]) -> String {
+    Command::new(program)
+        .args(args)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .and_then(|text| text.lines().next().map(|line| line.trim().to_string()))
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// returns the version of the dependency in Cargo.lock, or "unknown"
+///
+/// If several versions are locked, the one canistorage depends on directly is chosen.
+fn locked_version(name:&str) -> String {
+    let Ok(lock) = fs::read_to_string("Cargo.lock") else {
+        return "unknown".to_string();
+    };
+    let packages:Vec<&str> = lock.split("[[package]]").collect();
+    let field = |package:&str, key:&str| package.lines()
+        .find_map(|line| line.strip_prefix(&format!("{} = \"", key)))
+        .map(|value| value.trim_end_matches('"').to_string());
+
+    // "ic-cdk 0.18.0" when several versions are locked, "ic-cdk" otherwise
+    let direct = packages.iter()
+        .find(|package| field(package, "name").as_deref() == Some(env!("CARGO_PKG_NAME")))
+        .and_then(|package| package.lines()
+            .map(|line| line.trim().trim_matches(|c| c == '"' || c == ','))
+            .find_map(|dependency| dependency.strip_prefix(&format!("{} ", name)).map(|version| version.to_string())));
+    if let Some(version) = direct {
+        return version;
+    }
+    packages.iter()
+        .find(|package| field(package, "name").as_deref() == Some(name))
+        .and_then(|package| field(package, "version"))
+        .unwrap_or_else(|| "unknown".to_string())
+}
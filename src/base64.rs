@@ -0,0 +1,87 @@
+/// Canistorage
+///
+/// Copyright© 2025 toshio
+///
+/// Minimal base64 (RFC 4648, standard alphabet) encoder/decoder
+const ALPHABET:&[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// encodes the data in base64 with padding
+pub fn encode(data:&[u8]) -> String {
+    let mut text = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, byte)| bits | (*byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                text.push(ALPHABET[(bits >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+/// decodes base64 (padding is optional)
+///
+/// Returns None if the text contains characters out of the alphabet or has an invalid length.
+pub fn decode(text:&str) -> Option<Vec<u8>> {
+    let text = text.as_bytes();
+    let text = match text.iter().rposition(|c| *c != b'=') {
+        Some(last) if text.len() - last - 1 <= 2 => &text[..=last],
+        Some(_) => return None,
+        None if text.is_empty() => text,
+        None => return None,
+    };
+    if text.len() % 4 == 1 {
+        return None;
+    }
+
+    let mut data = Vec::with_capacity(text.len() * 3 / 4);
+    for chunk in text.chunks(4) {
+        let mut bits = 0u32;
+        for (i, c) in chunk.iter().enumerate() {
+            let value = ALPHABET.iter().position(|a| a == c)? as u32;
+            bits |= value << (18 - 6 * i);
+        }
+        for i in 0..chunk.len() - 1 {
+            data.push((bits >> (16 - 8 * i)) as u8);
+        }
+    }
+    Some(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        // RFC 4648 test vectors
+        assert_eq!(encode(b""), "");
+        assert_eq!(encode(b"f"), "Zg==");
+        assert_eq!(encode(b"fo"), "Zm8=");
+        assert_eq!(encode(b"foo"), "Zm9v");
+        assert_eq!(encode(b"foob"), "Zm9vYg==");
+        assert_eq!(encode(b"fooba"), "Zm9vYmE=");
+        assert_eq!(encode(b"foobar"), "Zm9vYmFy");
+        assert_eq!(encode(&[0xfb, 0xff]), "+/8=");
+    }
+
+    #[test]
+    fn test_decode() {
+        for data in ["", "f", "fo", "foo", "foob", "fooba", "foobar"] {
+            assert_eq!(decode(&encode(data.as_bytes())).unwrap(), data.as_bytes());
+        }
+        assert_eq!(decode("Zm8").unwrap(), b"fo"); // without padding
+        assert_eq!(decode("+/8=").unwrap(), [0xfb, 0xff]);
+        let data:Vec<u8> = (0..=255).collect();
+        assert_eq!(decode(&encode(&data)).unwrap(), data);
+
+        // invalid
+        assert!(decode("Zm9v!").is_none());
+        assert!(decode("Z").is_none());
+        assert!(decode("Zg===").is_none());
+        assert!(decode("=").is_none());
+        assert!(decode("Zm 9v").is_none());
+    }
+}
@@ -2,18 +2,48 @@
 /// 
 /// Copyright© 2025 toshio
 ///
+use std::borrow::Cow;
 use std::cell::RefCell;
 use std::cmp::{self, Ordering};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs::{self, File, OpenOptions};
-use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write, ErrorKind};
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write, ErrorKind};
 use serde::{Serialize, Deserialize};
 use candid::{CandidType, Principal};
 use sha2::{Sha256, Digest};
+use crate::base64;
+use crate::gzip;
+use ic_stable_structures::{memory_manager::VirtualMemory, storable::Bound, DefaultMemoryImpl, StableBTreeMap, StableCell, Storable};
 
 const MIMETYPE_DIRECTORY: &str = "canistorage/directory";
+const MIMETYPE_OCTET_STREAM: &str = "application/octet-stream";
+const INTERNAL_PREFIX: &str = "`"; // every internal file starts with it (rejected by validate_path)
+const METADATA_PREFIX: &str = "`"; // metadata of files kept by older versions
+const TEMP_PREFIX: &str = "``"; // file being saved
+const BACKUP_PREFIX: &str = "```"; // content to restore when a batch fails
+const BLOB_DIRECTORY_NAME: &str = "`blobs"; // shared blobs (content-addressed mode)
 const MAX_PATH:usize = 1024;
+const MAX_NAME:usize = 240; // bytes of a component of a path (room for the prefixes of temp/backup files within 255)
+const MAX_DEPTH:usize = 64; // components under ROOT (bounds the walk of permission checks)
 const MAX_READ_SIZE:usize = 1024 * 1024;
+const MAX_FILE_SIZE:u64 = 1024 * 1024 * 1024; // chunks are kept in heap memory until commit
+const CHUNK_SIZE:u64 = MAX_READ_SIZE as u64; // recommended chunk size for uploading
+const MAX_BATCH_SIZE:usize = 100;
+const MAX_TREE_HASH_SIZE:usize = 10000; // entries hashed by a treeHash/directoryMerkleRoot/merkleProof call
+const MAX_PREFIX_HASH_SIZE:u64 = 16 * 1024 * 1024; // bytes hashed by a loadWithPrefixHash call
+const MAX_SIGNATURE_SIZE:usize = 1024; // bytes of a detached signature (kept in the metadata)
+const MAX_INGRESS_SIZE:usize = 2 * 1024 * 1024; // bytes of the data saved by an update call
+const MAX_AUDIT_SIZE:usize = 1000; // paths returned by auditPrincipal/findModified
+const MAX_PERMISSION_TARGETS:usize = 1000; // paths updated by a recursive copyPermissions call
+const MAX_MANIFEST_SIZE:usize = 1000; // entries returned by manifest
+const MAX_COPY_STEP:usize = 100; // entries processed per copyDirectory/moveDirectory call
+const MAX_REPORTED_FAILURES:usize = 20; // paths listed in the error of a recursive delete
+const MAX_HTTP_SIZE:u64 = 2 * 1024 * 1024; // files served over HTTP (a response must be less than 3MiB)
+const DEFAULT_LOCK_TTL:u64 = 10 * 60 * 1000; // 10 minutes
+const UPLOAD_TIMEOUT:u64 = 10 * 60 * 1000; // upload sessions expire after 10 minutes of inactivity
+const WASM_PAGE_SIZE:u64 = 64 * 1024;
+const MAX_STABLE_MEMORY:u64 = 500 * 1024 * 1024 * 1024; // stable memory limit of a canister
+const STORAGE_MARGIN:u64 = 64 * 1024 * 1024; // kept free for metadata and temp files
 
 const ERROR_NOT_FOUND: u32 = 1; // File or directory not found
 const ERROR_ALREADY_EXISTS: u32 = 2; // Fire or directory already exists
@@ -24,6 +54,18 @@ const ERROR_INVALID_SEQUENCE: u32 = 6;
 const ERROR_INVALID_SIZE: u32 = 7;
 const ERROR_INVALID_HASH: u32 = 8;
 const ERROR_ALREADY_INITIALIZED: u32 = 9;
+const ERROR_LAST_MANAGER: u32 = 10; // The last manager of ROOT can't be removed
+const ERROR_READ_ONLY: u32 = 11; // Canister is in read-only mode
+const ERROR_LOCKED: u32 = 12; // Locked by another principal
+const ERROR_IS_DIRECTORY: u32 = 13; // Directory is specified where a file is expected
+const ERROR_NOT_A_DIRECTORY: u32 = 14; // File is specified where a directory is expected
+const ERROR_INVALID_SIGNATURE: u32 = 15; // Signature doesn't match sha256 of the file
+const ERROR_LOW_CYCLES: u32 = 16; // Cycle balance is below the configured minimum
+const ERROR_OUT_OF_SPACE: u32 = 17; // Not enough storage left for the data
+const ERROR_DIRECTORY_NOT_EMPTY: u32 = 18; // Directory has children (delete recursively)
+const ERROR_INVALID_ENCODING: u32 = 19; // Data isn't valid base64
+const ERROR_QUOTA_EXCEEDED: u32 = 20; // Data exceeds the quota of the owner
+const ERROR_VERSION_CONFLICT: u32 = 21; // Content has changed since the caller read it
 const ERROR_UNKNOWN: u32 = u32::MAX;
 
 /////////////////////////////////////////////////////////////////////////////
@@ -43,11 +85,11 @@ fn time() -> u64 {
 
 #[cfg(test)]
 thread_local! {
-    static CALLER:RefCell<Principal> = RefCell::new(Principal::anonymous());
+    static CALLER:RefCell<Principal> = const { RefCell::new(Principal::anonymous()) };
 }
 
 #[cfg(test)]
-fn set_caller(principal:Principal) -> () {
+fn set_caller(principal:Principal) {
     CALLER.with(|caller| {
         *caller.borrow_mut() = principal;
     })
@@ -59,6 +101,81 @@ fn caller() -> Principal {
     })
 }
 
+/// Returns the id of this canister
+#[cfg(test)]
+fn canister_id() -> Principal {
+    Principal::from_text("rrkah-fqaaa-aaaaa-aaaaq-cai").unwrap()
+}
+
+#[cfg(test)]
+thread_local! {
+    static CYCLE_BALANCE:RefCell<u128> = const { RefCell::new(u64::MAX as u128) };
+    static STABLE_SIZE:RefCell<u64> = const { RefCell::new(0) };
+    static RENAME_FAILURE:RefCell<bool> = const { RefCell::new(false) };
+    static REMOVE_FAILURE:RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+#[cfg(test)]
+fn set_cycle_balance(cycles:u128) {
+    CYCLE_BALANCE.with(|balance| {
+        *balance.borrow_mut() = cycles;
+    })
+}
+
+#[cfg(test)]
+fn cycle_balance() -> u128 {
+    CYCLE_BALANCE.with(|balance| {
+        *balance.borrow()
+    })
+}
+
+#[cfg(test)]
+fn set_stable_size(pages:u64) {
+    STABLE_SIZE.with(|size| {
+        *size.borrow_mut() = pages;
+    })
+}
+
+/// Returns the stable memory size in WebAssembly pages
+#[cfg(test)]
+fn stable_size() -> u64 {
+    STABLE_SIZE.with(|size| {
+        *size.borrow()
+    })
+}
+
+#[cfg(test)]
+fn set_rename_failure(failure:bool) {
+    RENAME_FAILURE.with(|value| {
+        *value.borrow_mut() = failure;
+    })
+}
+
+/// Renames a file (fails while set_rename_failure(true) to simulate filesystem errors)
+#[cfg(test)]
+fn rename_file(from:&str, to:&str) -> std::io::Result<()> {
+    if RENAME_FAILURE.with(|value| *value.borrow()) {
+        return Err(std::io::Error::other("Simulated rename failure"));
+    }
+    fs::rename(from, to)
+}
+
+#[cfg(test)]
+fn set_remove_failure(path:Option<String>) {
+    REMOVE_FAILURE.with(|value| {
+        *value.borrow_mut() = path;
+    })
+}
+
+/// Removes a file (fails for the path given to set_remove_failure to simulate filesystem errors)
+#[cfg(test)]
+fn remove_file(path:&str) -> std::io::Result<()> {
+    if REMOVE_FAILURE.with(|value| value.borrow().as_deref() == Some(path)) {
+        return Err(std::io::Error::other("Simulated remove failure"));
+    }
+    fs::remove_file(path)
+}
+
 /////////////////////////////////////////////////////////////////////////////
 // For Production
 /////////////////////////////////////////////////////////////////////////////
@@ -76,6 +193,33 @@ fn caller() -> Principal {
     ic_cdk::api::msg_caller()
 }
 
+/// Returns the id of this canister
+#[cfg(not(test))]
+fn canister_id() -> Principal {
+    ic_cdk::api::canister_self()
+}
+
+#[cfg(not(test))]
+fn cycle_balance() -> u128 {
+    ic_cdk::api::canister_cycle_balance()
+}
+
+/// Returns the stable memory size in WebAssembly pages
+#[cfg(not(test))]
+fn stable_size() -> u64 {
+    ic_cdk::api::stable_size()
+}
+
+#[cfg(not(test))]
+fn rename_file(from:&str, to:&str) -> std::io::Result<()> {
+    fs::rename(from, to)
+}
+
+#[cfg(not(test))]
+fn remove_file(path:&str) -> std::io::Result<()> {
+    fs::remove_file(path)
+}
+
 /////////////////////////////////////////////////////////////////////////////
 // Data Structures
 /////////////////////////////////////////////////////////////////////////////
@@ -104,21 +248,91 @@ pub struct FileInfo {
     manageable: Vec<Principal>, // Grant or Revoke permission
     readable: Vec<Principal>,
     writable: Vec<Principal>,
+    #[serde(default)]
+    denied_readable: Vec<Principal>, // Deny read even if inherited
+    #[serde(default)]
+    denied_writable: Vec<Principal>, // Deny write even if inherited
+    #[serde(default)]
+    default_manageable: Vec<Principal>, // Seeded onto new children (directory only)
+    #[serde(default)]
+    default_readable: Vec<Principal>, // Seeded onto new children (directory only)
+    #[serde(default)]
+    default_writable: Vec<Principal>, // Seeded onto new children (directory only)
     sha256: Option<[u8; 32]>,
-    signature: Option<Vec<u8>>,
+    signature: Option<Vec<u8>>, // Opaque detached signature, cleared when the content is written
+    #[serde(default)]
+    pubkey: Option<Vec<u8>>, // ed25519 public key which verified the signature
+    #[serde(default)]
+    blob: Option<[u8; 32]>, // Shared blob holding the content (content-addressed mode)
+    #[serde(default)]
+    accessed_at: Option<u64>, // Last read reported by recordAccess (milliseconds)
+    #[serde(default)]
+    track_access: bool, // Record accessed_at of the descendants (directory only)
+    #[serde(default)]
+    name: Option<String>, // Original casing of the name (case-insensitive mode)
+    #[serde(default)]
+    quota: Option<u64>, // Bytes of the files allowed in the subtree (directory only)
+    #[serde(default)]
+    listable: Vec<Principal>, // List the children without reading them (directory only)
+    #[serde(default)]
+    version: u64, // Incremented whenever the content is overwritten
 }
 
 impl FileInfo {
+    /// returns a new FileInfo without any permissions
+    fn new(creator:Principal, now:u64, mimetype:String) -> FileInfo {
+        FileInfo {
+            size: 0,
+            creator,
+            created_at: now,
+            updater: creator,
+            updated_at: now,
+            mimetype,
+            manageable: Vec::new(),
+            readable: Vec::new(),
+            writable: Vec::new(),
+            denied_readable: Vec::new(),
+            denied_writable: Vec::new(),
+            default_manageable: Vec::new(),
+            default_readable: Vec::new(),
+            default_writable: Vec::new(),
+            sha256: None,
+            signature: None,
+            pubkey: None,
+            blob: None,
+            accessed_at: None,
+            track_access: false,
+            name: None,
+            quota: None,
+            listable: Vec::new(),
+            version: 0,
+        }
+    }
+
     fn is_dir(&self) -> bool {
         self.mimetype == MIMETYPE_DIRECTORY
     }
 }
 
+impl Storable for FileInfo {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(serde_cbor::to_vec(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        serde_cbor::from_slice(&bytes).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
 #[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
 pub struct Permission {
     manageable: bool,
     writable: bool,
     readable: bool,
+    listable: bool, // can list the children (read permission includes it)
+    is_owner: bool, // creator of the path (not inherited)
 }
 
 #[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
@@ -128,24 +342,287 @@ pub struct Info {
     created_at: u64, // milliseconds
     updater: Principal,
     updated_at: u64, // milliseconds
+    version: u64, // incremented whenever the content is overwritten
     mimetype: String,
-    sha256: Option<[u8; 32]>,
+    sha256: Option<[u8; 32]>, // None: directory or saved by older versions (empty files have the hash of zero bytes)
+    sha256_hex: Option<String>, // sha256 in lowercase hex
+    pubkey: Option<Vec<u8>>, // ed25519 public key to re-verify the signature
+    accessed_at: Option<u64>, // milliseconds, if access tracking is enabled
+    child_count: u64, // files/directories in a directory (0 for a file)
+    is_dir: bool,
+    acl: Option<Acl>, // only for a caller with manage permission
+}
+
+/// explicit permissions of a path (inherited ones aren't included)
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Acl {
+    manageable: Vec<Principal>,
+    readable: Vec<Principal>,
+    writable: Vec<Principal>,
+    denied_readable: Vec<Principal>,
+    denied_writable: Vec<Principal>,
+    listable: Vec<Principal>,
+}
+
+/// an entry of a directory listing
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+pub struct DirEntry {
+    name: String, // original casing in case-insensitive mode
+    is_dir: bool,
+    size: u64, // bytes
+    mimetype: String,
+    updated_at: u64, // milliseconds
+}
+
+/// an entry of a manifest for synchronization
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+pub struct ManifestEntry {
+    path: String,
+    size: u64, // bytes
+    sha256: Option<[u8; 32]>, // None if saved by older versions
+    updated_at: u64, // milliseconds
+}
+
+/// request from the HTTP gateway
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+pub struct HttpRequest {
+    method: String,
+    url: String, // path and query string
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+pub struct HttpResponse {
+    status_code: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+/// key to sort a directory listing by
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum SortKey {
+    Name,
+    Size,
+    Updated,
+    Created,
+}
+
+/// directories whose `updated_at` follows the changes of their children
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum ParentUpdate {
+    #[default]
+    Off,
+    Parent, // the immediate parent directory
+    Ancestors, // every directory up to ROOT
+}
+
+/// Canister-wide settings (kept in stable memory)
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Config {
+    #[serde(default)]
+    read_only: bool,
+    #[serde(default = "default_lock_ttl")]
+    lock_ttl: u64, // milliseconds
+    #[serde(default)]
+    min_cycles: u64, // writes are refused below this balance (0: disabled)
+    #[serde(default = "default_max_storage")]
+    max_storage: u64, // bytes of stable memory available for storage
+    #[serde(default)]
+    content_addressed: bool, // new content is deduplicated into shared blobs
+    #[serde(default)]
+    case_insensitive: bool, // paths are case-folded into storage keys
+    #[serde(default)]
+    cors_origins: Vec<String>, // origins allowed to fetch over HTTP ("*": any, empty: same-origin only)
+    #[serde(default)]
+    mimetype_allow: Vec<String>, // mimetypes allowed to save (empty: any)
+    #[serde(default)]
+    mimetype_deny: Vec<String>, // mimetypes refused to save
+    #[serde(default)]
+    root: Option<String>, // directory mounted as ROOT (None: ROOT)
+    #[serde(default)]
+    parent_update: ParentUpdate, // directories whose updated_at is bumped by the changes of their children
+    #[serde(default)]
+    usage_built: bool, // USAGE and SUBTREE_SIZES have been built from the metadata
+    #[serde(default)]
+    migrated: bool, // metadata kept as backquote files has been imported into FILE_INFOS
+}
+
+fn default_lock_ttl() -> u64 {
+    DEFAULT_LOCK_TTL
+}
+
+fn default_max_storage() -> u64 {
+    MAX_STABLE_MEMORY
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            read_only: false,
+            lock_ttl: DEFAULT_LOCK_TTL,
+            min_cycles: 0,
+            max_storage: MAX_STABLE_MEMORY,
+            content_addressed: false,
+            case_insensitive: false,
+            cors_origins: Vec::new(),
+            mimetype_allow: Vec::new(),
+            mimetype_deny: Vec::new(),
+            root: None,
+            parent_update: ParentUpdate::Off,
+            usage_built: false,
+            migrated: false,
+        }
+    }
+}
+
+impl Storable for Config {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(serde_cbor::to_vec(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        serde_cbor::from_slice(&bytes).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+/// Operational status of the canister
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+pub struct Health {
+    cycles: u128,
+    stable_memory_pages: u64, // WebAssembly pages (64KiB)
+    remaining_bytes: u64, // estimated stable memory still available
+    min_cycles: u64, // writes are refused below this balance (0: disabled)
+}
+
+/// progress of a resumable directory copy
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+pub struct CopyProgress {
+    copied: u64, // entries copied by this call
+    done: bool,
+    token: Option<String>, // pass to the next call while not done
+}
+
+/// progress of a resumable directory move
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+pub struct MoveProgress {
+    moved: u64, // entries moved or removed from `from` by this call
+    done: bool,
+    token: Option<String>, // pass to the next call while not done
+}
+
+/// Constraints of the canister for clients to discover at runtime
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+pub struct Limits {
+    max_read_size: u64, // bytes returned by a load at most
+    max_file_size: u64, // bytes of a file uploaded with beginUpload
+    max_path: u64, // bytes of a path
+    max_name: u64, // bytes of a component of a path
+    max_depth: u64, // components of a path under ROOT
+    chunk_size: u64, // recommended chunk size for uploading
+    upload_timeout_ms: u64, // upload sessions expire after this inactivity
+    max_batch_size: u64, // paths/operations of a batch call
+    max_signature_size: u64, // bytes of a signature set by setSignature
+    max_dir_entries: Option<u64>, // entries of a directory (None: unlimited)
+}
+
+/// Mimetypes allowed/refused to save (e.g. "image/png", "image/*")
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+pub struct MimetypePolicy {
+    allow: Vec<String>, // empty: any
+    deny: Vec<String>, // takes precedence over allow
+}
+
+/// Advisory write lock of a path
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Lock {
+    owner: Principal,
+    locked_at: u64, // milliseconds
+}
+
+impl Storable for Lock {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(serde_cbor::to_vec(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        serde_cbor::from_slice(&bytes).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
 }
 
 struct Uploading {
     owner: Principal,
     size: u64,
+    expected_size: u64,
     updated_at: u64,
     mimetype: String,
-    chunk: HashMap<u64, Vec<u8>>,
+    overwrite: bool, // re-checked by commitUpload
+    chunk: BTreeMap<u64, Vec<u8>>, // keyed by start offset
+    layout: Option<BTreeMap<u64, u64>>, // declared chunks (offset -> size)
+}
+
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+pub struct Upload {
+    size: u64, // expected total size
+    chunk_size: u64, // recommended chunk size
+    chunk_count: u64, // number of chunks with chunk_size
+    expires_at: u64, // milliseconds, extended by each sendData
+}
+
+/// progress of an upload session
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+pub struct UploadStatus {
+    size: u64, // bytes received so far
+    expected_size: u64,
+    expires_at: u64, // milliseconds, extended by each sendData
+    remaining_ms: u64, // until expires_at
 }
 
 #[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
 pub struct Download {
     size: u64,
+    updated_at: u64, // changes if the file is overwritten during downloading
+    version: u64, // incremented if the file is overwritten during downloading
     downloaded_at: u64,
     chunk: Vec<u8>,
     sha256: Option<[u8; 32]>, // specified if end of file
+    sha256_hex: Option<String>, // sha256 in lowercase hex
+    compressed: bool, // chunk is gzipped
+    prefix_sha256: Option<[u8; 32]>, // sha256 of bytes 0..downloaded_at (loadWithPrefixHash only)
+}
+
+/// a level of a Merkle inclusion proof (from the file up to the directory)
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+pub struct MerkleProof {
+    name: String, // name of the file/directory at this level
+    siblings: Vec<MerkleSibling>, // from the bottom of the directory's tree
+}
+
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+pub struct MerkleSibling {
+    hash: [u8; 32],
+    is_left: bool, // the sibling is on the left
+}
+
+/// an operation of `batch`
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+pub enum Op {
+    Save { path: String, mimetype: String, data: Vec<u8>, overwrite: bool },
+    Delete { path: String },
+    CreateDirectory { path: String },
+    Move { from: String, to: String },
+}
+
+/// how to revert an applied operation of `batch`
+enum Undo {
+    Restore { path: String, backup: String, info: Box<FileInfo> }, // overwritten or deleted file
+    Remove { path: String }, // newly saved file
+    RemoveDirectory { path: String }, // newly created directory
+    Move { from: String, to: String }, // moved back from `to` to `from`
 }
 
 /////////////////////////////////////////////////////////////////////////////
@@ -154,6 +631,41 @@ pub struct Download {
 thread_local! {
     /// keep uploading temporary data
     static UPLOADING: RefCell<HashMap<String, Uploading>> = RefCell::default();
+
+    /// keep settings across upgrades
+    static CONFIG: RefCell<StableCell<Config, VirtualMemory<DefaultMemoryImpl>>> = RefCell::new(
+        StableCell::init(crate::MEMORY_MANAGER.with(|m| m.borrow().get(crate::CONFIG_MEMORY_ID)), Config::default()).unwrap()
+    );
+
+    /// keep advisory locks across upgrades
+    static LOCKS: RefCell<StableBTreeMap<String, Lock, VirtualMemory<DefaultMemoryImpl>>> = RefCell::new(
+        StableBTreeMap::init(crate::MEMORY_MANAGER.with(|m| m.borrow().get(crate::LOCK_MEMORY_ID)))
+    );
+
+    /// keep metadata of files/directories (keyed by path) across upgrades
+    static FILE_INFOS: RefCell<StableBTreeMap<String, FileInfo, VirtualMemory<DefaultMemoryImpl>>> = RefCell::new(
+        StableBTreeMap::init(crate::MEMORY_MANAGER.with(|m| m.borrow().get(crate::FILE_INFO_MEMORY_ID)))
+    );
+
+    /// keep reference counts of shared blobs (hex sha256) across upgrades
+    static BLOBS: RefCell<StableBTreeMap<String, u64, VirtualMemory<DefaultMemoryImpl>>> = RefCell::new(
+        StableBTreeMap::init(crate::MEMORY_MANAGER.with(|m| m.borrow().get(crate::BLOB_MEMORY_ID)))
+    );
+
+    /// keep quotas of principals (bytes, keyed by principal text) across upgrades
+    static QUOTAS: RefCell<StableBTreeMap<String, u64, VirtualMemory<DefaultMemoryImpl>>> = RefCell::new(
+        StableBTreeMap::init(crate::MEMORY_MANAGER.with(|m| m.borrow().get(crate::QUOTA_MEMORY_ID)))
+    );
+
+    /// keep bytes of the files created by principals (keyed by principal text), rebuilt on upgrade
+    static USAGE: RefCell<StableBTreeMap<String, u64, VirtualMemory<DefaultMemoryImpl>>> = RefCell::new(
+        StableBTreeMap::init(crate::MEMORY_MANAGER.with(|m| m.borrow().get(crate::USAGE_MEMORY_ID)))
+    );
+
+    /// keep bytes of the files under directories with a quota (keyed by path), rebuilt on upgrade
+    static SUBTREE_SIZES: RefCell<StableBTreeMap<String, u64, VirtualMemory<DefaultMemoryImpl>>> = RefCell::new(
+        StableBTreeMap::init(crate::MEMORY_MANAGER.with(|m| m.borrow().get(crate::SUBTREE_SIZE_MEMORY_ID)))
+    );
 }
 
 
@@ -172,7 +684,8 @@ thread_local! {
 /// * `writable` - add writable permission if true
 #[ic_cdk::update(name="addPermission")]
 pub fn add_permission(path:String, principal:Principal, manageable:bool, readable:bool, writable:bool) -> Result<(), Error> {
-    validate_path(&path)?;
+    check_writable()?;
+    let path = validate_path(&path)?;
 
     let caller = caller();
     let file_info = get_file_info(&path);
@@ -183,23 +696,17 @@ pub fn add_permission(path:String, principal:Principal, manageable:bool, readabl
     // Check whether file exists or not
     match file_info {
         Some(mut new_info) => {
-            if manageable {
-                if new_info.manageable.binary_search_by_key(&&principal, |p|p).is_err() {
-                    new_info.manageable.push(principal);
-                    new_info.manageable.sort();
-                }
+            if manageable && new_info.manageable.binary_search_by_key(&&principal, |p|p).is_err() {
+                new_info.manageable.push(principal);
+                new_info.manageable.sort();
             }
-            if readable {
-                if new_info.readable.binary_search_by_key(&&principal, |p|p).is_err() {
-                    new_info.readable.push(principal);
-                    new_info.readable.sort();
-                }
+            if readable && new_info.readable.binary_search_by_key(&&principal, |p|p).is_err() {
+                new_info.readable.push(principal);
+                new_info.readable.sort();
             }
-            if writable {
-                if new_info.writable.binary_search_by_key(&&principal, |p|p).is_err() {
-                    new_info.writable.push(principal);
-                    new_info.writable.sort();
-                }
+            if writable && new_info.writable.binary_search_by_key(&&principal, |p|p).is_err() {
+                new_info.writable.push(principal);
+                new_info.writable.sort();
             }
             set_file_info(&path, &new_info)?;
 
@@ -220,7 +727,8 @@ pub fn add_permission(path:String, principal:Principal, manageable:bool, readabl
 /// * `writable` - revoke wrie permission if true
 #[ic_cdk::update(name="removePermission")]
 pub fn remove_permission(path:String, principal:Principal, manageable:bool, readable:bool, writable:bool) -> Result<(), Error> {
-    validate_path(&path)?;
+    check_writable()?;
+    let path = validate_path(&path)?;
 
     let caller = caller();
     let file_info = get_file_info(&path);
@@ -232,28 +740,16 @@ pub fn remove_permission(path:String, principal:Principal, manageable:bool, read
     match file_info {
         Some(mut new_info) => {
             if manageable {
-                match new_info.manageable.binary_search_by_key(&&principal, |p|p) {
-                    Ok(index) => {
-                        new_info.manageable.remove(index);
-                    },
-                    Err(_) =>{}
+                new_info.manageable.retain(|p| p != &principal);
+                if *path == root() && new_info.manageable.is_empty() {
+                    return error!(ERROR_LAST_MANAGER, "The last manager can't be removed");
                 }
             }
             if readable {
-                match new_info.readable.binary_search_by_key(&&principal, |p|p) {
-                    Ok(index) => {
-                        new_info.readable.remove(index);
-                    },
-                    Err(_) =>{}
-                }
+                new_info.readable.retain(|p| p != &principal);
             }
             if writable {
-                match new_info.writable.binary_search_by_key(&&principal, |p|p) {
-                    Ok(index) => {
-                        new_info.writable.remove(index);
-                    },
-                    Err(_) =>{}
-                }
+                new_info.writable.retain(|p| p != &principal);
             }
             set_file_info(&path, &new_info)?;
 
@@ -263,1102 +759,7086 @@ pub fn remove_permission(path:String, principal:Principal, manageable:bool, read
     }
 }
 
-/// Returns permissions of the specified path
+/// denies permissions of read, write to the principal even if inherited from the ancestors
+///
+/// An explicit deny on a path takes precedence over an allow on the same path and
+/// over any allow inherited from the ancestors. An allow on a descendant, however,
+/// is checked before the deny of its ancestor.
+///
 /// # Arguments
 ///
 /// * `path` - must start with ROOT
-///
-#[ic_cdk::query(name="hasPermission")]
-pub fn has_permission(path:String) -> Result<Permission, Error> {
-    validate_path(&path)?;
+/// * `principal` - Principal to deny
+/// * `readable` - deny read permission if true
+/// * `writable` - deny write permission if true
+#[ic_cdk::update(name="denyPermission")]
+pub fn deny_permission(path:String, principal:Principal, readable:bool, writable:bool) -> Result<(), Error> {
+    check_writable()?;
+    let path = validate_path(&path)?;
 
+    let caller = caller();
     let file_info = get_file_info(&path);
-    if file_info.is_none() {
-        return error!(ERROR_NOT_FOUND, "File not found");
+    if !check_manage_permission(&caller, &path, file_info.as_ref()) {
+        return error!(ERROR_PERMISSION_DENIED, "Permission denied");
     }
 
-    let caller = caller();
+    match file_info {
+        Some(mut new_info) => {
+            if readable && new_info.denied_readable.binary_search_by_key(&&principal, |p|p).is_err() {
+                new_info.denied_readable.push(principal);
+                new_info.denied_readable.sort();
+            }
+            if writable && new_info.denied_writable.binary_search_by_key(&&principal, |p|p).is_err() {
+                new_info.denied_writable.push(principal);
+                new_info.denied_writable.sort();
+            }
+            set_file_info(&path, &new_info)?;
 
-    // TODO optimize algorithm
-    Ok(Permission {
-        manageable: check_manage_permission(&caller, &path, file_info.as_ref()),
-        readable: check_read_permission(&caller, &path, file_info.as_ref()),
-        writable: check_write_permission(&caller, &path, file_info.as_ref()),
-    })
+            Ok(())
+        },
+        None => error!(ERROR_NOT_FOUND, "File not found")
+    }
 }
 
-/// Uloads a file to the canister (less than 2MiB)
+/// removes denials of read, write from the principal
 ///
 /// # Arguments
 ///
-/// * `path` - must start with ROOT and the parent directory must exist
-/// * `mimetype` - mimetype of the file
-/// * 'data' - file content
-/// * 'overwrite' - whether to overwrite the file if it already exists
-#[ic_cdk::update]
-pub fn save(path:String, mimetype:String, data:Vec<u8>, overwrite:bool) -> Result<(), Error> {
-    // First, check path
-    validate_path(&path)?;
+/// * `path` - must start with ROOT
+/// * `principal` - Principal to allow
+/// * `readable` - remove the denial of read permission if true
+/// * `writable` - remove the denial of write permission if true
+#[ic_cdk::update(name="allowPermission")]
+pub fn allow_permission(path:String, principal:Principal, readable:bool, writable:bool) -> Result<(), Error> {
+    check_writable()?;
+    let path = validate_path(&path)?;
 
-    // Second, check mimetype
-    if mimetype.is_empty() || mimetype == MIMETYPE_DIRECTORY {
-        return error!(ERROR_INVALID_MIMETYPE, "Invalid mimetype");
+    let caller = caller();
+    let file_info = get_file_info(&path);
+    if !check_manage_permission(&caller, &path, file_info.as_ref()) {
+        return error!(ERROR_PERMISSION_DENIED, "Permission denied");
     }
 
-    // Third check permission
+    match file_info {
+        Some(mut new_info) => {
+            if readable {
+                new_info.denied_readable.retain(|p| p != &principal);
+            }
+            if writable {
+                new_info.denied_writable.retain(|p| p != &principal);
+            }
+            set_file_info(&path, &new_info)?;
+
+            Ok(())
+        },
+        None => error!(ERROR_NOT_FOUND, "File not found")
+    }
+}
+
+/// replaces the entire access control lists of the specified path
+///
+/// The denials of the path (`denyPermission`) are cleared as well.
+///
+/// # Arguments
+///
+/// * `path` - must start with ROOT
+/// * `manageable` - principals who can grant or revoke permissions
+/// * `readable` - principals who can read
+/// * `writable` - principals who can write
+#[ic_cdk::update(name="setAcl")]
+pub fn set_acl(path:String, manageable:Vec<Principal>, readable:Vec<Principal>, writable:Vec<Principal>) -> Result<(), Error> {
+    check_writable()?;
+    let path = validate_path(&path)?;
+
     let caller = caller();
     let file_info = get_file_info(&path);
-    if !check_write_permission(&caller, &path, file_info.as_ref()) {
+    if !check_manage_permission(&caller, &path, file_info.as_ref()) {
         return error!(ERROR_PERMISSION_DENIED, "Permission denied");
     }
 
-    // Forth Uploading
-    let uploading = UPLOADING.with(|uploading| {
-        let map = uploading.borrow();
-        map.get(&path).is_some() // TODO expired check
-    });
-    if uploading {
-      return error!(ERROR_ALREADY_EXISTS, "File already exists");
-    }
+    match file_info {
+        Some(mut new_info) => {
+            // keep sorted for binary_search_by_key
+            new_info.manageable = manageable;
+            new_info.manageable.sort();
+            new_info.manageable.dedup();
+            new_info.readable = readable;
+            new_info.readable.sort();
+            new_info.readable.dedup();
+            new_info.writable = writable;
+            new_info.writable.sort();
+            new_info.writable.dedup();
+            new_info.denied_readable.clear();
+            new_info.denied_writable.clear();
+
+            // Guard against locking out all managers
+            if *path == root() && new_info.manageable.is_empty() {
+                return error!(ERROR_LAST_MANAGER, "The last manager can't be removed");
+            }
+            if new_info.manageable.is_empty() && !check_manage_permission(&caller, &path, Some(&new_info)) {
+                return error!(ERROR_PERMISSION_DENIED, "No manager would remain");
+            }
+            set_file_info(&path, &new_info)?;
 
-    // Fifth, check whether file exists or not
-    if file_info.is_some() && overwrite == false {
-        return error!(ERROR_ALREADY_EXISTS, "File already exists");
-    } else {
-        let parent_info = get_file_info(&parent_path(&path));
-        if parent_info.is_none() || !parent_info.unwrap().is_dir() {
-            return error!(ERROR_NOT_FOUND, "Parent directory not found");
-        }
+            Ok(())
+        },
+        None => error!(ERROR_NOT_FOUND, "File not found")
     }
+}
 
-    // save as temp, and then rename it
-    let temp_path = temp_path(&path);
-    let file = OpenOptions::new().write(true).create(true).truncate(true).open(&temp_path);
-    match file {
-        Ok(mut file) => {
-            match file.write_all(&data) {
-                Ok(()) => {
-                    let now = time();
-                    let info = match file_info {
-                        Some(mut info) => {
-                            // Update
-                            info.size = data.len() as u64;
-                            info.updated_at = now;
-                            info.mimetype = mimetype;
-                            info.sha256 = Some(Sha256::digest(data).into());
-                            info.signature = None;
-                            info
-                        },
-                        None => {
-                            // New
-                            FileInfo {
-                                size: data.len() as u64,
-                                creator: caller,
-                                created_at: now,
-                                updater: caller,
-                                updated_at: now,
-                                mimetype: mimetype,
-                                manageable: Vec::new(),
-                                readable: Vec::new(),
-                                writable: Vec::new(),
-                                sha256: Some(Sha256::digest(data).into()),
-                                signature: None,
-                            }
-                        }
-                    };
+/// replaces the principals who can list the children of the directory without reading them
+///
+/// List permission is inherited like the others. Read permission includes it, and a denial of
+/// read permission denies it as well.
+///
+/// # Arguments
+///
+/// * `path` - directory; must start with ROOT
+/// * `listable` - principals who can list the children
+#[ic_cdk::update(name="setListable")]
+pub fn set_listable(path:String, listable:Vec<Principal>) -> Result<(), Error> {
+    check_writable()?;
+    let path = validate_path(&path)?;
 
-                    match fs::rename(&temp_path, &path) {
-                        Ok(_) => {
-                            set_file_info(&path, &info)?;
-                            Ok(())
-                        },
-                        Err(e) => error!(ERROR_UNKNOWN, format!("{:?}", e))
-                    }
-                },
-                Err(e) => error!(ERROR_UNKNOWN, format!("{:?}", e))
-            }
+    let caller = caller();
+    let file_info = get_file_info(&path);
+    if !check_manage_permission(&caller, &path, file_info.as_ref()) {
+        return error!(ERROR_PERMISSION_DENIED, "Permission denied");
+    }
+
+    match file_info {
+        Some(info) if !info.is_dir() => error!(ERROR_NOT_A_DIRECTORY, "Not a directory"),
+        Some(mut new_info) => {
+            new_info.listable = listable;
+            new_info.listable.sort();
+            new_info.listable.dedup();
+            set_file_info(&path, &new_info)
         },
-        Err(e) => error!(ERROR_UNKNOWN, format!("{:?}", e))
+        None => error!(ERROR_NOT_FOUND, "Directory not found")
     }
 }
 
-/// download a file to the canister (less than 2MiB)
+/// sets the default permissions copied onto new children of the directory
+///
+/// New files and directories created directly under the directory get these as their own
+/// explicit permissions, in addition to the permissions inherited at runtime.
 ///
 /// # Arguments
 ///
-/// * `path` - must start with ROOT and the parent directory must exist
-/// * `start_at` - must start with ROOT and the parent directory must exist
-
-#[ic_cdk::query]
-pub fn load(path:String, start_at:u64) -> Result<Download, Error> {
-    // First, check path 
-    validate_path(&path)?;
+/// * `path` - directory; must start with ROOT
+/// * `manageable` - default manageable principals of new children
+/// * `readable` - default readable principals of new children
+/// * `writable` - default writable principals of new children
+#[ic_cdk::update(name="setDefaultPermissions")]
+pub fn set_default_permissions(path:String, manageable:Vec<Principal>, readable:Vec<Principal>, writable:Vec<Principal>) -> Result<(), Error> {
+    check_writable()?;
+    let path = validate_path(&path)?;
 
-    // Second, check permission
     let caller = caller();
     let file_info = get_file_info(&path);
-    if !check_read_permission(&caller, &path, file_info.as_ref()) {
+    if !check_manage_permission(&caller, &path, file_info.as_ref()) {
         return error!(ERROR_PERMISSION_DENIED, "Permission denied");
     }
 
-    // Third, check whether file exists or not
-    if file_info.is_none() {
-        return error!(ERROR_NOT_FOUND, "File not found");
-    }
-
-    // FIXME check file size before read to 
-    match File::open(path) {
-        Ok(mut file) => {
-            let info = file_info.unwrap();
-            let mut buffer = vec![0; cmp::min(MAX_READ_SIZE, info.size as usize)];
-            if start_at != 0u64 {
-                let _ = file.seek(SeekFrom::Start(start_at)).or_else(|e| error!(ERROR_UNKNOWN, format!("{:?}", e)));
+    match file_info {
+        Some(mut new_info) => {
+            if !new_info.is_dir() {
+                return error!(ERROR_NOT_A_DIRECTORY, "Not a directory");
             }
-            let readsize = file.read(&mut buffer).or_else(|e| error!(ERROR_UNKNOWN, format!("{:?}", e))).unwrap();
-            let downloaded_at = start_at + readsize as u64;
-            Ok(Download {
-                size: info.size,
-                downloaded_at,
-                chunk: buffer[..readsize].to_vec(),
-                sha256: if info.size == downloaded_at {
-                    info.sha256
-                } else {
-                    None
-                }
-            })
+            new_info.default_manageable = manageable;
+            new_info.default_manageable.sort();
+            new_info.default_manageable.dedup();
+            new_info.default_readable = readable;
+            new_info.default_readable.sort();
+            new_info.default_readable.dedup();
+            new_info.default_writable = writable;
+            new_info.default_writable.sort();
+            new_info.default_writable.dedup();
+            set_file_info(&path, &new_info)?;
+
+            Ok(())
         },
-        Err(e) => match e.kind() { // Not expected
-            ErrorKind::NotFound => error!(ERROR_NOT_FOUND, "File not found"),
-            _ => error!(ERROR_UNKNOWN, format!("{:?}", e))
-        }
+        None => error!(ERROR_NOT_FOUND, "Directory not found")
     }
 }
 
-/// starts uploading a file to the canister (more than 2MiB)
+/// enables or disables recording the last-accessed time of files under the directory
+///
+/// Reads are queries whose state changes are discarded, so `load` itself can't persist
+/// the time. Instead, readers report reads in batches with `recordAccess`, which updates
+/// `accessed_at` only under directories with tracking enabled.
 ///
 /// # Arguments
 ///
-/// * `path` - must start with ROOT and the parent directory must exist
-/// * `mimetype` - mimetype of the file
-/// * 'data' - file content
-/// * 'overwrite' - whether to overwrite the file if it already exists
-#[ic_cdk::update(name="beginUpload")]
-pub fn begin_upload(path:String, mimetype:String, overwrite:bool) -> Result<(), Error> {
-    // First, check path 
-    validate_path(&path)?;
+/// * `path` - directory; must start with ROOT
+/// * `enabled` - records accessed_at of the descendants if true
+#[ic_cdk::update(name="setAccessTracking")]
+pub fn set_access_tracking(path:String, enabled:bool) -> Result<(), Error> {
+    check_writable()?;
+    let path = validate_path(&path)?;
 
-    // Second, check mimetype
-    if mimetype.is_empty() || mimetype == MIMETYPE_DIRECTORY {
-        return error!(ERROR_INVALID_MIMETYPE, "Invalid mimetype");
-    }
-    
-    // Third check permission
     let caller = caller();
     let file_info = get_file_info(&path);
-    if !check_write_permission(&caller, &path, file_info.as_ref()) {
+    if !check_manage_permission(&caller, &path, file_info.as_ref()) {
         return error!(ERROR_PERMISSION_DENIED, "Permission denied");
     }
 
-    // Forth Uploading
-    let uploading = UPLOADING.with(|uploading| {
-        let map = uploading.borrow();
-        map.get(&path).is_some() // TODO expired check
-    });
-    if uploading {
-      return error!(ERROR_ALREADY_EXISTS, "File already exists");
+    match file_info {
+        Some(info) if !info.is_dir() => error!(ERROR_NOT_A_DIRECTORY, "Not a directory"),
+        Some(mut info) => {
+            info.track_access = enabled;
+            set_file_info(&path, &info)
+        },
+        None => error!(ERROR_NOT_FOUND, "Directory not found")
     }
+}
 
-    // Fifth, check whether file exists or not
-    if file_info.is_some() && overwrite == false {
-        return error!(ERROR_ALREADY_EXISTS, "File already exists");
-    } else {
-        let parent_info = get_file_info(&parent_path(&path));
-        if parent_info.is_none() || !parent_info.unwrap().is_dir() {
-            return error!(ERROR_NOT_FOUND, "Parent directory not found");
-        }
-    }
+/// records that files were read (sets accessed_at to now)
+///
+/// Files outside directories with access tracking enabled are left untouched.
+/// Each path is processed independently, so one bad path doesn't fail the others.
+///
+/// # Arguments
+///
+/// * `paths` - readable files; each must start with ROOT (up to MAX_BATCH_SIZE paths)
+#[ic_cdk::update(name="recordAccess")]
+pub fn record_access(paths:Vec<String>) -> Vec<Result<(), Error>> {
+    let caller = caller();
+    let now = time();
+    paths.into_iter()
+        .enumerate()
+        .map(|(index, path)| {
+            if index >= MAX_BATCH_SIZE {
+                return error!(ERROR_INVALID_SIZE, "Too many paths");
+            }
+            check_writable()?;
+            let path = validate_path(&path)?;
+            let mut info = readable_file_info(&caller, &path, "File not found")?;
+            if info.is_dir() {
+                return error!(ERROR_IS_DIRECTORY, "Not a file");
+            }
+            if !is_access_tracked(&path) {
+                return Ok(());
+            }
+            info.accessed_at = Some(now);
+            set_file_info(&path, &info)
+        })
+        .collect()
+}
 
-    UPLOADING.with(|uploading| {
-        let mut map = uploading.borrow_mut();
+/// Returns permissions of the specified path
+/// # Arguments
+///
+/// * `path` - must start with ROOT
+///
+#[ic_cdk::query(name="hasPermission")]
+pub fn has_permission(path:String) -> Result<Permission, Error> {
+    let path = validate_path(&path)?;
 
-        // Remove expired first
-        let now = time();
-        map.retain(|_key, value| (value.updated_at + 10 * 60 * 1000) >= now); // expired 10 minutes.
+    let file_info = get_file_info(&path);
+    if file_info.is_none() {
+        return error!(ERROR_NOT_FOUND, "File not found");
+    }
 
-        // Insert entry
-        map.insert(path, Uploading{
-            owner: caller,
-            updated_at: now,
-            size: 0,
-            mimetype,
-            chunk: HashMap::new(),
-        });
-        Ok(())
+    let caller = caller();
+
+    // TODO optimize algorithm
+    Ok(Permission {
+        manageable: check_manage_permission(&caller, &path, file_info.as_ref()),
+        readable: check_read_permission(&caller, &path, file_info.as_ref()),
+        writable: check_write_permission(&caller, &path, file_info.as_ref()),
+        listable: check_list_permission(&caller, &path, file_info.as_ref()),
+        is_owner: file_info.as_ref().is_some_and(|info| info.creator == caller),
     })
 }
 
-/// uploads a chunk of the file to the canister
+/// Returns whether the caller can write the path, even if it doesn't exist yet
+///
+/// A missing path inherits the write permission of its ancestors, so this tells whether
+/// the caller can create it. Existence of the parent directory isn't checked.
 ///
 /// # Arguments
 ///
-/// * `path` - must start with ROOT and the parent directory must exist
-/// * `start` - start index
-/// * 'data' - chunk of the file
-#[ic_cdk::update(name="sendData")]
-pub fn send_data(path:String, start:u64, data:Vec<u8>) -> Result<u64, Error> {
-    let caller = caller();
-
-    UPLOADING.with(|uploading| {
-        let mut map = uploading.borrow_mut();
-        match map.get_mut(&path) {
-            Some(value) => {
-                let now = time();
-                if value.owner != caller {
-                    error!(ERROR_INVALID_SEQUENCE, "Invalid sequence")
-                } else if (value.updated_at + 10 * 60 * 1000) < now {
-                    error!(ERROR_PERMISSION_DENIED, "session expired")
-                } else {
-                    value.size += data.len() as u64;
-                    value.updated_at = now;
-
-                    // map.try_insert() is still unstable...
-                    match value.chunk.insert(start, data) {
-                        Some(old) => {
-                            // TODO better to be error but currently accepted and overwritten
-                            value.size -= old.len() as u64;
-                            Ok(value.size)
-                        },
-                        None => Ok(value.size)
-                    }
-                }
-            },
-            None => error!(ERROR_INVALID_SEQUENCE, "Invalid sequence")
-        }
-    })
+/// * `path` - must start with ROOT
+#[ic_cdk::query(name="canWriteHere")]
+pub fn can_write_here(path:String) -> Result<bool, Error> {
+    let path = validate_path(&path)?;
+    let file_info = get_file_info(&path);
+    Ok(check_write_permission(&caller(), &path, file_info.as_ref()))
 }
 
-/// commits uploading a file
+/// copies permissions of a path onto another path (overwriting), including the denials
 ///
 /// # Arguments
 ///
-/// * `path` - must start with ROOT and the parent directory must exist
-/// * `mimetype` - mimetype of the file
-/// * 'data' - file content
-/// * 'overwrite' - whether to overwrite the file if it already exists
-#[ic_cdk::update(name="commitUpload")]
-pub fn commit_upload(path:String, size:u64, sha256:Option<[u8; 32]>) -> Result<(), Error> {
+/// * `from` - must start with ROOT
+/// * `to` - must start with ROOT
+/// * `recursive` - also applies the permissions to every descendant of `to` if true
+///   (fails with ERROR_INVALID_SIZE if there are more than MAX_PERMISSION_TARGETS paths)
+#[ic_cdk::update(name="copyPermissions")]
+pub fn copy_permissions(from:String, to:String, recursive:bool) -> Result<(), Error> {
+    check_writable()?;
+    let from = validate_path(&from)?;
+    let to = validate_path(&to)?;
+
     let caller = caller();
+    let from_info = get_file_info(&from);
+    let to_info = get_file_info(&to);
+    if !check_manage_permission(&caller, &from, from_info.as_ref()) || !check_manage_permission(&caller, &to, to_info.as_ref()) {
+        return error!(ERROR_PERMISSION_DENIED, "Permission denied");
+    }
 
-    UPLOADING.with(|uploading| {
-        let mut map = uploading.borrow_mut();
-        match map.get_mut(&path) {
-            Some(value) => {
-                let now = time();
-                if value.owner != caller {
-                    error!(ERROR_INVALID_SEQUENCE, "Invalid sequence")
-                } else if (value.updated_at + 10 * 60 * 1000) < now {
-                    error!(ERROR_PERMISSION_DENIED, "transaction expired")
-                } else if value.size != size {
-                    error!(ERROR_INVALID_SEQUENCE, "Invalid sequence")
-                } else {
-                    // write file
-                    let temp_path = temp_path(&path);
-                    let mut hasher = Sha256::new();
-                    let mut sha256_verified:Option<[u8; 32]> = None;
-                    let result = match fs::File::create(&temp_path) {
-                        Ok(file) => {
-                            let mut buffer = BufWriter::with_capacity(2*1024*1024, file); // 2MiB Buffer
-                            let mut index:u64 = 0;
-                            loop {
-                                match value.chunk.get(&index) {
-                                    Some(data) => {
-                                        index += data.len() as u64;
-                                        hasher.update(data);
-                                        let _result = buffer.write(data); // TODO handling result
-                                    },
-                                    None => {
-                                        if index != size {
-                                            return error!(ERROR_INVALID_SIZE, "Invalid size");
-                                        }
-                                        sha256_verified = Some(hasher.finalize().into());
-                                        if sha256.is_some() && sha256_verified.unwrap() != sha256.unwrap() {
-                                            return error!(ERROR_INVALID_HASH, "Invalid hash");
-                                        }
-                                        let _result = buffer.flush(); // TODO handling result
-                                        break;
-                                    }
-                                }
-                            }
-                            Ok(())
-                        },
-                        Err(e) => error!(ERROR_UNKNOWN, e) 
-                    };
-                    match result {
-                        Ok(()) => {
-                            let file_info = get_file_info(&path);
-                            let info = match file_info {
-                                Some(mut info) => {
-                                    // Update
-                                    info.size = size;
-                                    info.updated_at = now;
-                                    info.mimetype = value.mimetype.clone();
-                                    info.sha256 = sha256_verified;
-                                    info.signature = None;
-                                    info
-                                },
-                                None => {
-                                    // New
-                                    FileInfo {
-                                        size,
-                                        creator: caller,
-                                        created_at: now,
-                                        updater: caller,
-                                        updated_at: now,
-                                        mimetype: value.mimetype.clone(),
-                                        manageable: Vec::new(),
-                                        readable: Vec::new(),
-                                        writable: Vec::new(),
-                                        sha256: sha256_verified,
-                                        signature: None,
-                                    }
-                                }
-                            };
-
-                            match fs::rename(&temp_path, &path) {
-                                Ok(_) => {
-                                    set_file_info(&path, &info)?;
-                                    map.remove(&path);
-                                    Ok(())
-                                },
-                                Err(e) => {
-                                    println!("fs::rename failed");
-                                    error!(ERROR_UNKNOWN, format!("{:?}", e))
-                                }
-                            }
-                        },
-                        Err(e) => Err(e)
-                    }
-                }
-             },
-            None => error!(ERROR_INVALID_SEQUENCE, "Invalid sequence")
+    let from_info = match from_info {
+        Some(info) => info,
+        None => return error!(ERROR_NOT_FOUND, "File not found")
+    };
+    if to_info.is_none() {
+        return error!(ERROR_NOT_FOUND, "File not found");
+    }
+    // Guard against locking out all managers
+    if to == root() && from_info.manageable.is_empty() {
+        return error!(ERROR_LAST_MANAGER, "The last manager can't be removed");
+    }
+
+    let targets = if recursive {
+        // walks the metadata lazily, so it stops as soon as there are too many paths
+        let prefix = child_prefix(&to);
+        let targets:Vec<String> = FILE_INFOS.with(|infos| {
+            let infos = infos.borrow();
+            std::iter::once(to.clone())
+                .chain(infos.range(prefix.clone()..)
+                    .map(|(path, _)| path)
+                    .take_while(|path| path.starts_with(&prefix))
+                    .filter(|path| *path != to)) // "/" is its own prefix
+                .take(MAX_PERMISSION_TARGETS + 1)
+                .collect()
+        });
+        if targets.len() > MAX_PERMISSION_TARGETS {
+            return error!(ERROR_INVALID_SIZE, "Too many paths to update");
         }
-    })
+        targets
+    } else {
+        vec![to]
+    };
+    for target in targets {
+        if let Some(mut info) = get_file_info(&target) {
+            info.manageable = from_info.manageable.clone();
+            info.readable = from_info.readable.clone();
+            info.writable = from_info.writable.clone();
+            info.listable = from_info.listable.clone();
+            info.denied_readable = from_info.denied_readable.clone();
+            info.denied_writable = from_info.denied_writable.clone();
+            set_file_info(&target, &info)?;
+        }
+    }
+    Ok(())
 }
 
-/// cancels uploading a file
+/// Returns every path under `root` where the principal is granted permissions explicitly
+///
+/// Inherited permissions are not reported; each entry tells which ACL vectors of the path
+/// contain the principal. Fails with ERROR_INVALID_SIZE if more than MAX_AUDIT_SIZE paths match.
 ///
 /// # Arguments
 ///
-/// * `path` - must start with ROOT and the parent directory must exist
-#[ic_cdk::update(name="cancelUpload")]
-pub fn cancel_upload(path:String) -> Result<(), Error> {
-    let caller = caller();
+/// * `root` - must start with ROOT (manage permission required)
+/// * `principal` - principal to audit
+#[ic_cdk::query(name="auditPrincipal")]
+pub fn audit_principal(root:String, principal:Principal) -> Result<Vec<(String, Permission)>, Error> {
+    let root = validate_path(&root)?;
+
+    let root_info = get_file_info(&root);
+    if !check_manage_permission(&caller(), &root, root_info.as_ref()) {
+        return error!(ERROR_PERMISSION_DENIED, "Permission denied");
+    }
+    if root_info.is_none() {
+        return error!(ERROR_NOT_FOUND, "File not found");
+    }
 
-    UPLOADING.with(|uploading| {
-        let mut map = uploading.borrow_mut();
-        match map.get(&path) {
-            Some(value) => {
-                if value.owner != caller {
-                    error!(ERROR_INVALID_SEQUENCE, "Invalid sequence")
-                } else {
-                    map.remove(&path);
-                    Ok(())
+    // walks the metadata lazily, so it stops as soon as too many paths match
+    let prefix = child_prefix(&root);
+    FILE_INFOS.with(|infos| {
+        let infos = infos.borrow();
+        let mut grants = Vec::new();
+        let entries = infos.get(&root).map(|info| (root.clone(), info)).into_iter()
+            .chain(infos.range(prefix.clone()..)
+                .take_while(|(path, _)| path.starts_with(&prefix))
+                .filter(|(path, _)| *path != root)); // "/" is its own prefix
+        for (path, info) in entries {
+            let permission = Permission {
+                manageable: info.manageable.contains(&principal),
+                readable: info.readable.contains(&principal),
+                writable: info.writable.contains(&principal),
+                listable: info.listable.contains(&principal),
+                is_owner: info.creator == principal,
+            };
+            if permission.manageable || permission.readable || permission.writable || permission.listable {
+                if grants.len() >= MAX_AUDIT_SIZE {
+                    return error!(ERROR_INVALID_SIZE, "Too many paths to audit");
                 }
+                grants.push((path, permission));
             }
-            None => error!(ERROR_INVALID_SEQUENCE, "Invalid sequence")
         }
+        Ok(grants)
     })
 }
 
-/// deletes a file
+/// Returns every path under `root` updated at or after `since`
+///
+/// The root itself is included if it matches. Fails with ERROR_INVALID_SIZE if more than
+/// MAX_AUDIT_SIZE paths match.
 ///
 /// # Arguments
 ///
-/// * `path` - must start with ROOT and the parent directory must exist
-#[ic_cdk::update(name="delete")]
-pub fn delete(path:String) -> Result<(), Error> {
-    validate_path(&path)?;
-
-    // Second, check permission
-    let caller = caller();
-    let file_info = get_file_info(&path);
-    if !check_write_permission(&caller, &path, file_info.as_ref()) {
+/// * `root` - must start with ROOT (manage permission required)
+/// * `by` - only paths last updated by the principal, if specified
+/// * `since` - milliseconds
+#[ic_cdk::query(name="findModified")]
+pub fn find_modified(root:String, by:Option<Principal>, since:u64) -> Result<Vec<String>, Error> {
+    let root = validate_path(&root)?;
+
+    let root_info = get_file_info(&root);
+    if !check_manage_permission(&caller(), &root, root_info.as_ref()) {
         return error!(ERROR_PERMISSION_DENIED, "Permission denied");
     }
+    if root_info.is_none() {
+        return error!(ERROR_NOT_FOUND, "File not found");
+    }
 
-    match fs::remove_file(&path) {
-        Ok(_) => {
-            delete_file_info(&path);
-
-            Ok(())
-        },
-        Err(e) => match e.kind() {   
-            ErrorKind::NotFound => error!(ERROR_NOT_FOUND, "File not found"),
-            _=> error!(ERROR_UNKNOWN, format!("{:?}", e))
+    // walks the metadata lazily, so it stops as soon as too many paths match
+    let prefix = child_prefix(&root);
+    FILE_INFOS.with(|infos| {
+        let infos = infos.borrow();
+        let mut paths = Vec::new();
+        let entries = infos.get(&root).map(|info| (root.clone(), info)).into_iter()
+            .chain(infos.range(prefix.clone()..)
+                .take_while(|(path, _)| path.starts_with(&prefix))
+                .filter(|(path, _)| *path != root)); // "/" is its own prefix
+        for (path, info) in entries {
+            if info.updated_at < since || by.is_some_and(|by| info.updater != by) {
+                continue;
+            }
+            if paths.len() >= MAX_AUDIT_SIZE {
+                return error!(ERROR_INVALID_SIZE, "Too many modified paths");
+            }
+            paths.push(path);
         }
-    }
+        Ok(paths)
+    })
 }
 
-/// returns a list of the files/directories in the specified path
+/// Returns size, sha256 and update time of every readable file under `root`
+///
+/// Directories are not included. Files the caller can't read are skipped. Fails with
+/// ERROR_INVALID_SIZE if more than MAX_MANIFEST_SIZE files match (narrow `root` or `since`).
 ///
 /// # Arguments
 ///
-/// * `path` - must start with ROOT and the parent directory must exist
-#[ic_cdk::query(name="listFiles")]
-pub fn list_files(path:String) -> Result<Vec<String>, Error> {
-    validate_path(&path)?;
+/// * `root` - must start with ROOT (read permission required)
+/// * `since` - only files updated at or after this time (milliseconds) if specified
+#[ic_cdk::query]
+pub fn manifest(root:String, since:Option<u64>) -> Result<Vec<ManifestEntry>, Error> {
+    let root = validate_path(&root)?;
 
-    let file_info = get_file_info(&path);
     let caller = caller();
-    if !check_read_permission(&caller, &path, file_info.as_ref()) {
+    readable_file_info(&caller, &root, "File not found")?;
+
+    // walks the metadata lazily, so it stops as soon as too many files match
+    let prefix = child_prefix(&root);
+    FILE_INFOS.with(|infos| {
+        let infos = infos.borrow();
+        let mut entries = Vec::new();
+        let files = infos.get(&root).map(|info| (root.clone(), info)).into_iter()
+            .chain(infos.range(prefix.clone()..)
+                .take_while(|(path, _)| path.starts_with(&prefix))
+                .filter(|(path, _)| *path != root)); // "/" is its own prefix
+        for (path, info) in files {
+            if info.is_dir() || since.is_some_and(|since| info.updated_at < since) {
+                continue;
+            }
+            if !check_read_permission(&caller, &path, Some(&info)) {
+                continue;
+            }
+            if entries.len() >= MAX_MANIFEST_SIZE {
+                return error!(ERROR_INVALID_SIZE, "Too many files in the manifest");
+            }
+            entries.push(ManifestEntry {
+                path,
+                size: info.size,
+                sha256: info.sha256,
+                updated_at: info.updated_at,
+            });
+        }
+        Ok(entries)
+    })
+}
+
+/// Returns the paths consulted when checking permissions of the specified path
+///
+/// The list starts with the path itself and ends with ROOT.
+///
+/// # Arguments
+///
+/// * `path` - must start with ROOT
+#[ic_cdk::query]
+pub fn ancestry(path:String) -> Result<Vec<String>, Error> {
+    let path = validate_path(&path)?;
+
+    let caller = caller();
+    if !check_read_permission(&caller, &path, get_file_info(&path).as_ref()) {
         return error!(ERROR_PERMISSION_DENIED, "Permission denied");
     }
 
-    if file_info.is_none() {
-        return error!(ERROR_NOT_FOUND, "Directory not found");
+    let root = root();
+    let mut paths = vec![path.clone()];
+    let mut current = path;
+    while current != root {
+        current = permission_parent_path(&current);
+        if !current.is_empty() {
+            paths.push(current.clone());
+        }
     }
+    Ok(paths)
+}
 
-    let entries = fs::read_dir(path).unwrap();
-    let mut files:Vec<String> = entries
-        .map(| entry | {
-            let entry = entry.unwrap();
-            let file_name = entry.path().file_name().unwrap().to_string_lossy().into_owned();
-            if entry.file_type().unwrap().is_dir() { 
-                format!("{}/", file_name)
-            } else {
-                file_name.to_string()
-            }
-        })
-        .filter(| file | !file.starts_with("`")) // Remove file_info
-        .collect();
-    files.sort();
-    Ok(files)
+/// checks a path and a mimetype as `save` does, without permissions or storage
+///
+/// Returns the same error as `save` would for an invalid path or a mimetype denied by the policy.
+///
+/// # Arguments
+///
+/// * `path` - path to check
+/// * `mimetype` - mimetype to check if specified
+#[ic_cdk::query]
+pub fn validate(path:String, mimetype:Option<String>) -> Result<(), Error> {
+    validate_path(&path)?;
+    match mimetype {
+        Some(mimetype) => check_mimetype(&mimetype),
+        None => Ok(())
+    }
 }
 
-/// creates a directory
+/// Uloads a file to the canister (less than 2MiB)
 ///
 /// # Arguments
 ///
 /// * `path` - must start with ROOT and the parent directory must exist
-#[ic_cdk::update(name="createDirectory")]
-pub fn create_directory(path:String) -> Result<(), Error> {
-    validate_path(&path)?;
+/// * `mimetype` - mimetype of the file
+/// * 'data' - file content
+/// * 'overwrite' - whether to overwrite the file if it already exists
+///
+/// Returns sha256 of the saved data
+#[ic_cdk::update]
+pub fn save(path:String, mimetype:String, data:Vec<u8>, overwrite:bool) -> Result<[u8; 32], Error> {
+    check_writable()?;
 
-    // Check write permission
+    // First, check path
+    let name = display_name(&path);
+    let path = validate_path(&path)?;
+
+    // Second, check mimetype
+    check_mimetype(&mimetype)?;
+
+    // Third check permission
     let caller = caller();
     let file_info = get_file_info(&path);
     if !check_write_permission(&caller, &path, file_info.as_ref()) {
         return error!(ERROR_PERMISSION_DENIED, "Permission denied");
     }
+    check_lock(&caller, &path)?;
 
-    if file_info.is_some() {
-        return error!(ERROR_ALREADY_EXISTS, "Directory already exists"); // FIXME Dir or file exists
+    // Forth Uploading
+    if is_uploading(&path) {
+      return error!(ERROR_ALREADY_EXISTS, "File already exists");
     }
 
-    // check parents
-    let parent_info = get_file_info(&parent_path(&path));
-    if parent_info.is_none() || !parent_info.unwrap().is_dir() {
-        return error!(ERROR_NOT_FOUND, "Parent directory not found");
+    // Fifth, check whether file exists or not
+    if file_info.as_ref().is_some_and(|info| info.is_dir()) {
+        return error!(ERROR_IS_DIRECTORY, "Directory already exists");
+    } else if file_info.is_some() && !overwrite {
+        return error!(ERROR_ALREADY_EXISTS, "File already exists");
+    } else {
+        check_parent_directory(&path)?;
     }
 
-    match fs::create_dir(&path) {
-        Ok(_) => {
-            // create file_info
-            set_file_info(&path, &FileInfo {
-                size: 0,
-                creator: caller,
-                created_at: time(),
-                updater: caller,
-                updated_at: time(),
-                mimetype: MIMETYPE_DIRECTORY.to_string(),
-                manageable: Vec::new(),
-                readable: Vec::new(),
-                writable: Vec::new(),
-                sha256: None,
-                signature: None,
-            })?;
+    check_space(data.len() as u64)?;
+    check_quotas(&caller, &path, file_info.as_ref(), data.len() as u64)?;
 
-            Ok(())
+    // save as temp, and then rename it
+    let temp_path = temp_path(&path);
+    let file = OpenOptions::new().write(true).create(true).truncate(true).open(&temp_path);
+    match file {
+        Ok(mut file) => {
+            match file.write_all(&data) {
+                Ok(()) => {
+                    let now = time();
+                    let sha256:[u8; 32] = Sha256::digest(&data).into();
+                    let old_blob = file_info.as_ref().and_then(|info| info.blob);
+                    let mut info = match file_info {
+                        Some(mut info) => {
+                            // Update
+                            info.size = data.len() as u64;
+                            info.updated_at = now;
+                            info.mimetype = mimetype;
+                            info.sha256 = Some(sha256);
+                            info.version += 1;
+                            info.signature = None;
+                            info.pubkey = None;
+                            info
+                        },
+                        None => {
+                            // New
+                            FileInfo {
+                                size: data.len() as u64,
+                                sha256: Some(sha256),
+                                name,
+                                ..new_file_info(&path, caller, now, mimetype)
+                            }
+                        }
+                    };
+
+                    info.blob = place_content(&temp_path, &path, &sha256)?;
+                    set_file_info(&path, &info)?;
+                    if let Some(old_blob) = old_blob {
+                        release_blob(&old_blob);
+                    }
+                    touch_parents(&path, now)?;
+                    Ok(sha256)
+                },
+                Err(e) => error!(ERROR_UNKNOWN, format!("{:?}", e))
+            }
         },
         Err(e) => error!(ERROR_UNKNOWN, format!("{:?}", e))
     }
 }
 
-/// deletes a directory
+/// creates a file, failing if the path already exists (create-only `save`)
 ///
 /// # Arguments
 ///
-/// * `path` - must start with ROOT and the parent directory must exist
-/// * 'recursively' - whether to delete recursively
-#[ic_cdk::update(name="deleteDirectory")]
-pub fn delete_directory(path:String, recursively:bool) -> Result<(), Error> {
-    validate_path(&path)?;
-
-    let file_info = get_file_info(&path);
-    let caller = caller();
-    if !check_read_permission(&caller, &path, file_info.as_ref()) {
-        return error!(ERROR_PERMISSION_DENIED, "Permission denied");
-    }
-
-    if file_info.is_none() {
-        return error!(ERROR_NOT_FOUND, "Directory not found");
-    }
-
-    if recursively {
-        // delete recursively
-        // delete only if empty
-        match fs::remove_dir_all(&path) {
-            Ok(_) => {
-                delete_file_info(&path);
-                Ok(())
-            },
-            Err(e) => error!(ERROR_UNKNOWN, format!("{:?}", e))
-        }
-    } else {
-        // delete only if empty
-        match fs::remove_dir(&path) {
-            Ok(_) => {
-                delete_file_info(&path);
-                Ok(())
-            },
-            Err(e) => error!(ERROR_UNKNOWN, format!("{:?}", e))
-        }
+/// * `path` - must start with ROOT, must not exist, and the parent directory must exist
+/// * `mimetype` - mimetype of the file
+/// * 'data' - file content
+#[ic_cdk::update(name="createFile")]
+pub fn create_file(path:String, mimetype:String, data:Vec<u8>) -> Result<(), Error> {
+    match save(path, mimetype, data, false) {
+        Ok(_) => Ok(()),
+        Err(e) if e.code == ERROR_IS_DIRECTORY => error!(ERROR_ALREADY_EXISTS, "Directory already exists"),
+        Err(e) => Err(e)
     }
 }
 
-/// returns a file info
+/// creates an empty file, or updates the modification time of an existing file
+///
+/// The content of an existing file is not rewritten.
 ///
 /// # Arguments
 ///
 /// * `path` - must start with ROOT and the parent directory must exist
-#[ic_cdk::query(name="getInfo")]
-pub fn get_info(path:String) -> Result<Info, Error> {
-    validate_path(&path)?;
+/// * `mimetype` - mimetype of the file if created
+#[ic_cdk::update]
+pub fn touch(path:String, mimetype:String) -> Result<(), Error> {
+    check_writable()?;
+    let name = display_name(&path);
+    let path = validate_path(&path)?;
+    check_mimetype(&mimetype)?;
 
-    let file_info = get_file_info(&path);
     let caller = caller();
-    if !check_read_permission(&caller, &path, file_info.as_ref()) {
+    let file_info = get_file_info(&path);
+    if !check_write_permission(&caller, &path, file_info.as_ref()) {
         return error!(ERROR_PERMISSION_DENIED, "Permission denied");
     }
+    check_lock(&caller, &path)?;
 
+    let now = time();
     match file_info {
-        Some(info) => Ok(Info {
-            size: info.size,
-            creator: info.creator,
-            created_at: info.created_at,
-            updater: info.updater,
-            updated_at: info.updated_at,
-            mimetype: info.mimetype,
-            sha256: info.sha256
-        }),
-        None => error!(ERROR_NOT_FOUND, "File not found")
+        Some(info) if info.is_dir() => error!(ERROR_IS_DIRECTORY, "Directory can't be touched"),
+        Some(mut info) => {
+            info.updated_at = now;
+            info.updater = caller;
+            set_file_info(&path, &info)
+        },
+        None => {
+            check_parent_directory(&path)?;
+            match File::create(&path) {
+                Ok(_) => set_file_info(&path, &FileInfo {
+                    sha256: Some(Sha256::digest(b"").into()),
+                    name,
+                    ..new_file_info(&path, caller, now, mimetype)
+                }),
+                Err(e) => error!(ERROR_UNKNOWN, format!("{:?}", e))
+            }
+        }
     }
 }
 
-/// initilizes canistorage
+/// download a file to the canister (less than 2MiB)
 ///
 /// # Arguments
 ///
-#[ic_cdk::update(name="initCanistorage")]
-pub fn init_canistorage() -> Result<(), Error> {
-    let root = ROOT.to_string();
-    let file_info = get_file_info(&root);
-    match file_info {
-        Some(_info) => {
-            error!(ERROR_ALREADY_INITIALIZED, "Already initialized")
-        },
-        None => {
-            let owner = caller();
-            if owner == Principal::anonymous() {
-                return error!(ERROR_PERMISSION_DENIED, "Anonymous is not allowed");
+/// * `path` - must start with ROOT and the parent directory must exist
+/// * `start_at` - offset to start reading
+/// * `length` - maximum bytes to read (up to MAX_READ_SIZE) if specified
+#[ic_cdk::query]
+pub fn load(path:String, start_at:u64, length:Option<u64>) -> Result<Download, Error> {
+    // First, check path 
+    let path = validate_path(&path)?;
+
+    // Second, check permission and whether file exists or not
+    let info = readable_file_info(&caller(), &path, "File not found")?;
+
+    // FIXME check file size before read to 
+    match File::open(content_path(&path, &info)) {
+        Ok(mut file) => {
+            let readable_size = cmp::min(MAX_READ_SIZE as u64, info.size.saturating_sub(start_at));
+            let mut buffer = vec![0; cmp::min(readable_size, length.unwrap_or(u64::MAX)) as usize];
+            if start_at != 0u64 {
+                let _ = file.seek(SeekFrom::Start(start_at));
             }
-            let now = time();
-                
-            set_file_info(&root, &FileInfo {
-                size: 0,
-                creator: owner,
-                created_at: now,
-                updater: owner,
-                updated_at: now,
-                mimetype: MIMETYPE_DIRECTORY.to_string(),
-                manageable: vec![owner],
-                readable: vec![owner],
-                writable: vec![owner],
-                sha256: None,
-                signature: None,
+            let readsize = match file.read(&mut buffer) {
+                Ok(size) => size,
+                Err(e) => return error!(ERROR_UNKNOWN, format!("{:?}", e))
+            };
+            let downloaded_at = start_at + readsize as u64;
+            Ok(Download {
+                size: info.size,
+                updated_at: info.updated_at,
+                version: info.version,
+                downloaded_at,
+                chunk: buffer[..readsize].to_vec(),
+                sha256: if info.size == downloaded_at {
+                    info.sha256
+                } else {
+                    None
+                },
+                sha256_hex: if info.size == downloaded_at {
+                    info.sha256.map(|sha256| to_hex(&sha256))
+                } else {
+                    None
+                },
+                compressed: false,
+                prefix_sha256: None,
             })
+        },
+        Err(e) => match e.kind() { // Not expected
+            ErrorKind::NotFound => error!(ERROR_NOT_FOUND, "File not found"),
+            _ => error!(ERROR_UNKNOWN, format!("{:?}", e))
         }
     }
 }
 
-
-/////////////////////////////////////////////////////////////////////////////
-// Internal functions
-/////////////////////////////////////////////////////////////////////////////
-
-/// Returns whether the specified path is manageable or not
+/// reads a whole small file in one call
+///
+/// Fails with ERROR_INVALID_SIZE if the file is larger than MAX_READ_SIZE (use `load` instead).
 ///
 /// # Arguments
 ///
-/// * `principal` - Principal to check
 /// * `path` - must start with ROOT
-/// * `file_info` - FileInfo
-fn check_manage_permission(principal:&Principal, path:&String, file_info:Option<&FileInfo>) -> bool {
-    // First, check manageable of file_info
-    if let Some(info) = file_info {
-        if info.manageable.iter().any(|p| p == principal) {
-            // Found manageable
-            return true;
-        }
+///
+/// Returns the content, the mimetype and sha256 of the content
+#[ic_cdk::query(name="readAll")]
+pub fn read_all(path:String) -> Result<(Vec<u8>, String, [u8; 32]), Error> {
+    let path = validate_path(&path)?;
+    let info = readable_file_info(&caller(), &path, "File not found")?;
+    if info.is_dir() {
+        return error!(ERROR_IS_DIRECTORY, "Directory can't be read");
     }
-    if path == ROOT {
-        // Second, check if ROOT
-        false
-    } else {
-        // Then, check parent file_info recursively
-        let parent_path = match path.rfind("/") {
-            Some(index) => {
-                path[0..index].to_string()
-            },
-            None => {
-                // Special case: "" -> "/""
-                "/".to_string()
-            }
-        };
-        let parent_info = get_file_info(&parent_path);
-        check_manage_permission(principal, &parent_path, parent_info.as_ref())
+    if info.size > MAX_READ_SIZE as u64 {
+        return error!(ERROR_INVALID_SIZE, "File is too large to read at once");
+    }
+
+    match fs::read(content_path(&path, &info)) {
+        Ok(data) => {
+            let sha256 = info.sha256.unwrap_or_else(|| Sha256::digest(&data).into());
+            Ok((data, info.mimetype, sha256))
+        },
+        Err(e) => error!(ERROR_UNKNOWN, format!("{:?}", e))
     }
 }
 
-/// Returns whether the specified path is readable or not
+/// downloads a file with the chunk gzipped if it gets smaller
+///
+/// `downloaded_at` and `sha256` refer to the original (uncompressed) bytes.
 ///
 /// # Arguments
 ///
-/// * `principal` - Principal to check
 /// * `path` - must start with ROOT
-/// * `file_info` - FileInfo
-fn check_read_permission(principal:&Principal, path:&String, file_info:Option<&FileInfo>) -> bool {
-    // First, check readable of file_info
-    if let Some(info) = file_info {
-        if info.readable.iter().any(|p| p == principal) {
-            // Found readable
-            return true;
-        }
-    }
-    if path == ROOT {
-        // Second, check if ROOT
-        false
-    } else {
-        // Then, check parent file_info recursively
-        let parent_path = match path.rfind("/") {
-            Some(index) => {
-                path[0..index].to_string()
-            },
-            None => {
-                // Special case: "" -> "/""
-                "/".to_string()
-            }
-        };
-        let parent_info = get_file_info(&parent_path);
-        check_read_permission(principal, &parent_path, parent_info.as_ref())
+/// * `start_at` - offset of the original bytes to start reading
+#[ic_cdk::query(name="loadCompressed")]
+pub fn load_compressed(path:String, start_at:u64) -> Result<Download, Error> {
+    let mut download = load(path, start_at, None)?;
+    let compressed = gzip::compress(&download.chunk);
+    if compressed.len() < download.chunk.len() {
+        download.chunk = compressed;
+        download.compressed = true;
     }
+    Ok(download)
 }
 
-/// Returns whether the specified path is writable or not
+/// downloads a file with the sha256 of the bytes read so far
+///
+/// `prefix_sha256` covers bytes `0..downloaded_at`, so a client can compare it with the hash
+/// of what it has received and abort early on corruption. The prefix is re-hashed on every call,
+/// which makes downloading a whole file this way O(n^2); use `load` if verifying at the end is enough.
+/// A prefix longer than MAX_PREFIX_HASH_SIZE fails with ERROR_INVALID_SIZE (except at the end of
+/// the file, whose hash is kept): beyond it, use `load` and hash on the client.
 ///
 /// # Arguments
 ///
-/// * `principal` - Principal to check
 /// * `path` - must start with ROOT
-/// * `file_info` - FileInfo
-fn check_write_permission(principal:&Principal, path:&String, file_info:Option<&FileInfo>) -> bool {
-    // First, check writeable of file_info
-    if let Some(info) = file_info {
-        if info.writable.iter().any(|p| p == principal) {
-            // Found writeable
-            return true;
+/// * `start_at` - offset to start reading
+/// * `length` - maximum bytes to read (up to MAX_READ_SIZE) if specified
+#[ic_cdk::query(name="loadWithPrefixHash")]
+pub fn load_with_prefix_hash(path:String, start_at:u64, length:Option<u64>) -> Result<Download, Error> {
+    let mut download = load(path.clone(), start_at, length)?;
+    download.prefix_sha256 = match download.sha256 {
+        Some(sha256) => Some(sha256), // end of file
+        None if download.downloaded_at > MAX_PREFIX_HASH_SIZE => {
+            return error!(ERROR_INVALID_SIZE, "Prefix is too long to hash; use load and hash on the client");
+        },
+        None => {
+            let path = validate_path(&path)?;
+            let info = readable_file_info(&caller(), &path, "File not found")?;
+            Some(prefix_sha256(&content_path(&path, &info), download.downloaded_at)?)
         }
-    }
-    if path == ROOT {
-        // Second, check if ROOT
-        false
-    } else {
-        // Then, check parent file_info recursively
-        let parent_path = match path.rfind("/") {
-            Some(index) => {
-                path[0..index].to_string()
-            },
-            None => {
-                // Special case: "" -> "/""
-                "/".to_string()
-            }
-        };
-        let parent_info = get_file_info(&parent_path);
-        check_write_permission(principal, &parent_path, parent_info.as_ref())
+    };
+    Ok(download)
+}
+
+/// returns sha256 of the first `length` bytes of the file
+fn prefix_sha256(path:&str, length:u64) -> Result<[u8; 32], Error> {
+    let result = File::open(path).and_then(|file| {
+        let mut hasher = Sha256::new();
+        std::io::copy(&mut file.take(length), &mut hasher)?;
+        Ok(hasher.finalize().into())
+    });
+    match result {
+        Ok(sha256) => Ok(sha256),
+        Err(e) => error!(ERROR_UNKNOWN, format!("{:?}", e))
     }
 }
 
-/// validates the specified path
+/// saves a file given in base64 (for web clients)
 ///
 /// # Arguments
 ///
-/// * `path` - path to check
-/// 
-fn validate_path(path:&String) -> Result<(), Error> {
-    // length
-    let length = path.len();
-    if length == 0 {
-        return error!(ERROR_INVALID_PATH, "Path is empty");
-    } else if length > MAX_PATH {
-        return error!(ERROR_INVALID_PATH, "Path is too long");
+/// * `path` - must start with ROOT and the parent directory must exist
+/// * `mimetype` - mimetype of the file
+/// * 'data' - file content in base64 (padding is optional)
+/// * 'overwrite' - whether to overwrite the file if it already exists
+///
+/// Returns sha256 of the saved data
+#[ic_cdk::update(name="saveBase64")]
+pub fn save_base64(path:String, mimetype:String, data:String, overwrite:bool) -> Result<[u8; 32], Error> {
+    match base64::decode(&data) {
+        Some(data) => save(path, mimetype, data, overwrite),
+        None => error!(ERROR_INVALID_ENCODING, "Invalid base64")
     }
+}
+
+/// downloads a file in base64 (for web clients)
+///
+/// # Arguments
+///
+/// * `path` - must start with ROOT
+/// * `start_at` - offset to start reading
+///
+/// Returns the chunk in base64, the offset to continue reading from, and sha256 in hex at the end of the file
+#[ic_cdk::query(name="loadBase64")]
+pub fn load_base64(path:String, start_at:u64) -> Result<(String, u64, Option<String>), Error> {
+    let download = load(path, start_at, None)?;
+    Ok((base64::encode(&download.chunk), download.downloaded_at, download.sha256_hex))
+}
+
+/// starts uploading a file to the canister (more than 2MiB)
+///
+/// # Arguments
+///
+/// * `path` - must start with ROOT and the parent directory must exist
+/// * `mimetype` - mimetype of the file
+/// * 'overwrite' - whether to overwrite the file if it already exists
+/// * 'expected_size' - total size of the file to be committed
+#[ic_cdk::update(name="beginUpload")]
+pub fn begin_upload(path:String, mimetype:String, overwrite:bool, expected_size:u64) -> Result<Upload, Error> {
+    start_upload(path, mimetype, overwrite, expected_size, None)
+}
+
+/// starts uploading a file with the declared chunk layout
+///
+/// `sendData` accepts only the declared offsets with the declared sizes (once each),
+/// and `commitUpload` fails with ERROR_INVALID_SEQUENCE naming the offset of a missing chunk.
+///
+/// # Arguments
+///
+/// * `path` - must start with ROOT and the parent directory must exist
+/// * `mimetype` - mimetype of the file
+/// * 'overwrite' - whether to overwrite the file if it already exists
+/// * 'expected_size' - total size of the file to be committed
+/// * 'chunk_offsets' - start offsets of the chunks in ascending order from 0 (each chunk up to CHUNK_SIZE)
+#[ic_cdk::update(name="beginUploadWithChunks")]
+pub fn begin_upload_with_chunks(path:String, mimetype:String, overwrite:bool, expected_size:u64, chunk_offsets:Vec<u64>) -> Result<Upload, Error> {
+    let layout = chunk_layout(expected_size, &chunk_offsets)?;
+    start_upload(path, mimetype, overwrite, expected_size, Some(layout))
+}
+
+fn start_upload(path:String, mimetype:String, overwrite:bool, expected_size:u64, layout:Option<BTreeMap<u64, u64>>) -> Result<Upload, Error> {
+    check_writable()?;
+
+    // First, check path 
+    let path = validate_path(&path)?;
 
-    // starts with
-    if path.starts_with(ROOT) == false {
-        return error!(ERROR_INVALID_PATH, "Not full path");
+    // Second, check mimetype and size
+    check_mimetype(&mimetype)?;
+    if expected_size > MAX_FILE_SIZE {
+        return error!(ERROR_INVALID_SIZE, "File is too large");
     }
+    check_space(expected_size)?;
 
-    // ends with '/' (except root)
-    if length > 1 && path.ends_with('/') {
-        return error!(ERROR_INVALID_PATH, "Ends with path separator (/)");
+    // Third check permission
+    let caller = caller();
+    let file_info = get_file_info(&path);
+    if !check_write_permission(&caller, &path, file_info.as_ref()) {
+        return error!(ERROR_PERMISSION_DENIED, "Permission denied");
     }
-    
-    // invalid characters
-    if ["..", "`"].iter().any(|s| path.contains(s)) {
-        return error!(ERROR_INVALID_PATH, "Path contains invalid characters");
+    check_lock(&caller, &path)?;
+
+    // Forth Uploading
+    if is_uploading(&path) {
+      return error!(ERROR_ALREADY_EXISTS, "File already exists");
     }
-    Ok(())
-}
 
-/// returns file info path (metadata of file)
-fn file_info_path(path:&String) -> String {
-    if path == "/" {
-        return "/`".to_string();
+    // Fifth, check whether file exists or not
+    if file_info.as_ref().is_some_and(|info| info.is_dir()) {
+        return error!(ERROR_IS_DIRECTORY, "Directory already exists");
+    } else if file_info.is_some() && !overwrite {
+        return error!(ERROR_ALREADY_EXISTS, "File already exists");
+    } else {
+        check_parent_directory(&path)?;
     }
-    match path.rfind("/") {
-        Some(index) => {
-            format!("{}`{}", &path[0..index +1], &path[index + 1..])
-        },
-        None => {
-            // FIXME Not expected
-            format!("`{}", path)
+    check_quotas(&caller, &path, file_info.as_ref(), expected_size)?;
+
+    UPLOADING.with(|uploading| {
+        let mut map = uploading.borrow_mut();
+
+        // Remove expired first
+        let now = time();
+        map.retain(|_key, value| (value.updated_at + UPLOAD_TIMEOUT) >= now);
+
+        // Insert entry
+        map.insert(path, Uploading{
+            owner: caller,
+            updated_at: now,
+            size: 0,
+            expected_size,
+            mimetype,
+            overwrite,
+            chunk: BTreeMap::new(),
+            layout: layout.clone(),
+        });
+        Ok(Upload {
+            size: expected_size,
+            chunk_size: CHUNK_SIZE,
+            chunk_count: layout.map_or(expected_size.div_ceil(CHUNK_SIZE), |layout| layout.len() as u64),
+            expires_at: now + UPLOAD_TIMEOUT,
+        })
+    })
+}
+
+/// uploads a chunk of the file to the canister
+///
+/// # Arguments
+///
+/// * `path` - must start with ROOT and the parent directory must exist
+/// * `start` - start index
+/// * 'data' - chunk of the file (up to CHUNK_SIZE bytes, within the expected size)
+#[ic_cdk::update(name="sendData")]
+pub fn send_data(path:String, start:u64, data:Vec<u8>) -> Result<u64, Error> {
+    check_writable()?;
+    let path = storage_key(&path);
+    let caller = caller();
+
+    UPLOADING.with(|uploading| {
+        let mut map = uploading.borrow_mut();
+        match map.get_mut(&path) {
+            Some(value) => {
+                let now = time();
+                if value.owner != caller {
+                    no_upload_session()
+                } else if (value.updated_at + UPLOAD_TIMEOUT) < now {
+                    error!(ERROR_PERMISSION_DENIED, "session expired")
+                } else if data.len() as u64 > CHUNK_SIZE {
+                    error!(ERROR_INVALID_SIZE, "Chunk is too large")
+                } else if start.saturating_add(data.len() as u64) > value.expected_size {
+                    error!(ERROR_INVALID_SIZE, "Chunk exceeds the expected size")
+                } else {
+                    check_declared_chunk(value, start, data.len() as u64)?;
+                    // a re-sent chunk replaces the previous one, so only the covered size is charged
+                    let replaced = value.chunk.get(&start).map_or(0, |old| old.len() as u64);
+                    if let Err(e) = check_quotas(&caller, &path, get_file_info(&path).as_ref(), value.size - replaced + data.len() as u64) {
+                        map.remove(&path);
+                        return Err(e);
+                    }
+                    value.size += data.len() as u64;
+                    value.updated_at = now;
+
+                    // map.try_insert() is still unstable...
+                    match value.chunk.insert(start, data) {
+                        Some(old) => {
+                            value.size -= old.len() as u64;
+                            Ok(value.size)
+                        },
+                        None => Ok(value.size)
+                    }
+                }
+            },
+            None => no_upload_session()
         }
-    }
+    })
 }
 
-fn parent_path(path:&String) -> String {
-    if path == "/" { // Not expected
-        "".to_string()
+/// returns the progress and the expiry of the caller's upload session
+///
+/// # Arguments
+///
+/// * `path` - path given to `beginUpload`
+#[ic_cdk::query(name="uploadStatus")]
+pub fn upload_status(path:String) -> Result<UploadStatus, Error> {
+    let path = storage_key(&path);
+    let caller = caller();
+
+    UPLOADING.with(|uploading| {
+        match uploading.borrow().get(&path) {
+            Some(value) if value.owner == caller => {
+                let now = time();
+                let expires_at = value.updated_at + UPLOAD_TIMEOUT;
+                if expires_at < now {
+                    return error!(ERROR_PERMISSION_DENIED, "session expired");
+                }
+                Ok(UploadStatus {
+                    size: value.size,
+                    expected_size: value.expected_size,
+                    expires_at,
+                    remaining_ms: expires_at - now,
+                })
+            },
+            _ => no_upload_session()
+        }
+    })
+}
+
+/// commits uploading a file
+///
+/// On a size or hash mismatch (ERROR_INVALID_SEQUENCE, ERROR_INVALID_SIZE, ERROR_INVALID_HASH),
+/// the session is kept so that the client can resend the chunks and retry.
+/// On an expired session or an IO error (ERROR_UNKNOWN), the session and its temp file are removed
+/// and the client must start over with `beginUpload`. So is the session if the rules of `save`
+/// no longer hold for the file, e.g. it was created by someone else after `beginUpload` without
+/// `overwrite` (ERROR_ALREADY_EXISTS) or the write permission was revoked.
+///
+/// # Arguments
+///
+/// * `path` - must start with ROOT and the parent directory must exist
+/// * `size` - total size of the file
+/// * `sha256` - hash of the file to verify if specified
+///
+/// Returns the info of the stored file, including the verified size and sha256.
+#[ic_cdk::update(name="commitUpload")]
+pub fn commit_upload(path:String, size:u64, sha256:Option<[u8; 32]>) -> Result<Info, Error> {
+    check_writable()?;
+    let name = display_name(&path);
+    let path = storage_key(&path);
+    let caller = caller();
+    check_lock(&caller, &path)?;
+
+    UPLOADING.with(|uploading| {
+        let mut map = uploading.borrow_mut();
+        let value = match map.get(&path) {
+            Some(value) => value,
+            None => return no_upload_session()
+        };
+        let now = time();
+        if value.owner != caller {
+            return no_upload_session();
+        } else if (value.updated_at + UPLOAD_TIMEOUT) < now {
+            map.remove(&path);
+            return error!(ERROR_PERMISSION_DENIED, "transaction expired");
+        } else if value.expected_size != size {
+            return error!(ERROR_INVALID_SIZE, "Size differs from the expected size");
+        } else if let Some(offset) = value.layout.iter().flat_map(|layout| layout.keys()).find(|offset| !value.chunk.contains_key(offset)) {
+            return error!(ERROR_INVALID_SEQUENCE, format!("Missing chunk at {}", offset));
+        }
+
+        // First, verify chunks in offset order (retryable)
+        check_chunks(&value.chunk, size)?;
+        let mut hasher = Sha256::new();
+        for data in value.chunk.values() {
+            hasher.update(data);
+        }
+        let sha256_verified:[u8; 32] = hasher.finalize().into();
+        if sha256.is_some_and(|sha256| sha256 != sha256_verified) {
+            return error!(ERROR_INVALID_HASH, "Invalid hash");
+        }
+
+        // things may have changed since beginUpload
+        let file_info = get_file_info(&path);
+        if let Err(e) = check_commit(&caller, &path, file_info.as_ref(), value) {
+            map.remove(&path);
+            return Err(e);
+        }
+        check_space(size)?;
+        if let Err(e) = check_quotas(&caller, &path, file_info.as_ref(), size) {
+            map.remove(&path);
+            return Err(e);
+        }
+
+        // Second, write file as temp, and then rename it (not retryable)
+        let temp_path = temp_path(&path);
+        let result = File::create(&temp_path)
+            .and_then(|file| {
+                let mut buffer = BufWriter::with_capacity(2*1024*1024, file); // 2MiB Buffer
+                for data in value.chunk.values() {
+                    buffer.write_all(data)?;
+                }
+                buffer.flush()
+            });
+        if let Err(e) = result {
+            map.remove(&path);
+            let _ = fs::remove_file(&temp_path);
+            return error!(ERROR_UNKNOWN, format!("{:?}", e));
+        }
+        let old_blob = file_info.as_ref().and_then(|info| info.blob);
+        let blob = match place_content(&temp_path, &path, &sha256_verified) {
+            Ok(blob) => blob,
+            Err(e) => {
+                map.remove(&path);
+                return Err(e);
+            }
+        };
+
+        let mut info = match file_info {
+            Some(mut info) => {
+                // Update
+                info.size = size;
+                info.updated_at = now;
+                info.mimetype = value.mimetype.clone();
+                info.sha256 = Some(sha256_verified);
+                info.version += 1;
+                info.signature = None;
+                info.pubkey = None;
+                info
+            },
+            None => {
+                // New
+                FileInfo {
+                    size,
+                    sha256: Some(sha256_verified),
+                    name,
+                    ..new_file_info(&path, caller, now, value.mimetype.clone())
+                }
+            }
+        };
+        info.blob = blob;
+        map.remove(&path);
+        set_file_info(&path, &info)?;
+        if let Some(old_blob) = old_blob {
+            release_blob(&old_blob);
+        }
+        touch_parents(&path, now)?;
+        to_info(&caller, &path, info)
+    })
+}
+
+/// checks the rules of `save` again against the current state when committing an upload
+fn check_commit(caller:&Principal, path:&String, file_info:Option<&FileInfo>, session:&Uploading) -> Result<(), Error> {
+    check_mimetype(&session.mimetype)?;
+    if !check_write_permission(caller, path, file_info) {
+        return error!(ERROR_PERMISSION_DENIED, "Permission denied");
+    }
+    if file_info.is_some_and(|info| info.is_dir()) {
+        error!(ERROR_IS_DIRECTORY, "Directory already exists")
+    } else if file_info.is_some() && !session.overwrite {
+        error!(ERROR_ALREADY_EXISTS, "File already exists")
     } else {
-        match path.rfind("/") {
-            Some(index) => format!("{}", &path[0..index]),
-            None => "".to_string() // not expected
+        check_parent_directory(path)
+    }
+}
+
+/// returns whether any upload session is in progress (not expired)
+///
+/// Upload sessions are kept in heap memory and discarded on upgrade,
+/// so operators can check this before upgrading the canister.
+#[ic_cdk::query(name="hasActiveUploads")]
+pub fn has_active_uploads() -> bool {
+    active_upload_count() > 0
+}
+
+/// cancels uploading a file
+///
+/// # Arguments
+///
+/// * `path` - must start with ROOT and the parent directory must exist
+#[ic_cdk::update(name="cancelUpload")]
+pub fn cancel_upload(path:String) -> Result<(), Error> {
+    let path = storage_key(&path);
+    let caller = caller();
+
+    UPLOADING.with(|uploading| {
+        let mut map = uploading.borrow_mut();
+        match map.get(&path) {
+            Some(value) => {
+                if value.owner != caller {
+                    no_upload_session()
+                } else {
+                    map.remove(&path);
+                    // best effort: a partially run commit may have left it
+                    let _ = fs::remove_file(temp_path(&path));
+                    Ok(())
+                }
+            }
+            None => no_upload_session()
+        }
+    })
+}
+
+/// deletes a file
+///
+/// # Arguments
+///
+/// * `path` - must start with ROOT and the parent directory must exist
+#[ic_cdk::update(name="delete")]
+pub fn delete(path:String) -> Result<(), Error> {
+    delete_file(path, None)
+}
+
+/// deletes a file only if its content is unchanged
+///
+/// Fails with ERROR_VERSION_CONFLICT if sha256 of the file doesn't match.
+///
+/// # Arguments
+///
+/// * `path` - must start with ROOT
+/// * `expected_sha256` - sha256 of the content the caller read (`Info.sha256`)
+#[ic_cdk::update(name="deleteIfMatch")]
+pub fn delete_if_match(path:String, expected_sha256:[u8; 32]) -> Result<(), Error> {
+    delete_file(path, Some(expected_sha256))
+}
+
+/// deletes a file after checking sha256 if expected
+fn delete_file(path:String, expected_sha256:Option<[u8; 32]>) -> Result<(), Error> {
+    check_writable()?;
+    let path = validate_path(&path)?;
+
+    // Second, check permission
+    let caller = caller();
+    let file_info = get_file_info(&path);
+    if !check_write_permission(&caller, &path, file_info.as_ref()) {
+        return error!(ERROR_PERMISSION_DENIED, "Permission denied");
+    }
+    check_lock(&caller, &path)?;
+
+    if file_info.as_ref().is_some_and(|info| info.is_dir()) {
+        return error!(ERROR_IS_DIRECTORY, "Directory can't be deleted by delete; use deleteDirectory");
+    }
+    if let Some(expected_sha256) = expected_sha256 {
+        match &file_info {
+            None => return error!(ERROR_NOT_FOUND, "File not found"),
+            Some(info) if info.sha256 != Some(expected_sha256) => return error!(ERROR_VERSION_CONFLICT, "File has been changed"),
+            _ => {}
+        }
+    }
+
+    match fs::remove_file(&path) {
+        Ok(_) => {
+            delete_file_info(&path);
+            if let Some(blob) = file_info.and_then(|info| info.blob) {
+                release_blob(&blob);
+            }
+
+            touch_parents(&path, time())
+        },
+        Err(e) => match e.kind() {   
+            ErrorKind::NotFound => error!(ERROR_NOT_FOUND, "File not found"),
+            _=> error!(ERROR_UNKNOWN, format!("{:?}", e))
         }
     }
 }
 
-fn get_file_info(path:&String) -> Option<FileInfo> {
-    match File::open(file_info_path(path)) {
-        Ok(file) => {
-            let reader = BufReader::new(file);
-            let result = serde_cbor::from_reader(reader).unwrap();
-            Some(result)
-       },
-        Err(_) => {
-            None
-        }
-    }
-}
+/// deletes files
+///
+/// Each path is processed independently with the same checks as `delete`,
+/// so one bad path doesn't fail the others.
+///
+/// # Arguments
+///
+/// * `paths` - each must start with ROOT (up to MAX_BATCH_SIZE paths)
+#[ic_cdk::update(name="deleteMany")]
+pub fn delete_many(paths:Vec<String>) -> Vec<Result<(), Error>> {
+    paths.into_iter()
+        .enumerate()
+        .map(|(index, path)| {
+            if index < MAX_BATCH_SIZE {
+                delete(path)
+            } else {
+                error!(ERROR_INVALID_SIZE, "Too many paths")
+            }
+        })
+        .collect()
+}
+
+/// saves small files
+///
+/// Each file is processed independently with the same checks as `save`,
+/// so one bad file doesn't fail the others.
+///
+/// # Arguments
+///
+/// * `files` - path, mimetype and content of each file (up to MAX_BATCH_SIZE files, MAX_INGRESS_SIZE bytes in total)
+/// * `overwrite` - whether to overwrite the files if they already exist
+#[ic_cdk::update(name="saveMany")]
+pub fn save_many(files:Vec<(String, String, Vec<u8>)>, overwrite:bool) -> Vec<Result<(), Error>> {
+    let mut total:usize = 0;
+    files.into_iter()
+        .enumerate()
+        .map(|(index, (path, mimetype, data))| {
+            total = total.saturating_add(data.len());
+            if index >= MAX_BATCH_SIZE {
+                error!(ERROR_INVALID_SIZE, "Too many files")
+            } else if total > MAX_INGRESS_SIZE {
+                error!(ERROR_INVALID_SIZE, "Files are too large")
+            } else {
+                save(path, mimetype, data, overwrite).map(|_| ())
+            }
+        })
+        .collect()
+}
+
+/// moves (renames) a file or directory
+///
+/// # Arguments
+///
+/// * `from` - must start with ROOT
+/// * `to` - must start with ROOT, must not exist, and the parent directory must exist
+#[ic_cdk::update(name="move")]
+pub fn move_path(from:String, to:String) -> Result<(), Error> {
+    check_writable()?;
+    let name = display_name(&to);
+    let from = validate_path(&from)?;
+    let to = validate_path(&to)?;
+    check_not_into_itself(&from, &to)?;
+
+    let caller = caller();
+    let from_info = get_file_info(&from);
+    let to_info = get_file_info(&to);
+    if !check_write_permission(&caller, &from, from_info.as_ref()) || !check_write_permission(&caller, &to, to_info.as_ref()) {
+        return error!(ERROR_PERMISSION_DENIED, "Permission denied");
+    }
+    check_lock(&caller, &from)?;
+    check_lock(&caller, &to)?;
+    check_descendant_locks(&caller, &from)?;
+
+    if from_info.is_none() {
+        return error!(ERROR_NOT_FOUND, "File not found");
+    }
+    if to_info.is_some() {
+        return error!(ERROR_ALREADY_EXISTS, "File already exists");
+    }
+    check_not_uploading(&from)?;
+    check_not_uploading(&to)?;
+    check_parent_directory(&to)?;
+    check_move_quotas(&from, &to, path_size(&from, from_info.as_ref()), 0)?;
+
+    match fs::rename(&from, &to) {
+        Ok(_) => {
+            move_file_infos(&from, &to);
+            if let Some(mut info) = get_file_info(&to).filter(|_| name.is_some()) {
+                info.name = name;
+                set_file_info(&to, &info)?;
+            }
+            Ok(())
+        },
+        Err(e) => error!(ERROR_UNKNOWN, format!("{:?}", e))
+    }
+}
+
+/// swaps two files or two directories with their metadata
+///
+/// The contents are exchanged by renaming through a temp name within the call, so neither
+/// path is ever observed missing.
+///
+/// # Arguments
+///
+/// * `path_a` - must start with ROOT and must exist
+/// * `path_b` - must start with ROOT and must exist; a file if `path_a` is a file, a directory otherwise
+#[ic_cdk::update]
+pub fn swap(path_a:String, path_b:String) -> Result<(), Error> {
+    check_writable()?;
+    let a = validate_path(&path_a)?;
+    let b = validate_path(&path_b)?;
+    check_not_into_itself(&a, &b)?;
+    check_not_into_itself(&b, &a)?;
+
+    let caller = caller();
+    let a_info = get_file_info(&a);
+    let b_info = get_file_info(&b);
+    if !check_write_permission(&caller, &a, a_info.as_ref()) || !check_write_permission(&caller, &b, b_info.as_ref()) {
+        return error!(ERROR_PERMISSION_DENIED, "Permission denied");
+    }
+    check_lock(&caller, &a)?;
+    check_lock(&caller, &b)?;
+
+    let (Some(a_info), Some(b_info)) = (a_info, b_info) else {
+        return error!(ERROR_NOT_FOUND, "File not found");
+    };
+    match (a_info.is_dir(), b_info.is_dir()) {
+        (true, false) => return error!(ERROR_IS_DIRECTORY, "Can't swap a directory with a file"),
+        (false, true) => return error!(ERROR_NOT_A_DIRECTORY, "Can't swap a file with a directory"),
+        _ => {}
+    }
+    check_not_uploading(&a)?;
+    check_not_uploading(&b)?;
+    let (a_size, b_size) = (path_size(&a, Some(&a_info)), path_size(&b, Some(&b_info)));
+    check_move_quotas(&a, &b, a_size, b_size)?;
+    check_move_quotas(&b, &a, b_size, a_size)?;
+
+    // a -> temp, b -> a, temp -> b
+    let temp = temp_path(&a);
+    if let Err(e) = fs::rename(&a, &temp) {
+        return error!(ERROR_UNKNOWN, format!("{:?}", e));
+    }
+    if let Err(e) = fs::rename(&b, &a) {
+        let _ = fs::rename(&temp, &a);
+        return error!(ERROR_UNKNOWN, format!("{:?}", e));
+    }
+    if let Err(e) = fs::rename(&temp, &b) {
+        let _ = fs::rename(&a, &b);
+        let _ = fs::rename(&temp, &a);
+        return error!(ERROR_UNKNOWN, format!("{:?}", e));
+    }
+    move_file_infos(&a, &temp);
+    move_file_infos(&b, &a);
+    move_file_infos(&temp, &b);
+
+    // names stay with the paths (case-insensitive mode)
+    for (path, name) in [(&a, a_info.name), (&b, b_info.name)] {
+        if let Some(mut info) = get_file_info(path).filter(|info| info.name != name) {
+            info.name = name;
+            set_file_info(path, &info)?;
+        }
+    }
+    Ok(())
+}
+
+/// renames a file or directory within its parent directory
+///
+/// # Arguments
+///
+/// * `path` - must start with ROOT
+/// * `new_name` - new last component of the path (no '/')
+/// * `overwrite` - whether to replace an existing file (directories are never replaced)
+#[ic_cdk::update]
+pub fn rename(path:String, new_name:String, overwrite:bool) -> Result<(), Error> {
+    check_writable()?;
+    if new_name.is_empty() || new_name.contains('/') || new_name == "." || new_name == ".." {
+        return error!(ERROR_INVALID_PATH, "Invalid name");
+    }
+    let from = validate_path(&path)?;
+    if from == root() {
+        return error!(ERROR_INVALID_PATH, "ROOT can't be renamed");
+    }
+    let parent = match parent_path(&from) {
+        parent if parent.is_empty() => root(),
+        parent => parent
+    };
+    let to = format!("{}/{}", parent.trim_end_matches('/'), new_name);
+    let name = display_name(&to);
+    let to = validate_path(&to)?;
+
+    let caller = caller();
+    if !check_write_permission(&caller, &parent, get_file_info(&parent).as_ref()) {
+        return error!(ERROR_PERMISSION_DENIED, "Permission denied");
+    }
+    check_lock(&caller, &from)?;
+    check_lock(&caller, &to)?;
+    check_descendant_locks(&caller, &from)?;
+
+    let Some(mut from_info) = get_file_info(&from) else {
+        return error!(ERROR_NOT_FOUND, "File not found");
+    };
+    if to == from {
+        // only the casing changes (case-insensitive mode)
+        if name.is_some() {
+            from_info.name = name;
+            set_file_info(&from, &from_info)?;
+        }
+        return Ok(());
+    }
+
+    let to_info = get_file_info(&to);
+    if let Some(to_info) = to_info.as_ref() {
+        if to_info.is_dir() {
+            return error!(ERROR_IS_DIRECTORY, "Directory already exists");
+        } else if !overwrite || from_info.is_dir() {
+            return error!(ERROR_ALREADY_EXISTS, "File already exists");
+        }
+    }
+    check_not_uploading(&from)?;
+    check_not_uploading(&to)?;
+
+    match fs::rename(&from, &to) {
+        Ok(_) => {
+            if to_info.is_some() {
+                release_content(&to);
+                delete_file_info(&to);
+            }
+            move_file_infos(&from, &to);
+            if let Some(mut info) = get_file_info(&to).filter(|_| name.is_some()) {
+                info.name = name;
+                set_file_info(&to, &info)?;
+            }
+            Ok(())
+        },
+        Err(e) => error!(ERROR_UNKNOWN, format!("{:?}", e))
+    }
+}
+
+/// applies operations all-or-nothing
+///
+/// All operations are validated first (path, mimetype, permission and lock) without writing,
+/// so a batch rejected there leaves nothing to revert. Then they are applied in order.
+/// If one of them fails while applying, the already applied ones are reverted and the error
+/// of the failed one is returned, followed by the operations that couldn't be reverted.
+///
+/// # Arguments
+///
+/// * `ops` - operations to apply (up to MAX_BATCH_SIZE operations)
+#[ic_cdk::update]
+pub fn batch(ops:Vec<Op>) -> Result<(), Error> {
+    check_writable()?;
+    if ops.len() > MAX_BATCH_SIZE {
+        return error!(ERROR_INVALID_SIZE, "Too many operations");
+    }
+
+    // storage keys of the paths (backups and undos must not depend on how a path was written)
+    let caller = caller();
+    let mut keys:Vec<Vec<String>> = Vec::new();
+    for (index, op) in ops.iter().enumerate() {
+        match op_keys(op).and_then(|op_keys| check_op(&caller, op, &op_keys).map(|_| op_keys)) {
+            Ok(op_keys) => keys.push(op_keys),
+            Err(e) => return error!(e.code, format!("ops[{}]: {}", index, e.message))
+        }
+    }
+
+    let mut undos:Vec<Undo> = Vec::new();
+    for (index, (op, op_keys)) in ops.into_iter().zip(keys).enumerate() {
+        if let Err(e) = apply_op(index, op, op_keys, &mut undos) {
+            let mut message = format!("ops[{}]: {}", index, e.message);
+            for undo in undos.into_iter().rev() {
+                if let Err(revert_error) = revert_op(undo) {
+                    message.push_str(&format!("; revert failed: {}", revert_error.message));
+                }
+            }
+            return error!(e.code, message);
+        }
+    }
+
+    // Commit: backups are no longer needed
+    for undo in undos {
+        if let Undo::Restore { backup, info, .. } = undo {
+            discard_backup(&backup, &info);
+        }
+    }
+    Ok(())
+}
+
+/// removes a backup of `batch` (and its reference to the shared blob)
+fn discard_backup(backup:&String, info:&FileInfo) {
+    let _ = fs::remove_file(backup);
+    if let Some(blob) = info.blob {
+        release_blob(&blob);
+    }
+}
+
+/// releases the shared blob referenced by the current file of the path (if any)
+fn release_content(path:&str) {
+    if let Some(blob) = get_file_info(&path.to_string()).and_then(|info| info.blob) {
+        release_blob(&blob);
+    }
+}
+
+/// returns the storage keys of the paths of an operation of `batch` (`[path]` or `[from, to]`)
+fn op_keys(op:&Op) -> Result<Vec<String>, Error> {
+    match op {
+        Op::Save { path, .. } | Op::Delete { path } | Op::CreateDirectory { path } => Ok(vec![validate_path(path)?]),
+        Op::Move { from, to } => Ok(vec![validate_path(from)?, validate_path(to)?]),
+    }
+}
+
+/// validates an operation of `batch` against the current state without writing
+///
+/// Existence is left to `apply_op` because earlier operations may create or remove the paths.
+fn check_op(caller:&Principal, op:&Op, keys:&[String]) -> Result<(), Error> {
+    match op {
+        Op::Save { mimetype, .. } => check_mimetype(mimetype)?,
+        Op::Move { .. } => check_not_into_itself(&keys[0], &keys[1])?,
+        _ => {}
+    }
+    for key in keys {
+        if !check_write_permission(caller, key, get_file_info(key).as_ref()) {
+            return error!(ERROR_PERMISSION_DENIED, "Permission denied");
+        }
+        check_lock(caller, key)?;
+    }
+    Ok(())
+}
+
+/// copies the current content of a file aside to restore it (None if there is no file)
+fn backup_file(key:&String, index:usize) -> Result<Option<(String, FileInfo)>, Error> {
+    match get_file_info(key) {
+        Some(info) if !info.is_dir() => {
+            let backup = backup_path(key, index);
+            if let Err(e) = fs::copy(key, &backup) {
+                return error!(ERROR_UNKNOWN, format!("{:?}", e));
+            }
+            if let Some(blob) = info.blob {
+                retain_blob(&blob); // keep the shared content while backed up
+            }
+            Ok(Some((backup, info)))
+        },
+        _ => Ok(None)
+    }
+}
+
+/// applies an operation of `batch`, recording how to revert it
+///
+/// The operation is given its original paths (to keep the display names), while backups and
+/// undos use the storage keys from `op_keys`.
+fn apply_op(index:usize, op:Op, keys:Vec<String>, undos:&mut Vec<Undo>) -> Result<(), Error> {
+    let mut keys = keys.into_iter();
+    let key = keys.next().unwrap_or_default();
+    match op {
+        Op::Save { path, mimetype, data, overwrite } => {
+            let backup = if overwrite { backup_file(&key, index)? } else { None };
+            match save(path, mimetype, data, overwrite) {
+                Ok(_) => {
+                    undos.push(match backup {
+                        Some((backup, info)) => Undo::Restore { path: key, backup, info: Box::new(info) },
+                        None => Undo::Remove { path: key }
+                    });
+                    Ok(())
+                },
+                Err(e) => {
+                    if let Some((backup, info)) = backup {
+                        discard_backup(&backup, &info);
+                    }
+                    Err(e)
+                }
+            }
+        },
+        Op::Delete { path } => {
+            let backup = backup_file(&key, index)?;
+            match delete(path) {
+                Ok(_) => {
+                    if let Some((backup, info)) = backup {
+                        undos.push(Undo::Restore { path: key, backup, info: Box::new(info) });
+                    }
+                    Ok(())
+                },
+                Err(e) => {
+                    if let Some((backup, info)) = backup {
+                        discard_backup(&backup, &info);
+                    }
+                    Err(e)
+                }
+            }
+        },
+        Op::CreateDirectory { path } => {
+            create_directory(path)?;
+            undos.push(Undo::RemoveDirectory { path: key });
+            Ok(())
+        },
+        Op::Move { from, to } => {
+            move_path(from, to)?;
+            undos.push(Undo::Move { from: key, to: keys.next().unwrap_or_default() });
+            Ok(())
+        },
+    }
+}
+
+/// reverts an applied operation of `batch`
+fn revert_op(undo:Undo) -> Result<(), Error> {
+    match undo {
+        Undo::Restore { path, backup, info } => {
+            release_content(&path);
+            if let Err(e) = fs::rename(&backup, &path) {
+                return error!(ERROR_UNKNOWN, format!("{}: {:?}", path, e));
+            }
+            set_file_info(&path, &info)
+        },
+        Undo::Remove { path } => {
+            release_content(&path);
+            delete_file_info(&path);
+            match fs::remove_file(&path) {
+                Ok(_) => Ok(()),
+                Err(e) => error!(ERROR_UNKNOWN, format!("{}: {:?}", path, e))
+            }
+        },
+        Undo::RemoveDirectory { path } => {
+            delete_file_info(&path);
+            match fs::remove_dir(&path) {
+                Ok(_) => Ok(()),
+                Err(e) => error!(ERROR_UNKNOWN, format!("{}: {:?}", path, e))
+            }
+        },
+        Undo::Move { from, to } => {
+            match fs::rename(&to, &from) {
+                Ok(_) => {
+                    move_file_infos(&to, &from);
+                    Ok(())
+                },
+                Err(e) => error!(ERROR_UNKNOWN, format!("{}: {:?}", to, e))
+            }
+        },
+    }
+}
+
+/// returns a list of the files/directories in the specified path
+///
+/// # Arguments
+///
+/// * `path` - must start with ROOT and the parent directory must exist
+#[ic_cdk::query(name="listFiles")]
+pub fn list_files(path:String) -> Result<Vec<String>, Error> {
+    let path = validate_path(&path)?;
+    let info = listable_file_info(&caller(), &path, "Directory not found")?;
+    if !info.is_dir() {
+        return error!(ERROR_NOT_A_DIRECTORY, "Not a directory");
+    }
+
+    let entries = match fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(e) => return error!(ERROR_UNKNOWN, format!("{:?}", e))
+    };
+    let mut files:Vec<String> = entries
+        .map(| entry | {
+            let entry = entry.unwrap();
+            let file_name = entry.path().file_name().unwrap().to_string_lossy().into_owned();
+            // original casing in case-insensitive mode
+            let file_name = get_file_info(&entry.path().to_string_lossy().into_owned())
+                .and_then(|info| info.name)
+                .unwrap_or(file_name);
+            if entry.file_type().unwrap().is_dir() { 
+                format!("{}/", file_name)
+            } else {
+                file_name.to_string()
+            }
+        })
+        .filter(| file | !file.starts_with(INTERNAL_PREFIX)) // Remove internal files
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// returns a list of the files/directories in the specified path sorted by the key
+///
+/// Directories end with '/' as `listFiles`. Ties are broken by name (ascending).
+///
+/// # Arguments
+///
+/// * `path` - must start with ROOT and the directory must exist
+/// * `by` - key to sort by
+/// * `desc` - sorts in descending order if true
+/// * `dirs_first` - lists directories before files (each sorted by the key) if true
+#[ic_cdk::query(name="listFilesSorted")]
+pub fn list_files_sorted(path:String, by:SortKey, desc:bool, dirs_first:bool) -> Result<Vec<String>, Error> {
+    let path = validate_path(&path)?;
+    let info = listable_file_info(&caller(), &path, "Directory not found")?;
+    if !info.is_dir() {
+        return error!(ERROR_NOT_A_DIRECTORY, "Not a directory");
+    }
+
+    let mut children = child_infos(&path)?;
+    children.sort_by(|(a_name, a), (b_name, b)| {
+        let ordering = match by {
+            SortKey::Name => a_name.cmp(b_name),
+            SortKey::Size => a.size.cmp(&b.size),
+            SortKey::Updated => a.updated_at.cmp(&b.updated_at),
+            SortKey::Created => a.created_at.cmp(&b.created_at),
+        };
+        let ordering = if desc { ordering.reverse() } else { ordering };
+        let ordering = if dirs_first { b.is_dir().cmp(&a.is_dir()).then(ordering) } else { ordering };
+        ordering.then_with(|| a_name.cmp(b_name))
+    });
+    Ok(children.into_iter()
+        .map(|(name, info)| if info.is_dir() { format!("{}/", name) } else { name })
+        .collect())
+}
+
+/// serves the HTTP gateway
+///
+/// A directory path ending with '/' returns a JSON array of `{name, is_dir, size, mimetype}`.
+/// Other paths return the content of the file (up to MAX_HTTP_SIZE); with `?download=1`,
+/// `Content-Disposition: attachment` makes browsers save it with the original name.
+/// Only paths readable by anonymous (public) are served.
+/// CORS headers are added for the allowed origins, and `OPTIONS` (preflight) returns 204.
+///
+/// # Arguments
+///
+/// * `request` - `url` is relative to ROOT
+#[ic_cdk::query]
+pub fn http_request(request:HttpRequest) -> HttpResponse {
+    let mut response = if request.method.eq_ignore_ascii_case("OPTIONS") {
+        HttpResponse {
+            status_code: 204,
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    } else {
+        serve_http(&request)
+    };
+    response.headers.extend(cors_headers(&request.headers));
+    response
+}
+
+/// returns the directory index or the file of the request
+fn serve_http(request:&HttpRequest) -> HttpResponse {
+    let (url_path, query) = request.url.split_once('?').unwrap_or((&request.url, ""));
+    let url_path = percent_decode(url_path);
+    let target = match url_path.trim_end_matches('/') {
+        "" => "/",
+        target => target
+    };
+    let Ok(path) = validate_path(target) else {
+        return http_error(400, "Bad Request");
+    };
+    let info = match readable_file_info(&Principal::anonymous(), &path, "File not found") {
+        Ok(info) => info,
+        Err(e) if e.code == ERROR_PERMISSION_DENIED => return http_error(403, "Forbidden"),
+        Err(_) => return http_error(404, "Not Found")
+    };
+    match (url_path.ends_with('/'), info.is_dir()) {
+        (true, true) => http_directory_index(&path),
+        (false, false) => http_file(&path, &info, query_param(query, "download").as_deref() == Some("1")),
+        _ => http_error(404, "Not Found")
+    }
+}
+
+/// returns CORS headers if the `Origin` of the request is allowed
+fn cors_headers(headers:&[(String, String)]) -> Vec<(String, String)> {
+    let Some((_, origin)) = headers.iter().find(|(name, _)| name.eq_ignore_ascii_case("Origin")) else {
+        return Vec::new();
+    };
+    let origins = config().cors_origins;
+    let allowed = if origins.iter().any(|allowed| allowed == origin) {
+        origin.clone()
+    } else if origins.iter().any(|allowed| allowed == "*") {
+        "*".to_string()
+    } else {
+        return Vec::new();
+    };
+    vec![
+        ("Access-Control-Allow-Origin".to_string(), allowed),
+        ("Access-Control-Allow-Methods".to_string(), "GET, HEAD, OPTIONS".to_string()),
+        ("Access-Control-Allow-Headers".to_string(), "Content-Type, Range".to_string()),
+        ("Vary".to_string(), "Origin".to_string()),
+    ]
+}
+
+/// returns a JSON array of the entries of a directory
+fn http_directory_index(path:&String) -> HttpResponse {
+    let mut entries = match child_entries(path) {
+        Ok(entries) => entries,
+        Err(_) => return http_error(500, "Internal Server Error")
+    };
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    let json = entries.iter()
+        .map(|entry| format!(
+            "{{\"name\":{},\"is_dir\":{},\"size\":{},\"mimetype\":{}}}",
+            json_string(&entry.name), entry.is_dir, entry.size, json_string(&entry.mimetype)
+        ))
+        .collect::<Vec<String>>()
+        .join(",");
+    HttpResponse {
+        status_code: 200,
+        headers: vec![("Content-Type".to_string(), "application/json".to_string())],
+        body: format!("[{}]", json).into_bytes(),
+    }
+}
+
+/// returns the files/directories in the specified path with their metadata
+///
+/// # Arguments
+///
+/// * `path` - must start with ROOT and the directory must exist
+#[ic_cdk::query(name="listEntries")]
+pub fn list_entries(path:String) -> Result<Vec<DirEntry>, Error> {
+    let path = validate_path(&path)?;
+    let info = listable_file_info(&caller(), &path, "Directory not found")?;
+    if !info.is_dir() {
+        return error!(ERROR_NOT_A_DIRECTORY, "Not a directory");
+    }
+
+    let mut entries = child_entries(&path)?;
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(entries)
+}
+
+/// creates a directory
+///
+/// # Arguments
+///
+/// * `path` - must start with ROOT and the parent directory must exist
+#[ic_cdk::update(name="createDirectory")]
+pub fn create_directory(path:String) -> Result<(), Error> {
+    check_writable()?;
+    let name = display_name(&path);
+    let path = validate_path(&path)?;
+
+    // Check write permission
+    let caller = caller();
+    let file_info = get_file_info(&path);
+    if !check_write_permission(&caller, &path, file_info.as_ref()) {
+        return error!(ERROR_PERMISSION_DENIED, "Permission denied");
+    }
+
+    match file_info {
+        Some(info) if info.is_dir() => return error!(ERROR_ALREADY_EXISTS, "Directory already exists"),
+        Some(_) => return error!(ERROR_ALREADY_EXISTS, "File already exists"),
+        None => {}
+    }
+
+    // check parents
+    check_parent_directory(&path)?;
+
+    match fs::create_dir(&path) {
+        Ok(_) => {
+            // create file_info
+            let now = time();
+            set_file_info(&path, &FileInfo {
+                name,
+                ..new_file_info(&path, caller, now, MIMETYPE_DIRECTORY.to_string())
+            })?;
+
+            touch_parents(&path, now)
+        },
+        Err(e) => error!(ERROR_UNKNOWN, format!("{:?}", e))
+    }
+}
+
+/// copies a directory with its descendants, up to MAX_COPY_STEP entries per call
+///
+/// The first call (without a token) creates `to`, and each call continues after the entry
+/// named by the token: the path (relative to `from`) of the last copied entry. Entries are
+/// copied in path order, so directories come before their contents. Call again with the
+/// returned token until `done` is true. Copies are owned by the caller with the default
+/// permissions of their new parent.
+///
+/// # Arguments
+///
+/// * `from` - directory to copy (read permission required for every entry)
+/// * `to` - must not exist on the first call, and the parent directory must exist
+/// * `token` - None to start, or the token returned by the previous call
+#[ic_cdk::update(name="copyDirectory")]
+pub fn copy_directory(from:String, to:String, token:Option<String>) -> Result<CopyProgress, Error> {
+    check_writable()?;
+    let from = validate_path(&from)?;
+    let to = validate_path(&to)?;
+    check_not_into_itself(&from, &to)?;
+
+    let caller = caller();
+    let from_info = readable_file_info(&caller, &from, "Directory not found")?;
+    if !from_info.is_dir() {
+        return error!(ERROR_NOT_A_DIRECTORY, "Not a directory");
+    }
+    let to_info = get_file_info(&to);
+    if !check_write_permission(&caller, &to, to_info.as_ref()) {
+        return error!(ERROR_PERMISSION_DENIED, "Permission denied");
+    }
+    check_lock(&caller, &to)?;
+    match (&token, to_info) {
+        (None, Some(_)) => return error!(ERROR_ALREADY_EXISTS, "File already exists"),
+        (None, None) => {
+            check_parent_directory(&to)?;
+            copy_entry(&from, &to, &from_info, &caller)?;
+        },
+        (Some(_), Some(info)) if info.is_dir() => {},
+        (Some(_), _) => return error!(ERROR_INVALID_SEQUENCE, "Destination of the copy not found"),
+    }
+
+    // entries after the token (a prefix sorts before its extensions, so parents come first)
+    let prefix = format!("{}/", from);
+    let start = format!("{}{}", prefix, token.as_deref().unwrap_or_default());
+    let entries:Vec<(String, FileInfo)> = FILE_INFOS.with(|infos| {
+        infos.borrow()
+            .range(start.clone()..)
+            .take_while(|(key, _)| key.starts_with(&prefix))
+            .filter(|(key, _)| *key != start)
+            .take(MAX_COPY_STEP + 1)
+            .collect()
+    });
+
+    let done = entries.len() <= MAX_COPY_STEP;
+    let mut copied = 0;
+    let mut last = None;
+    for (path, info) in entries.into_iter().take(MAX_COPY_STEP) {
+        if !check_read_permission(&caller, &path, Some(&info)) {
+            return error!(ERROR_PERMISSION_DENIED, format!("Permission denied: {}", path));
+        }
+        let relative = &path[prefix.len()..];
+        copy_entry(&path, &format!("{}/{}", to, relative), &info, &caller)?;
+        copied += 1;
+        last = Some(relative.to_string());
+    }
+    Ok(CopyProgress {
+        copied,
+        done,
+        token: if done { None } else { last },
+    })
+}
+
+/// copies a file or an (empty) directory with its metadata, owned by the caller
+fn copy_entry(from:&String, to:&String, info:&FileInfo, caller:&Principal) -> Result<(), Error> {
+    let result = if info.is_dir() {
+        fs::create_dir(to)
+    } else {
+        check_space(info.size)?;
+        check_quotas(caller, to, None, info.size)?;
+        match info.blob {
+            Some(blob) => File::create(to).map(|_| retain_blob(&blob)),
+            None => fs::copy(from, to).map(|_| ())
+        }
+    };
+    if let Err(e) = result {
+        return error!(ERROR_UNKNOWN, format!("{:?}", e));
+    }
+    set_file_info(to, &FileInfo {
+        size: info.size,
+        sha256: info.sha256,
+        blob: info.blob,
+        name: info.name.clone(),
+        ..new_file_info(to, *caller, time(), info.mimetype.clone())
+    })
+}
+
+/// moves a directory with its descendants, up to MAX_COPY_STEP entries per call
+///
+/// Unlike `move`, the work is split across calls for a large tree. The first call (without a
+/// token) creates `to` with the metadata of `from`, and each call continues after the entry
+/// named by the token: the path (relative to `from`) of the last processed entry. Files are
+/// moved one by one and directories are recreated under `to`, in path order, so every file is
+/// found either under `from` or under `to` between calls. Once every file has moved, the
+/// directories left under `from` are removed (deepest first) and `done` becomes true.
+/// Call again with the returned token until then.
+///
+/// # Arguments
+///
+/// * `from` - directory to move
+/// * `to` - must not exist on the first call, and the parent directory must exist
+/// * `token` - None to start, or the token returned by the previous call
+#[ic_cdk::update(name="moveDirectory")]
+pub fn move_directory(from:String, to:String, token:Option<String>) -> Result<MoveProgress, Error> {
+    check_writable()?;
+    let name = display_name(&to);
+    let from = validate_path(&from)?;
+    let to = validate_path(&to)?;
+    check_not_into_itself(&from, &to)?;
+
+    let caller = caller();
+    let from_info = get_file_info(&from);
+    let to_info = get_file_info(&to);
+    if !check_write_permission(&caller, &from, from_info.as_ref()) || !check_write_permission(&caller, &to, to_info.as_ref()) {
+        return error!(ERROR_PERMISSION_DENIED, "Permission denied");
+    }
+    check_lock(&caller, &from)?;
+    check_lock(&caller, &to)?;
+    check_descendant_locks(&caller, &from)?;
+
+    let Some(from_info) = from_info else {
+        return error!(ERROR_NOT_FOUND, "Directory not found");
+    };
+    if !from_info.is_dir() {
+        return error!(ERROR_NOT_A_DIRECTORY, "Not a directory");
+    }
+    match (&token, to_info) {
+        (None, Some(_)) => return error!(ERROR_ALREADY_EXISTS, "File already exists"),
+        (None, None) => {
+            check_parent_directory(&to)?;
+            if let Err(e) = fs::create_dir(&to) {
+                return error!(ERROR_UNKNOWN, format!("{:?}", e));
+            }
+            set_file_info(&to, &FileInfo { name: name.or(from_info.name.clone()), ..from_info })?;
+        },
+        (Some(_), Some(info)) if info.is_dir() => {},
+        (Some(_), _) => return error!(ERROR_INVALID_SEQUENCE, "Destination of the move not found"),
+    }
+
+    // entries after the token (a prefix sorts before its extensions, so parents come first)
+    let prefix = format!("{}/", from);
+    let start = format!("{}{}", prefix, token.as_deref().unwrap_or_default());
+    let entries:Vec<(String, FileInfo)> = FILE_INFOS.with(|infos| {
+        infos.borrow()
+            .range(start.clone()..)
+            .take_while(|(key, _)| key.starts_with(&prefix))
+            .filter(|(key, _)| *key != start)
+            .take(MAX_COPY_STEP)
+            .collect()
+    });
+
+    let mut moved = 0;
+    let mut last = token;
+    for (path, info) in entries {
+        let relative = &path[prefix.len()..];
+        let dest = format!("{}/{}", to, relative);
+        if info.is_dir() {
+            // removed after every file has moved
+            if let Err(e) = fs::create_dir(&dest) {
+                return error!(ERROR_UNKNOWN, format!("{:?}", e));
+            }
+            set_file_info(&dest, &info)?;
+        } else {
+            check_not_uploading(&path)?;
+            check_move_quotas(&path, &dest, info.size, 0)?;
+            if let Err(e) = fs::rename(&path, &dest) {
+                return error!(ERROR_UNKNOWN, format!("{:?}", e));
+            }
+            delete_file_info(&path);
+            set_file_info(&dest, &info)?;
+        }
+        moved += 1;
+        last = Some(relative.to_string());
+    }
+    if moved == MAX_COPY_STEP as u64 {
+        return Ok(MoveProgress { moved, done: false, token: last });
+    }
+
+    // only directories are left under `from` (children sort after their parents)
+    let end = format!("{}0", from); // '0' follows '/'
+    let directories:Vec<String> = FILE_INFOS.with(|infos| {
+        infos.borrow()
+            .range(prefix.clone()..end)
+            .rev()
+            .take(MAX_COPY_STEP - moved as usize)
+            .map(|(key, _)| key)
+            .collect()
+    });
+    for path in directories {
+        if let Err(e) = fs::remove_dir(&path) {
+            return error!(ERROR_UNKNOWN, format!("{:?}", e));
+        }
+        delete_file_info(&path);
+        moved += 1;
+    }
+    if moved == MAX_COPY_STEP as u64 {
+        return Ok(MoveProgress { moved, done: false, token: last });
+    }
+    if let Err(e) = fs::remove_dir(&from) {
+        return error!(ERROR_UNKNOWN, format!("{:?}", e));
+    }
+    delete_file_info(&from);
+    Ok(MoveProgress { moved, done: true, token: None })
+}
+
+/// creates a directory unless it already exists (idempotent `createDirectory`)
+///
+/// # Arguments
+///
+/// * `path` - must start with ROOT and the parent directory must exist; fails if it's a file
+#[ic_cdk::update(name="ensureDirectory")]
+pub fn ensure_directory(path:String) -> Result<(), Error> {
+    check_writable()?;
+    let key = validate_path(&path)?;
+
+    match get_file_info(&key) {
+        Some(info) => {
+            if !check_write_permission(&caller(), &key, Some(&info)) {
+                return error!(ERROR_PERMISSION_DENIED, "Permission denied");
+            }
+            if !info.is_dir() {
+                return error!(ERROR_NOT_A_DIRECTORY, "File already exists");
+            }
+            Ok(())
+        },
+        None => create_directory(path)
+    }
+}
+
+/// deletes a directory
+///
+/// Write permission of the directory is required. When deleting recursively, descendants the
+/// caller can't write or locked by another principal are left and reported as failures.
+///
+/// # Arguments
+///
+/// * `path` - must start with ROOT and the parent directory must exist
+/// * 'recursively' - whether to delete recursively
+#[ic_cdk::update(name="deleteDirectory")]
+pub fn delete_directory(path:String, recursively:bool) -> Result<(), Error> {
+    check_writable()?;
+    let path = validate_path(&path)?;
+    if path == root() {
+        // use the admin reset to wipe everything
+        return error!(ERROR_INVALID_PATH, "ROOT can't be deleted");
+    }
+
+    let file_info = get_file_info(&path);
+    let caller = caller();
+    if !check_write_permission(&caller, &path, file_info.as_ref()) {
+        return error!(ERROR_PERMISSION_DENIED, "Permission denied");
+    }
+    check_lock(&caller, &path)?;
+
+    if file_info.is_none() {
+        return error!(ERROR_NOT_FOUND, "Directory not found");
+    }
+
+    if recursively {
+        // delete recursively (bottom-up, continuing past failures)
+        let mut failures = Vec::new();
+        remove_tree(&caller, &path, &mut failures);
+        if failures.is_empty() {
+            return Ok(());
+        }
+        let count = failures.len();
+        failures.truncate(MAX_REPORTED_FAILURES);
+        let more = if count > MAX_REPORTED_FAILURES { format!(" and {} more", count - MAX_REPORTED_FAILURES) } else { String::new() };
+        error!(ERROR_UNKNOWN, format!("Couldn't delete: {}{}", failures.join(", "), more))
+    } else {
+        // delete only if empty
+        match fs::remove_dir(&path) {
+            Ok(_) => {
+                delete_file_info(&path);
+                Ok(())
+            },
+            Err(e) if e.kind() == ErrorKind::DirectoryNotEmpty => error!(ERROR_DIRECTORY_NOT_EMPTY, "Directory is not empty"),
+            Err(e) => error!(ERROR_UNKNOWN, format!("{:?}", e))
+        }
+    }
+}
+
+/// returns a file info
+///
+/// A caller with manage permission also gets the ACL of the path in `acl`.
+///
+/// `sha256` is always set for files written by save/touch/commitUpload, including empty files
+/// (the hash of zero bytes), so None means a directory or a legacy file without a hash.
+///
+/// Anonymous callers get info only for existing public (anonymous-readable) paths, and
+/// ERROR_PERMISSION_DENIED for everything else, so that they can't probe which paths exist.
+///
+/// # Arguments
+///
+/// * `path` - must start with ROOT and the parent directory must exist
+#[ic_cdk::query(name="getInfo")]
+pub fn get_info(path:String) -> Result<Info, Error> {
+    let path = validate_path(&path)?;
+
+    let caller = caller();
+    let info = match readable_file_info(&caller, &path, "File not found") {
+        Err(e) if e.code == ERROR_NOT_FOUND && caller == Principal::anonymous() => {
+            return error!(ERROR_PERMISSION_DENIED, "Permission denied");
+        },
+        result => result?
+    };
+    to_info(&caller, &path, info)
+}
+
+/// returns the entire stored metadata of a file or directory (manage permission required)
+///
+/// Includes what `getInfo` doesn't return, such as the default permissions and the signature.
+///
+/// # Arguments
+///
+/// * `path` - must start with ROOT
+#[ic_cdk::query(name="getRawInfo")]
+pub fn get_raw_info(path:String) -> Result<FileInfo, Error> {
+    let path = validate_path(&path)?;
+
+    let file_info = get_file_info(&path);
+    if !check_manage_permission(&caller(), &path, file_info.as_ref()) {
+        return error!(ERROR_PERMISSION_DENIED, "Permission denied");
+    }
+    match file_info {
+        Some(info) => Ok(info),
+        None => error!(ERROR_NOT_FOUND, "File not found")
+    }
+}
+
+/// converts a file info into Info for the caller
+fn to_info(caller:&Principal, path:&str, info:FileInfo) -> Result<Info, Error> {
+    let acl = check_manage_permission(caller, path, Some(&info)).then(|| Acl {
+        manageable: info.manageable.clone(),
+        readable: info.readable.clone(),
+        writable: info.writable.clone(),
+        denied_readable: info.denied_readable.clone(),
+        denied_writable: info.denied_writable.clone(),
+        listable: info.listable.clone(),
+    });
+    let is_dir = info.is_dir();
+    let child_count = if is_dir { child_paths(&path.to_string())?.len() as u64 } else { 0 };
+    Ok(Info {
+        size: info.size,
+        creator: info.creator,
+        created_at: info.created_at,
+        updater: info.updater,
+        updated_at: info.updated_at,
+        version: info.version,
+        mimetype: info.mimetype,
+        sha256: info.sha256,
+        sha256_hex: info.sha256.map(|sha256| to_hex(&sha256)),
+        pubkey: info.pubkey,
+        accessed_at: info.accessed_at,
+        child_count,
+        is_dir,
+        acl,
+    })
+}
+
+/// returns sha256 of a file in lowercase hex
+///
+/// Returns None if sha256 wasn't recorded (saved by older versions).
+///
+/// # Arguments
+///
+/// * `path` - must start with ROOT
+#[ic_cdk::query(name="sha256Hex")]
+pub fn sha256_hex(path:String) -> Result<Option<String>, Error> {
+    let path = validate_path(&path)?;
+
+    let info = readable_file_info(&caller(), &path, "File not found")?;
+    if info.is_dir() {
+        return error!(ERROR_IS_DIRECTORY, "Not a file");
+    }
+    Ok(info.sha256.map(|sha256| to_hex(&sha256)))
+}
+
+/// returns file infos of the specified paths
+///
+/// Each path is processed independently, so one bad path doesn't fail the others.
+///
+/// # Arguments
+///
+/// * `paths` - each must start with ROOT (up to MAX_BATCH_SIZE paths)
+#[ic_cdk::query(name="getInfos")]
+pub fn get_infos(paths:Vec<String>) -> Vec<Result<Info, Error>> {
+    paths.into_iter()
+        .enumerate()
+        .map(|(index, path)| {
+            if index < MAX_BATCH_SIZE {
+                get_info(path)
+            } else {
+                error!(ERROR_INVALID_SIZE, "Too many paths")
+            }
+        })
+        .collect()
+}
+
+/// attaches a detached signature to a file
+///
+/// The canister doesn't interpret the signature bytes. The signature is cleared
+/// when the content of the file is written again.
+///
+/// # Arguments
+///
+/// * `path` - must start with ROOT and the file must exist
+/// * `signature` - opaque signature bytes (up to MAX_SIGNATURE_SIZE bytes)
+#[ic_cdk::update(name="setSignature")]
+pub fn set_signature(path:String, signature:Vec<u8>) -> Result<(), Error> {
+    check_writable()?;
+    let path = validate_path(&path)?;
+    if signature.len() > MAX_SIGNATURE_SIZE {
+        return error!(ERROR_INVALID_SIZE, "Signature is too large");
+    }
+
+    let caller = caller();
+    let file_info = get_file_info(&path);
+    if !check_write_permission(&caller, &path, file_info.as_ref()) {
+        return error!(ERROR_PERMISSION_DENIED, "Permission denied");
+    }
+    check_lock(&caller, &path)?;
+
+    match file_info {
+        Some(info) if info.is_dir() => error!(ERROR_IS_DIRECTORY, "Directory can't be signed"),
+        Some(mut info) => {
+            info.signature = Some(signature);
+            info.pubkey = None;
+            set_file_info(&path, &info)
+        },
+        None => error!(ERROR_NOT_FOUND, "File not found")
+    }
+}
+
+/// attaches an ed25519 signature over sha256 of a file after verifying it
+///
+/// The public key is kept with the signature so that readers can re-verify it.
+///
+/// # Arguments
+///
+/// * `path` - must start with ROOT and the file must exist
+/// * `signature` - ed25519 signature (64 bytes) over the 32-byte sha256 of the file
+/// * `pubkey` - ed25519 public key (32 bytes)
+#[ic_cdk::update(name="setVerifiedSignature")]
+pub fn set_verified_signature(path:String, signature:Vec<u8>, pubkey:Vec<u8>) -> Result<(), Error> {
+    check_writable()?;
+    let path = validate_path(&path)?;
+
+    let caller = caller();
+    let file_info = get_file_info(&path);
+    if !check_write_permission(&caller, &path, file_info.as_ref()) {
+        return error!(ERROR_PERMISSION_DENIED, "Permission denied");
+    }
+    check_lock(&caller, &path)?;
+
+    match file_info {
+        Some(info) if info.is_dir() => error!(ERROR_IS_DIRECTORY, "Directory can't be signed"),
+        Some(mut info) => {
+            let Some(sha256) = info.sha256 else {
+                return error!(ERROR_INVALID_HASH, "sha256 is not available");
+            };
+            if !crate::ed25519::verify(&pubkey, &sha256, &signature) {
+                return error!(ERROR_INVALID_SIGNATURE, "Invalid signature");
+            }
+            info.signature = Some(signature);
+            info.pubkey = Some(pubkey);
+            set_file_info(&path, &info)
+        },
+        None => error!(ERROR_NOT_FOUND, "File not found")
+    }
+}
+
+/// returns the detached signature of a file (None if not signed)
+///
+/// # Arguments
+///
+/// * `path` - must start with ROOT and the file must exist
+#[ic_cdk::query(name="getSignature")]
+pub fn get_signature(path:String) -> Result<Option<Vec<u8>>, Error> {
+    let path = validate_path(&path)?;
+
+    let caller = caller();
+    let file_info = get_file_info(&path);
+    if !check_read_permission(&caller, &path, file_info.as_ref()) {
+        return error!(ERROR_PERMISSION_DENIED, "Permission denied");
+    }
+
+    match file_info {
+        Some(info) => Ok(info.signature),
+        None => error!(ERROR_NOT_FOUND, "File not found")
+    }
+}
+
+/// returns the Merkle root of a directory
+///
+/// Children are sorted by name. Each child is hashed as sha256(name || hash) where hash is
+/// sha256 of the file or the Merkle root of the subdirectory, and the child hashes are combined
+/// pairwise as sha256(left || right) (an odd one is carried up). An empty directory has sha256("").
+/// Children the caller can't read are left out, as in `treeHash`.
+/// Fails with ERROR_INVALID_SIZE if the subtree has more than MAX_TREE_HASH_SIZE entries.
+///
+/// # Arguments
+///
+/// * `path` - must start with ROOT and the directory must exist
+#[ic_cdk::query(name="directoryMerkleRoot")]
+pub fn directory_merkle_root(path:String) -> Result<[u8; 32], Error> {
+    let path = validate_path(&path)?;
+
+    let caller = caller();
+    let file_info = get_file_info(&path);
+    if !check_read_permission(&caller, &path, file_info.as_ref()) {
+        return error!(ERROR_PERMISSION_DENIED, "Permission denied");
+    }
+
+    let mut remaining = MAX_TREE_HASH_SIZE;
+    match file_info {
+        Some(info) if info.is_dir() => merkle_hash(&caller, &path, &info, &mut remaining),
+        Some(_) => error!(ERROR_NOT_A_DIRECTORY, "Not a directory"),
+        None => error!(ERROR_NOT_FOUND, "Directory not found")
+    }
+}
+
+/// returns a hash of the subtree of a directory to detect changes
+///
+/// Children are hashed in name order as the length of the name, the name, a marker of the type
+/// and sha256 of the file or the tree hash of the subdirectory. Unlike `directoryMerkleRoot`,
+/// an empty file and an empty directory of the same name hash differently. It has no proofs.
+/// Children the caller can't read are left out, so the hash covers only what the caller can see.
+/// Fails with ERROR_INVALID_SIZE if the subtree has more than MAX_TREE_HASH_SIZE entries.
+///
+/// # Arguments
+///
+/// * `path` - must start with ROOT and the directory must exist
+#[ic_cdk::query(name="treeHash")]
+pub fn tree_hash(path:String) -> Result<[u8; 32], Error> {
+    let path = validate_path(&path)?;
+
+    let caller = caller();
+    let file_info = get_file_info(&path);
+    if !check_read_permission(&caller, &path, file_info.as_ref()) {
+        return error!(ERROR_PERMISSION_DENIED, "Permission denied");
+    }
+
+    let mut remaining = MAX_TREE_HASH_SIZE;
+    match file_info {
+        Some(info) if info.is_dir() => subtree_hash(&caller, &path, &mut remaining),
+        Some(_) => error!(ERROR_NOT_A_DIRECTORY, "Not a directory"),
+        None => error!(ERROR_NOT_FOUND, "Directory not found")
+    }
+}
+
+/// returns the proof that a file is included in the Merkle root of a directory
+///
+/// Starting from sha256 of the file, apply each level in order:
+/// hash = sha256(name || hash), then hash = sha256(sibling || hash) or sha256(hash || sibling)
+/// for each sibling. The result equals `directoryMerkleRoot(dir)` of the caller.
+/// Fails with ERROR_INVALID_SIZE if more than MAX_TREE_HASH_SIZE entries are hashed.
+///
+/// # Arguments
+///
+/// * `dir` - must start with ROOT and the directory must exist
+/// * `path` - file under `dir` (read permission required)
+#[ic_cdk::query(name="merkleProof")]
+pub fn merkle_proof(dir:String, path:String) -> Result<Vec<MerkleProof>, Error> {
+    let dir = validate_path(&dir)?;
+    let path = validate_path(&path)?;
+    if !path.starts_with(&child_prefix(&dir)) {
+        return error!(ERROR_INVALID_PATH, "Not under the directory");
+    }
+
+    let caller = caller();
+    let dir_info = get_file_info(&dir);
+    if !check_read_permission(&caller, &dir, dir_info.as_ref()) {
+        return error!(ERROR_PERMISSION_DENIED, "Permission denied");
+    }
+    match get_file_info(&path) {
+        Some(info) if info.is_dir() => return error!(ERROR_IS_DIRECTORY, "Not a file"),
+        Some(info) if !check_read_permission(&caller, &path, Some(&info)) => return error!(ERROR_PERMISSION_DENIED, "Permission denied"),
+        Some(_) => {},
+        None => return error!(ERROR_NOT_FOUND, "File not found")
+    }
+
+    let mut remaining = MAX_TREE_HASH_SIZE;
+    let mut proof:Vec<MerkleProof> = Vec::new();
+    let mut current = path;
+    while current != dir {
+        let parent = parent_path(&current);
+        let children = readable_children(&caller, &parent)?;
+        // a directory on the way may be hidden from the caller
+        let Some(mut index) = children.iter().position(|(child, _)| *child == current) else {
+            return error!(ERROR_PERMISSION_DENIED, "Permission denied");
+        };
+        let mut hashes:Vec<[u8; 32]> = Vec::new();
+        for (child, info) in children.iter() {
+            hashes.push(merkle_child_hash(&caller, child, info, &mut remaining)?);
+        }
+
+        let mut siblings:Vec<MerkleSibling> = Vec::new();
+        while hashes.len() > 1 {
+            let sibling = index ^ 1;
+            if sibling < hashes.len() {
+                siblings.push(MerkleSibling { hash: hashes[sibling], is_left: sibling < index });
+            }
+            hashes = merkle_combine(&hashes);
+            index /= 2;
+        }
+        proof.push(MerkleProof { name: file_name(&current), siblings });
+        current = parent;
+    }
+    Ok(proof)
+}
+
+/// acquires (or refreshes) an advisory write lock of the path
+///
+/// While locked, `save`, uploads and `delete` by other principals fail with ERROR_LOCKED, and so
+/// does moving a directory holding the path.
+/// The lock expires automatically after the lock TTL.
+///
+/// # Arguments
+///
+/// * `path` - must start with ROOT
+#[ic_cdk::update]
+pub fn lock(path:String) -> Result<(), Error> {
+    check_writable()?;
+    let path = validate_path(&path)?;
+
+    let caller = caller();
+    let file_info = get_file_info(&path);
+    if !check_write_permission(&caller, &path, file_info.as_ref()) {
+        return error!(ERROR_PERMISSION_DENIED, "Permission denied");
+    }
+    check_lock(&caller, &path)?;
+
+    purge_expired_locks();
+    LOCKS.with(|locks| {
+        locks.borrow_mut().insert(path, Lock {
+            owner: caller,
+            locked_at: time(),
+        });
+    });
+    Ok(())
+}
+
+/// releases the advisory write lock of the path
+///
+/// # Arguments
+///
+/// * `path` - must start with ROOT (the lock holder or a manager can unlock)
+#[ic_cdk::update]
+pub fn unlock(path:String) -> Result<(), Error> {
+    check_writable()?;
+    let path = validate_path(&path)?;
+
+    let caller = caller();
+    match lock_holder(&path) {
+        Some(holder) => {
+            if holder != caller && !check_manage_permission(&caller, &path, get_file_info(&path).as_ref()) {
+                return error!(ERROR_LOCKED, "Locked by another principal");
+            }
+            LOCKS.with(|locks| locks.borrow_mut().remove(&path));
+            Ok(())
+        },
+        None => error!(ERROR_NOT_FOUND, "Lock not found")
+    }
+}
+
+/// returns the principal holding the advisory write lock of the path
+///
+/// # Arguments
+///
+/// * `path` - must start with ROOT
+#[ic_cdk::query(name="whoHoldsLock")]
+pub fn who_holds_lock(path:String) -> Result<Option<Principal>, Error> {
+    let path = validate_path(&path)?;
+
+    let caller = caller();
+    if !check_read_permission(&caller, &path, get_file_info(&path).as_ref()) {
+        return error!(ERROR_PERMISSION_DENIED, "Permission denied");
+    }
+    Ok(lock_holder(&path))
+}
+
+/// sets the TTL of advisory write locks
+///
+/// # Arguments
+///
+/// * `ttl` - milliseconds
+#[ic_cdk::update(name="setLockTtl")]
+pub fn set_lock_ttl(ttl:u64) -> Result<(), Error> {
+    check_writable()?;
+    if !is_admin(&caller()) {
+        return error!(ERROR_PERMISSION_DENIED, "Permission denied");
+    }
+
+    update_config(|config| config.lock_ttl = ttl)
+}
+
+/// switches read-only (maintenance) mode
+///
+/// While enabled, every update except `cancelUpload` fails with ERROR_READ_ONLY.
+/// Queries are unaffected. The mode is kept across upgrades.
+///
+/// # Arguments
+///
+/// * `read_only` - blocks all writes if true
+#[ic_cdk::update(name="setReadOnly")]
+pub fn set_read_only(read_only:bool) -> Result<(), Error> {
+    if !is_admin(&caller()) {
+        return error!(ERROR_PERMISSION_DENIED, "Permission denied");
+    }
+
+    update_config(|config| config.read_only = read_only)
+}
+
+/// returns whether the canister is in read-only mode
+#[ic_cdk::query(name="isReadOnly")]
+pub fn is_read_only() -> bool {
+    config().read_only
+}
+
+/// returns cycle balance and stable memory usage of the canister
+#[ic_cdk::query]
+pub fn health() -> Health {
+    let used = stable_size() * WASM_PAGE_SIZE;
+    Health {
+        cycles: cycle_balance(),
+        stable_memory_pages: stable_size(),
+        remaining_bytes: config().max_storage.saturating_sub(used),
+        min_cycles: config().min_cycles,
+    }
+}
+
+/// returns the constraints of the canister
+#[ic_cdk::query]
+pub fn limits() -> Limits {
+    Limits {
+        max_read_size: MAX_READ_SIZE as u64,
+        max_file_size: MAX_FILE_SIZE,
+        max_path: MAX_PATH as u64,
+        max_name: MAX_NAME as u64,
+        max_depth: MAX_DEPTH as u64,
+        chunk_size: CHUNK_SIZE,
+        upload_timeout_ms: UPLOAD_TIMEOUT,
+        max_batch_size: MAX_BATCH_SIZE as u64,
+        max_signature_size: MAX_SIGNATURE_SIZE as u64,
+        max_dir_entries: None,
+    }
+}
+
+/// sets the minimum cycle balance for writes
+///
+/// While the balance is below it, every update except `cancelUpload` and settings
+/// fails with ERROR_LOW_CYCLES, so writes don't fail halfway for lack of cycles.
+///
+/// # Arguments
+///
+/// * `min_cycles` - 0 disables the check
+#[ic_cdk::update(name="setMinCycles")]
+pub fn set_min_cycles(min_cycles:u64) -> Result<(), Error> {
+    if !is_admin(&caller()) {
+        return error!(ERROR_PERMISSION_DENIED, "Permission denied");
+    }
+
+    update_config(|config| config.min_cycles = min_cycles)
+}
+
+/// sets the bytes of stable memory available for storage
+///
+/// Writes which wouldn't fit (with a safety margin) fail with ERROR_OUT_OF_SPACE.
+///
+/// # Arguments
+///
+/// * `max_storage` - bytes (up to the stable memory limit of a canister)
+#[ic_cdk::update(name="setMaxStorage")]
+pub fn set_max_storage(max_storage:u64) -> Result<(), Error> {
+    if !is_admin(&caller()) {
+        return error!(ERROR_PERMISSION_DENIED, "Permission denied");
+    }
+
+    update_config(|config| config.max_storage = cmp::min(max_storage, MAX_STABLE_MEMORY))
+}
+
+/// sets the bytes a principal can own (admin only)
+///
+/// Files count against the quota of their creator. Writes which would exceed it fail with
+/// ERROR_QUOTA_EXCEEDED; files already saved are kept even if they exceed a lowered quota.
+///
+/// # Arguments
+///
+/// * `principal` - owner of the files
+/// * `quota` - bytes, or None for no limit
+#[ic_cdk::update(name="setQuota")]
+pub fn set_quota(principal:Principal, quota:Option<u64>) -> Result<(), Error> {
+    if !is_admin(&caller()) {
+        return error!(ERROR_PERMISSION_DENIED, "Permission denied");
+    }
+
+    QUOTAS.with(|quotas| {
+        let mut quotas = quotas.borrow_mut();
+        match quota {
+            Some(quota) => quotas.insert(principal.to_text(), quota),
+            None => quotas.remove(&principal.to_text())
+        }
+    });
+    Ok(())
+}
+
+/// returns the quota of a principal (admin only)
+///
+/// # Arguments
+///
+/// * `principal` - owner of the files
+#[ic_cdk::query(name="getQuota")]
+pub fn get_quota(principal:Principal) -> Result<Option<u64>, Error> {
+    if !is_admin(&caller()) {
+        return error!(ERROR_PERMISSION_DENIED, "Permission denied");
+    }
+    Ok(quota(&principal))
+}
+
+/// returns the bytes owned by the caller and the caller's quota
+///
+/// The quota is u64::MAX if none is set.
+#[ic_cdk::query(name="myQuota")]
+pub fn my_quota() -> (u64, u64) {
+    let caller = caller();
+    (usage(&caller), quota(&caller).unwrap_or(u64::MAX))
+}
+
+/// removes temp files left by failed writes (admin only)
+///
+/// Temp files younger than UPLOAD_TIMEOUT are kept so as not to race a write in progress.
+///
+/// # Arguments
+///
+/// * `root` - directory to clean with its descendants
+///
+/// Returns the number of removed files
+#[ic_cdk::update(name="cleanTempFiles")]
+pub fn clean_temp_files(root:String) -> Result<u64, Error> {
+    check_writable()?;
+    if !is_admin(&caller()) {
+        return error!(ERROR_PERMISSION_DENIED, "Permission denied");
+    }
+    let root = validate_path(&root)?;
+    match get_file_info(&root) {
+        Some(info) if info.is_dir() => Ok(remove_temp_files(&root, time().saturating_sub(UPLOAD_TIMEOUT))),
+        Some(_) => error!(ERROR_NOT_A_DIRECTORY, "Not a directory"),
+        None => error!(ERROR_NOT_FOUND, "Directory not found")
+    }
+}
+
+/// removes temp files modified before `expired_at` (milliseconds) under the directory
+fn remove_temp_files(dir:&str, expired_at:u64) -> u64 {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return 0;
+    };
+    let mut count = 0;
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let path = entry.path().to_string_lossy().into_owned();
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        if metadata.is_dir() {
+            if !name.starts_with(INTERNAL_PREFIX) {
+                count += remove_temp_files(&path, expired_at);
+            }
+        } else if name.starts_with(TEMP_PREFIX) {
+            let modified_at = metadata.modified().ok()
+                .and_then(|modified| modified.duration_since(std::time::UNIX_EPOCH).ok())
+                .map_or(0, |duration| duration.as_millis() as u64);
+            if modified_at < expired_at && remove_file(&path).is_ok() {
+                count += 1;
+            }
+        }
+    }
+    count
+}
+
+/// sets the bytes of the files allowed under a directory
+///
+/// Writes which would exceed the quota of any ancestor directory fail with ERROR_QUOTA_EXCEEDED.
+///
+/// # Arguments
+///
+/// * `path` - directory (manage permission required)
+/// * `quota` - bytes, or None for no limit
+#[ic_cdk::update(name="setDirectoryQuota")]
+pub fn set_directory_quota(path:String, quota:Option<u64>) -> Result<(), Error> {
+    check_writable()?;
+    let path = validate_path(&path)?;
+
+    let file_info = get_file_info(&path);
+    if !check_manage_permission(&caller(), &path, file_info.as_ref()) {
+        return error!(ERROR_PERMISSION_DENIED, "Permission denied");
+    }
+    match file_info {
+        Some(mut info) if info.is_dir() => {
+            info.quota = quota;
+            set_file_info(&path, &info)
+        },
+        Some(_) => error!(ERROR_NOT_A_DIRECTORY, "Not a directory"),
+        None => error!(ERROR_NOT_FOUND, "Directory not found")
+    }
+}
+
+/// returns the bytes of the files under a directory with a quota, and the quota
+///
+/// # Arguments
+///
+/// * `path` - directory (read permission required)
+#[ic_cdk::query(name="getDirectoryQuota")]
+pub fn get_directory_quota(path:String) -> Result<(u64, Option<u64>), Error> {
+    let path = validate_path(&path)?;
+    let info = readable_file_info(&caller(), &path, "Directory not found")?;
+    if !info.is_dir() {
+        return error!(ERROR_NOT_A_DIRECTORY, "Not a directory");
+    }
+    match info.quota {
+        Some(quota) => Ok((subtree_size(&path), Some(quota))),
+        None => Ok((sum_subtree_size(&path), None))
+    }
+}
+
+/// switches content-addressed mode
+///
+/// While enabled, new content is stored once per sha256 as a shared blob and each path
+/// references it, so identical content saved to several paths takes the space only once.
+/// Files saved before keep their own copy. The mode is kept across upgrades.
+///
+/// # Arguments
+///
+/// * `enabled` - deduplicates new content if true
+#[ic_cdk::update(name="setContentAddressed")]
+pub fn set_content_addressed(enabled:bool) -> Result<(), Error> {
+    check_writable()?;
+    if !is_admin(&caller()) {
+        return error!(ERROR_PERMISSION_DENIED, "Permission denied");
+    }
+
+    update_config(|config| config.content_addressed = enabled)
+}
+
+/// returns whether the canister is in content-addressed mode
+#[ic_cdk::query(name="isContentAddressed")]
+pub fn is_content_addressed() -> bool {
+    config().content_addressed
+}
+
+/// sets the directories whose `updated_at` is bumped when a child changes (admin only)
+///
+/// `save`, `commitUpload`, `delete` and `createDirectory` bump them, so that `updated_at`
+/// of a directory can be polled to detect changes. Off by default, as it costs a metadata
+/// write per directory and operation.
+///
+/// # Arguments
+///
+/// * `mode` - Off, Parent (the immediate parent) or Ancestors (every directory up to ROOT)
+#[ic_cdk::update(name="setParentUpdate")]
+pub fn set_parent_update(mode:ParentUpdate) -> Result<(), Error> {
+    check_writable()?;
+    if !is_admin(&caller()) {
+        return error!(ERROR_PERMISSION_DENIED, "Permission denied");
+    }
+
+    update_config(|config| config.parent_update = mode)
+}
+
+/// returns the directories whose `updated_at` is bumped when a child changes
+#[ic_cdk::query(name="getParentUpdate")]
+pub fn get_parent_update() -> ParentUpdate {
+    config().parent_update
+}
+
+/// turns case-insensitive path mode on/off (admin only)
+///
+/// In case-insensitive mode, paths are lowercased into the keys of the filesystem and metadata,
+/// and the original casing of the name is kept for `listFiles`.
+/// Can be changed only while ROOT is empty, so that existing keys stay consistent.
+///
+/// # Arguments
+///
+/// * `enabled` - case-folds paths if true
+#[ic_cdk::update(name="setCaseInsensitive")]
+pub fn set_case_insensitive(enabled:bool) -> Result<(), Error> {
+    check_writable()?;
+    if !is_admin(&caller()) {
+        return error!(ERROR_PERMISSION_DENIED, "Permission denied");
+    }
+    if enabled != config().case_insensitive && !child_paths(&root())?.is_empty() {
+        return error!(ERROR_ALREADY_EXISTS, "Storage is not empty");
+    }
+
+    update_config(|config| config.case_insensitive = enabled)
+}
+
+/// sets the origins allowed to fetch over HTTP (admin only)
+///
+/// # Arguments
+///
+/// * `origins` - e.g. "https://example.com"; "*" allows any origin, empty allows same-origin only
+#[ic_cdk::update(name="setCorsOrigins")]
+pub fn set_cors_origins(origins:Vec<String>) -> Result<(), Error> {
+    check_writable()?;
+    if !is_admin(&caller()) {
+        return error!(ERROR_PERMISSION_DENIED, "Permission denied");
+    }
+
+    update_config(|config| config.cors_origins = origins)
+}
+
+/// sets the mimetypes allowed/refused to save (admin only)
+///
+/// Rules are exact mimetypes or prefixes like "image/*", compared case-insensitively
+/// without parameters (e.g. "; charset=utf-8"). Files already saved are not affected.
+///
+/// # Arguments
+///
+/// * `policy` - empty `allow` allows any mimetype not in `deny`
+#[ic_cdk::update(name="setMimetypePolicy")]
+pub fn set_mimetype_policy(policy:MimetypePolicy) -> Result<(), Error> {
+    check_writable()?;
+    if !is_admin(&caller()) {
+        return error!(ERROR_PERMISSION_DENIED, "Permission denied");
+    }
+
+    update_config(|config| {
+        config.mimetype_allow = policy.allow;
+        config.mimetype_deny = policy.deny;
+    })
+}
+
+/// returns the mimetypes allowed/refused to save
+#[ic_cdk::query(name="getMimetypePolicy")]
+pub fn get_mimetype_policy() -> MimetypePolicy {
+    let config = config();
+    MimetypePolicy {
+        allow: config.mimetype_allow,
+        deny: config.mimetype_deny,
+    }
+}
+
+/// returns the origins allowed to fetch over HTTP
+#[ic_cdk::query(name="getCorsOrigins")]
+pub fn get_cors_origins() -> Vec<String> {
+    config().cors_origins
+}
+
+/// returns whether the canister is in case-insensitive path mode
+#[ic_cdk::query(name="isCaseInsensitive")]
+pub fn is_case_insensitive() -> bool {
+    config().case_insensitive
+}
+
+/// initilizes canistorage
+///
+/// # Arguments
+///
+/// * `owner` - principal to own ROOT (manage, read, write); the caller if not specified
+/// * `admins` - principals to manage ROOT in addition to the owner
+/// * `root` - directory to mount as ROOT (must be ROOT or under it); ROOT if not specified
+#[ic_cdk::update(name="initCanistorage")]
+pub fn init_canistorage(owner:Option<Principal>, admins:Vec<Principal>, root:Option<String>) -> Result<(), Error> {
+    check_writable()?;
+    let file_info = get_file_info(&self::root());
+    match file_info {
+        Some(_info) => {
+            error!(ERROR_ALREADY_INITIALIZED, "Already initialized")
+        },
+        None => {
+            let caller = caller();
+            let owner = owner.unwrap_or(caller);
+            if caller == Principal::anonymous() || owner == Principal::anonymous() || admins.contains(&Principal::anonymous()) {
+                return error!(ERROR_PERMISSION_DENIED, "Anonymous is not allowed");
+            }
+            let now = time();
+
+            let mut manageable = admins;
+            manageable.push(owner);
+            manageable.sort();
+            manageable.dedup();
+            let root = match root {
+                Some(root) => mount_root(&root)?,
+                None => self::root()
+            };
+            set_file_info(&root, &FileInfo {
+                manageable,
+                readable: vec![owner],
+                writable: vec![owner],
+                ..FileInfo::new(owner, now, MIMETYPE_DIRECTORY.to_string())
+            })
+        }
+    }
+}
+
+/// returns whether the canister has been initialized by initCanistorage
+#[ic_cdk::query(name="isInitialized")]
+pub fn is_initialized() -> bool {
+    get_file_info(&root()).is_some()
+}
+
+/// deletes all files/directories, metadata, upload sessions and locks (admin only)
+///
+/// ROOT is re-initialized and owned by the caller. Settings are kept.
+///
+/// # Arguments
+///
+/// * `confirm` - must be the canister id (text), to prevent resetting by mistake
+#[ic_cdk::update]
+pub fn reset(confirm:String) -> Result<(), Error> {
+    check_writable()?;
+    let caller = caller();
+    if !is_admin(&caller) {
+        return error!(ERROR_PERMISSION_DENIED, "Permission denied");
+    }
+    if confirm != canister_id().to_text() {
+        return error!(ERROR_PERMISSION_DENIED, "Confirmation doesn't match the canister id");
+    }
+
+    clear_storage()?;
+    set_file_info(&root(), &FileInfo {
+        manageable: vec![caller],
+        readable: vec![caller],
+        writable: vec![caller],
+        ..FileInfo::new(caller, time(), MIMETYPE_DIRECTORY.to_string())
+    })
+}
+
+/////////////////////////////////////////////////////////////////////////////
+// Internal functions
+/////////////////////////////////////////////////////////////////////////////
+
+/// validates the directory to mount as ROOT, creates it and keeps it in the config
+fn mount_root(root:&str) -> Result<String, Error> {
+    let root = match root.trim_end_matches('/') {
+        "" => "/",
+        root => root
+    };
+    let parent = ROOT.trim_end_matches('/');
+    if root != ROOT && !root.starts_with(&format!("{}/", parent)) {
+        return error!(ERROR_INVALID_PATH, "Root must start with /");
+    }
+    if root != ROOT {
+        let relative = &root[parent.len() + 1..];
+        if relative.split('/').any(|name| name.is_empty() || name == "." || name == ".." || name.starts_with(INTERNAL_PREFIX))
+            || root.chars().any(|c| c.is_control()) || root.len() > MAX_PATH {
+            return error!(ERROR_INVALID_PATH, "Invalid root");
+        }
+    }
+    if let Err(e) = fs::create_dir_all(root) {
+        return error!(ERROR_UNKNOWN, format!("{:?}", e));
+    }
+    let root = root.to_string();
+    update_config(|config| config.root = Some(root.clone()))?;
+    Ok(root)
+}
+
+/// deletes all the entries under ROOT and clears metadata, upload sessions, locks and blobs
+fn clear_storage() -> Result<(), Error> {
+    let entries = match fs::read_dir(root()) {
+        Ok(entries) => entries,
+        Err(e) => return error!(ERROR_UNKNOWN, format!("{:?}", e))
+    };
+    for entry in entries {
+        let result = entry.and_then(|entry| {
+            if entry.file_type()?.is_dir() {
+                fs::remove_dir_all(entry.path())
+            } else {
+                fs::remove_file(entry.path())
+            }
+        });
+        if let Err(e) = result {
+            return error!(ERROR_UNKNOWN, format!("{:?}", e));
+        }
+    }
+    UPLOADING.with(|uploading| uploading.borrow_mut().clear());
+    LOCKS.with(|locks| locks.borrow_mut().clear_new());
+    BLOBS.with(|blobs| blobs.borrow_mut().clear_new());
+    FILE_INFOS.with(|infos| infos.borrow_mut().clear_new());
+    USAGE.with(|usage| usage.borrow_mut().clear_new());
+    SUBTREE_SIZES.with(|sizes| sizes.borrow_mut().clear_new());
+    Ok(())
+}
+
+/// Returns whether the specified path is manageable or not
+///
+/// # Arguments
+///
+/// * `principal` - Principal to check
+/// * `path` - must start with ROOT
+/// * `file_info` - FileInfo
+fn check_manage_permission(principal:&Principal, path:&str, file_info:Option<&FileInfo>) -> bool {
+    inherited_permission(path, file_info, |info| {
+        // manageable of file_info
+        info.manageable.contains(principal).then_some(true)
+    })
+}
+
+/// Returns whether the specified path is readable or not
+///
+/// # Arguments
+///
+/// * `principal` - Principal to check
+/// * `path` - must start with ROOT
+/// * `file_info` - FileInfo
+fn check_read_permission(principal:&Principal, path:&str, file_info:Option<&FileInfo>) -> bool {
+    inherited_permission(path, file_info, |info| {
+        if info.denied_readable.contains(principal) {
+            Some(false) // explicit deny beats allow
+        } else if info.readable.contains(principal) {
+            Some(true)
+        } else {
+            None
+        }
+    })
+}
+
+/// Returns file info of the specified path if it is readable
+///
+/// Read permission is checked before existence, and a missing path inherits the permission
+/// of its parent. So a caller without read permission gets ERROR_PERMISSION_DENIED whether
+/// the path exists or not, and only an authorized caller can see ERROR_NOT_FOUND.
+///
+/// # Arguments
+///
+/// * `principal` - Principal to check
+/// * `path` - must start with ROOT
+/// * `not_found` - error message if the path doesn't exist
+fn readable_file_info(principal:&Principal, path:&str, not_found:&str) -> Result<FileInfo, Error> {
+    let file_info = get_file_info(&path.to_string());
+    if !check_read_permission(principal, path, file_info.as_ref()) {
+        return error!(ERROR_PERMISSION_DENIED, "Permission denied");
+    }
+    match file_info {
+        Some(info) => Ok(info),
+        None => error!(ERROR_NOT_FOUND, not_found)
+    }
+}
+
+/// Returns whether the children of the specified path can be listed or not
+///
+/// Read permission includes list permission, so `listable` only needs to name the principals
+/// who can list the names without reading the contents.
+///
+/// # Arguments
+///
+/// * `principal` - Principal to check
+/// * `path` - must start with ROOT
+/// * `file_info` - FileInfo
+fn check_list_permission(principal:&Principal, path:&str, file_info:Option<&FileInfo>) -> bool {
+    inherited_permission(path, file_info, |info| {
+        if info.denied_readable.contains(principal) {
+            Some(false) // explicit deny beats allow
+        } else if info.listable.contains(principal) || info.readable.contains(principal) {
+            Some(true)
+        } else {
+            None
+        }
+    })
+}
+
+/// Returns file info of the specified path if its children can be listed
+///
+/// Existence is checked after the permission as `readable_file_info`.
+///
+/// # Arguments
+///
+/// * `principal` - Principal to check
+/// * `path` - must start with ROOT
+/// * `not_found` - error message if the path doesn't exist
+fn listable_file_info(principal:&Principal, path:&str, not_found:&str) -> Result<FileInfo, Error> {
+    let file_info = get_file_info(&path.to_string());
+    if !check_list_permission(principal, path, file_info.as_ref()) {
+        return error!(ERROR_PERMISSION_DENIED, "Permission denied");
+    }
+    match file_info {
+        Some(info) => Ok(info),
+        None => error!(ERROR_NOT_FOUND, not_found)
+    }
+}
+
+/// Returns whether the specified path is writable or not
+///
+/// # Arguments
+///
+/// * `principal` - Principal to check
+/// * `path` - must start with ROOT
+/// * `file_info` - FileInfo
+fn check_write_permission(principal:&Principal, path:&str, file_info:Option<&FileInfo>) -> bool {
+    inherited_permission(path, file_info, |info| {
+        if info.denied_writable.contains(principal) {
+            Some(false) // explicit deny beats allow
+        } else if info.writable.contains(principal) {
+            Some(true)
+        } else {
+            None
+        }
+    })
+}
+
+/// Returns the first decision found from the path up to ROOT (false if none)
+///
+/// The walk is iterative and bounded by MAX_DEPTH (validate_path rejects deeper paths),
+/// so a deep path can't exhaust the stack or the instruction limit.
+///
+/// # Arguments
+///
+/// * `path` - must start with ROOT
+/// * `file_info` - FileInfo of the path
+/// * `decide` - returns Some(allowed) if the file info decides the permission
+fn inherited_permission<F:Fn(&FileInfo) -> Option<bool>>(path:&str, file_info:Option<&FileInfo>, decide:F) -> bool {
+    if let Some(decision) = file_info.and_then(&decide) {
+        return decision;
+    }
+    let root = root();
+    let mut current = path.to_string();
+    for _ in 0..=MAX_DEPTH + 1 { // "/name" reaches "/" through "" in production
+        if current == root {
+            return false;
+        }
+        current = permission_parent_path(&current);
+        if let Some(decision) = get_file_info(&current).as_ref().and_then(&decide) {
+            return decision;
+        }
+    }
+    false
+}
+
+/// Returns the parent path consulted when checking permissions
+///
+/// # Arguments
+///
+/// * `path` - must start with ROOT and must not be ROOT
+fn permission_parent_path(path:&str) -> String {
+    match path.rfind("/") {
+        Some(index) => {
+            path[0..index].to_string()
+        },
+        None => {
+            // Special case: "" -> "/""
+            "/".to_string()
+        }
+    }
+}
+
+/// Returns whether the principal is an administrator (manager of ROOT)
+///
+/// # Arguments
+///
+/// * `principal` - Principal to check
+fn is_admin(principal:&Principal) -> bool {
+    let root = root();
+    check_manage_permission(principal, &root, get_file_info(&root).as_ref())
+}
+
+/// returns an error if the canister is in read-only mode
+fn check_writable() -> Result<(), Error> {
+    let config = config();
+    if config.read_only {
+        return error!(ERROR_READ_ONLY, "Read-only mode");
+    }
+    if cycle_balance() < config.min_cycles as u128 {
+        return error!(ERROR_LOW_CYCLES, "Cycle balance is too low");
+    }
+    Ok(())
+}
+
+/// returns whether an ancestor directory has access tracking enabled
+fn is_access_tracked(path:&str) -> bool {
+    let root = root();
+    let mut current = path.to_string();
+    while current != root {
+        current = permission_parent_path(&current);
+        if current.is_empty() {
+            break;
+        }
+        if get_file_info(&current).is_some_and(|info| info.track_access) {
+            return true;
+        }
+    }
+    false
+}
+
+/// returns an error if `size` bytes wouldn't fit in the remaining storage
+fn check_space(size:u64) -> Result<(), Error> {
+    let used = stable_size() * WASM_PAGE_SIZE;
+    let remaining = config().max_storage.saturating_sub(used);
+    if size.saturating_add(STORAGE_MARGIN) > remaining {
+        return error!(ERROR_OUT_OF_SPACE, "Not enough storage");
+    }
+    Ok(())
+}
+
+/// returns an error if writing `new_size` bytes to the file exceeds a quota
+///
+/// The file is charged to its creator (the caller for a new file) and to every ancestor
+/// directory with a quota. The current content of the file is replaced, so it isn't counted.
+fn check_quotas(caller:&Principal, path:&str, file_info:Option<&FileInfo>, new_size:u64) -> Result<(), Error> {
+    let (owner, old_size) = match file_info {
+        Some(info) => (info.creator, info.size),
+        None => (*caller, 0)
+    };
+    if quota(&owner).is_some_and(|quota| usage(&owner).saturating_sub(old_size).saturating_add(new_size) > quota) {
+        return error!(ERROR_QUOTA_EXCEEDED, "Quota exceeded");
+    }
+    for ancestor in ancestor_paths(path) {
+        let Some(quota) = get_file_info(&ancestor).and_then(|info| info.quota) else {
+            continue;
+        };
+        if subtree_size(&ancestor).saturating_sub(old_size).saturating_add(new_size) > quota {
+            return error!(ERROR_QUOTA_EXCEEDED, format!("Quota of {} exceeded", ancestor));
+        }
+    }
+    Ok(())
+}
+
+/// returns an error if moving `moved_size` bytes from `from` to `to` exceeds a directory quota
+///
+/// Only the directories which the content enters are checked: the creators don't change and
+/// the common ancestors keep their size. `replaced_size` bytes leave `to` at the same time (swap).
+fn check_move_quotas(from:&str, to:&str, moved_size:u64, replaced_size:u64) -> Result<(), Error> {
+    let from_ancestors = ancestor_paths(from);
+    for ancestor in ancestor_paths(to).into_iter().filter(|ancestor| !from_ancestors.contains(ancestor)) {
+        let Some(quota) = get_file_info(&ancestor).and_then(|info| info.quota) else {
+            continue;
+        };
+        if subtree_size(&ancestor).saturating_sub(replaced_size).saturating_add(moved_size) > quota {
+            return error!(ERROR_QUOTA_EXCEEDED, format!("Quota of {} exceeded", ancestor));
+        }
+    }
+    Ok(())
+}
+
+/// returns the bytes of the file or the files under the directory
+fn path_size(path:&str, file_info:Option<&FileInfo>) -> u64 {
+    match file_info {
+        Some(info) if info.is_dir() => sum_subtree_size(&path.to_string()),
+        Some(info) => info.size,
+        None => 0
+    }
+}
+
+/// bumps `updated_at` of the parent directory (or every ancestor) if enabled by setParentUpdate
+fn touch_parents(path:&str, now:u64) -> Result<(), Error> {
+    let ancestors = match config().parent_update {
+        ParentUpdate::Off => return Ok(()),
+        ParentUpdate::Parent => vec![parent_path(&path.to_string())],
+        ParentUpdate::Ancestors => ancestor_paths(path),
+    };
+    for ancestor in ancestors {
+        if let Some(mut info) = get_file_info(&ancestor).filter(|info| info.is_dir()) {
+            info.updated_at = now;
+            set_file_info(&ancestor, &info)?;
+        }
+    }
+    Ok(())
+}
+
+/// returns the ancestors of the path up to ROOT (nearest first)
+fn ancestor_paths(path:&str) -> Vec<String> {
+    let root = root();
+    let mut ancestors = Vec::new();
+    let mut current = path.to_string();
+    while current != root && ancestors.len() <= MAX_DEPTH + 1 {
+        current = permission_parent_path(&current);
+        ancestors.push(current.clone());
+    }
+    ancestors
+}
+
+/// returns the cached bytes of the files under the directory with a quota
+fn subtree_size(path:&str) -> u64 {
+    SUBTREE_SIZES.with(|sizes| sizes.borrow().get(&path.to_string()).unwrap_or(0))
+}
+
+/// sums the bytes of the files under the directory
+fn sum_subtree_size(path:&String) -> u64 {
+    descendant_file_info_paths(path).iter()
+        .filter_map(get_file_info)
+        .filter(|info| !info.is_dir())
+        .map(|info| info.size)
+        .sum()
+}
+
+/// updates the cached subtree sizes when file info of the path is replaced
+fn update_subtree_sizes(path:&str, old:Option<&FileInfo>, new:Option<&FileInfo>) {
+    // the directory itself starts (or stops) caching the size of its subtree
+    let key = path.to_string();
+    if new.is_some_and(|info| info.quota.is_some()) {
+        if SUBTREE_SIZES.with(|sizes| !sizes.borrow().contains_key(&key)) {
+            let size = sum_subtree_size(&key);
+            SUBTREE_SIZES.with(|sizes| sizes.borrow_mut().insert(key, size));
+        }
+    } else {
+        SUBTREE_SIZES.with(|sizes| sizes.borrow_mut().remove(&key));
+    }
+
+    let file_size = |info:Option<&FileInfo>| info.filter(|info| !info.is_dir()).map_or(0, |info| info.size);
+    let (old_size, new_size) = (file_size(old), file_size(new));
+    if old_size == new_size {
+        return;
+    }
+    SUBTREE_SIZES.with(|sizes| {
+        let mut sizes = sizes.borrow_mut();
+        for ancestor in ancestor_paths(path) {
+            if let Some(size) = sizes.get(&ancestor) {
+                sizes.insert(ancestor, size.saturating_sub(old_size).saturating_add(new_size));
+            }
+        }
+    })
+}
+
+fn quota(principal:&Principal) -> Option<u64> {
+    QUOTAS.with(|quotas| quotas.borrow().get(&principal.to_text()))
+}
+
+fn usage(principal:&Principal) -> u64 {
+    USAGE.with(|usage| usage.borrow().get(&principal.to_text()).unwrap_or(0))
+}
+
+/// updates the usage of the creator when file info is replaced
+fn update_usage(old:Option<&FileInfo>, new:Option<&FileInfo>) {
+    USAGE.with(|usage| {
+        let mut usage = usage.borrow_mut();
+        for (info, added) in [(old, false), (new, true)] {
+            let Some(info) = info.filter(|info| !info.is_dir() && info.size > 0) else {
+                continue;
+            };
+            let key = info.creator.to_text();
+            let bytes = usage.get(&key).unwrap_or(0);
+            let bytes = if added { bytes.saturating_add(info.size) } else { bytes.saturating_sub(info.size) };
+            if bytes == 0 {
+                usage.remove(&key);
+            } else {
+                usage.insert(key, bytes);
+            }
+        }
+    })
+}
+
+/// builds the usage from the metadata unless already built
+///
+/// Called on upgrade. The usage is kept in stable memory and updated along with the metadata,
+/// so only the first upgrade after the files were stored without it walks FILE_INFOS.
+pub fn build_usage() {
+    if config().usage_built {
+        return;
+    }
+    rebuild_usage();
+    let _ = update_config(|config| config.usage_built = true);
+}
+
+/// recalculates the usage of every principal and directory with a quota from the metadata
+fn rebuild_usage() {
+    USAGE.with(|usage| usage.borrow_mut().clear_new());
+    SUBTREE_SIZES.with(|sizes| sizes.borrow_mut().clear_new());
+    let infos:Vec<(String, FileInfo)> = FILE_INFOS.with(|infos| infos.borrow().iter().collect());
+    for (path, info) in infos {
+        update_usage(None, Some(&info));
+        if info.quota.is_some() {
+            let size = sum_subtree_size(&path);
+            SUBTREE_SIZES.with(|sizes| sizes.borrow_mut().insert(path, size));
+        }
+    }
+}
+
+/// returns whether an unexpired upload session exists for the path (an expired one is evicted)
+fn is_uploading(path:&String) -> bool {
+    UPLOADING.with(|uploading| {
+        let mut map = uploading.borrow_mut();
+        match map.get(path) {
+            Some(value) if (value.updated_at + UPLOAD_TIMEOUT) < time() => {
+                map.remove(path);
+                false
+            },
+            Some(_) => true,
+            None => false
+        }
+    })
+}
+
+/// returns an error if an unexpired upload session exists for the path or its descendants
+fn check_not_uploading(path:&String) -> Result<(), Error> {
+    let prefix = format!("{}/", path);
+    let now = time();
+    let uploading = UPLOADING.with(|uploading| {
+        uploading.borrow().iter().any(|(key, value)| {
+            (key == path || key.starts_with(&prefix)) && (value.updated_at + UPLOAD_TIMEOUT) >= now
+        })
+    });
+    if uploading {
+        return error!(ERROR_ALREADY_EXISTS, "Upload in progress");
+    }
+    Ok(())
+}
+
+/// returns the number of upload sessions in progress (not expired)
+pub fn active_upload_count() -> usize {
+    let now = time();
+    UPLOADING.with(|uploading| {
+        uploading.borrow()
+            .values()
+            .filter(|value| now <= value.updated_at + UPLOAD_TIMEOUT)
+            .count()
+    })
+}
+
+/// returns the declared chunks (offset -> size) of the offsets
+fn chunk_layout(expected_size:u64, chunk_offsets:&[u64]) -> Result<BTreeMap<u64, u64>, Error> {
+    if chunk_offsets.first().is_some_and(|offset| *offset != 0) || (chunk_offsets.is_empty() && expected_size > 0) {
+        return error!(ERROR_INVALID_SEQUENCE, "Chunks must start at 0");
+    }
+    let mut layout = BTreeMap::new();
+    for (index, offset) in chunk_offsets.iter().enumerate() {
+        let end = chunk_offsets.get(index + 1).copied().unwrap_or(expected_size);
+        if end <= *offset {
+            return error!(ERROR_INVALID_SEQUENCE, format!("Chunk offsets must be ascending within the expected size: {}", offset));
+        } else if end - offset > CHUNK_SIZE {
+            return error!(ERROR_INVALID_SIZE, format!("Chunk is too large: {}", offset));
+        }
+        layout.insert(*offset, end - offset);
+    }
+    Ok(layout)
+}
+
+/// returns an error unless the chunks cover `size` bytes from 0 without gaps or overlaps
+///
+/// A gap fails with ERROR_INVALID_SEQUENCE naming the offset of the missing chunk,
+/// overlapping chunks or chunks beyond `size` fail with ERROR_INVALID_SIZE.
+fn check_chunks(chunk:&BTreeMap<u64, Vec<u8>>, size:u64) -> Result<(), Error> {
+    let mut offset:u64 = 0;
+    for (start, data) in chunk.iter().filter(|(_, data)| !data.is_empty()) {
+        match (*start).cmp(&offset) {
+            Ordering::Greater => return error!(ERROR_INVALID_SEQUENCE, format!("Missing chunk at {}", offset)),
+            Ordering::Less => return error!(ERROR_INVALID_SIZE, format!("Overlapping chunks at {}", start)),
+            Ordering::Equal => {}
+        }
+        offset += data.len() as u64;
+    }
+    match offset.cmp(&size) {
+        Ordering::Less => error!(ERROR_INVALID_SEQUENCE, format!("Missing chunk at {}", offset)),
+        Ordering::Greater => error!(ERROR_INVALID_SIZE, "Chunks exceed the size"),
+        Ordering::Equal => Ok(())
+    }
+}
+
+/// returns an error unless the chunk is declared and not received yet (if the layout is declared)
+fn check_declared_chunk(value:&Uploading, start:u64, size:u64) -> Result<(), Error> {
+    let Some(layout) = value.layout.as_ref() else {
+        return Ok(());
+    };
+    match layout.get(&start) {
+        None => error!(ERROR_INVALID_SEQUENCE, format!("Undeclared chunk: {}", start)),
+        Some(declared) if *declared != size => error!(ERROR_INVALID_SIZE, format!("Chunk size differs from the declared size: {}", start)),
+        Some(_) if value.chunk.contains_key(&start) => error!(ERROR_INVALID_SEQUENCE, format!("Chunk already received: {}", start)),
+        Some(_) => Ok(())
+    }
+}
+
+/// returns the error for a missing upload session or one owned by another principal
+///
+/// Both cases must be indistinguishable, so that callers can't probe uploads of others.
+fn no_upload_session<T>() -> Result<T, Error> {
+    error!(ERROR_INVALID_SEQUENCE, "Invalid sequence")
+}
+
+/// returns the principal holding the unexpired lock of the path
+fn lock_holder(path:&String) -> Option<Principal> {
+    let lock = LOCKS.with(|locks| locks.borrow().get(path))?;
+    if is_expired(&lock) {
+        None
+    } else {
+        Some(lock.owner)
+    }
+}
+
+fn is_expired(lock:&Lock) -> bool {
+    lock.locked_at.saturating_add(config().lock_ttl) < time()
+}
+
+/// returns an error if the path is locked by another principal (an expired lock is removed)
+fn check_lock(principal:&Principal, path:&String) -> Result<(), Error> {
+    match LOCKS.with(|locks| locks.borrow().get(path)) {
+        Some(lock) if is_expired(&lock) => {
+            LOCKS.with(|locks| locks.borrow_mut().remove(path));
+            Ok(())
+        },
+        Some(lock) if lock.owner != *principal => error!(ERROR_LOCKED, "Locked by another principal"),
+        _ => Ok(())
+    }
+}
+
+/// returns an error if a descendant of the path is locked by another principal
+fn check_descendant_locks(principal:&Principal, path:&str) -> Result<(), Error> {
+    let prefix = child_prefix(path);
+    let locked = LOCKS.with(|locks| {
+        locks.borrow().range(prefix.clone()..)
+            .take_while(|(key, _)| key.starts_with(&prefix))
+            .any(|(_, lock)| lock.owner != *principal && !is_expired(&lock))
+    });
+    if locked {
+        return error!(ERROR_LOCKED, "Locked by another principal");
+    }
+    Ok(())
+}
+
+/// removes the expired locks, so abandoned ones don't pile up in stable memory
+fn purge_expired_locks() {
+    let expired:Vec<String> = LOCKS.with(|locks| {
+        locks.borrow().iter()
+            .filter(|(_, lock)| is_expired(lock))
+            .map(|(path, _)| path)
+            .collect()
+    });
+    LOCKS.with(|locks| {
+        let mut locks = locks.borrow_mut();
+        for path in expired {
+            locks.remove(&path);
+        }
+    });
+}
+
+/// validates the specified path
+///
+/// Returns the storage key of the path (lowercased in case-insensitive mode).
+///
+/// # Arguments
+///
+/// * `path` - path to check (relative to ROOT, or starting with ROOT)
+/// 
+fn validate_path(path:&str) -> Result<String, Error> {
+    if path.is_empty() {
+        return error!(ERROR_INVALID_PATH, "Path is empty");
+    }
+    let path = &canonical_path(path);
+
+    // length
+    let length = path.len();
+    if length > MAX_PATH {
+        return error!(ERROR_INVALID_PATH, "Path is too long");
+    }
+
+    // ends with '/' (except root)
+    if length > 1 && path.ends_with('/') {
+        return error!(ERROR_INVALID_PATH, "Ends with path separator (/)");
+    }
+    
+    // reserved for metadata and temp files
+    if path.split('/').any(|name| name.starts_with(INTERNAL_PREFIX)) {
+        return error!(ERROR_INVALID_PATH, "Names starting with ` are reserved");
+    }
+
+    // invalid characters
+    if ["..", INTERNAL_PREFIX].iter().any(|s| path.contains(s)) || path.chars().any(|c| c.is_control()) {
+        return error!(ERROR_INVALID_PATH, "Path contains invalid characters");
+    }
+
+    // every component under ROOT must be a plain name (no "", "." or "..")
+    let root = root();
+    if *path != root {
+        let Some(relative) = path.strip_prefix(&child_prefix(&root)) else {
+            return error!(ERROR_INVALID_PATH, "Not under ROOT");
+        };
+        if relative.split('/').any(|name| name.is_empty() || name == "." || name == "..") {
+            return error!(ERROR_INVALID_PATH, "Path contains invalid components");
+        }
+        if relative.split('/').count() > MAX_DEPTH {
+            return error!(ERROR_INVALID_PATH, "Path is too deep");
+        }
+        if relative.split('/').any(|name| name.len() > MAX_NAME) {
+            return error!(ERROR_INVALID_PATH, "Name is too long");
+        }
+    }
+    Ok(storage_key(path))
+}
+
+/// returns the absolute form of the path
+///
+/// Paths starting with ROOT are kept as they are. Other paths are relative to ROOT
+/// (a leading '/' is allowed), so "docs/file.txt" and "/docs/file.txt" are "ROOT/docs/file.txt".
+fn canonical_path(path:&str) -> String {
+    canonical_path_in(&root(), path)
+}
+
+/// returns the absolute form of the path under the root (see `canonical_path`)
+fn canonical_path_in(root:&str, path:&str) -> String {
+    let prefix = child_prefix(root);
+    if path == root || path.starts_with(&prefix) {
+        return path.to_string();
+    }
+    let relative = path.trim_start_matches('/');
+    if relative.is_empty() {
+        root.to_string()
+    } else {
+        format!("{}{}", prefix, relative)
+    }
+}
+
+/// returns the key of the path in the filesystem and metadata (lowercased in case-insensitive mode)
+fn storage_key(path:&str) -> String {
+    let path = canonical_path(path);
+    if config().case_insensitive {
+        path.to_lowercase()
+    } else {
+        path.to_string()
+    }
+}
+
+/// returns the original casing of the name to keep for display (case-insensitive mode only)
+fn display_name(path:&str) -> Option<String> {
+    if config().case_insensitive {
+        Some(file_name(path))
+    } else {
+        None
+    }
+}
+
+/// returns the effective ROOT (set by initCanistorage, or ROOT if not specified)
+fn root() -> String {
+    CONFIG.with(|config| config.borrow().get().root.clone()).unwrap_or_else(|| ROOT.to_string())
+}
+
+fn config() -> Config {
+    CONFIG.with(|config| config.borrow().get().clone())
+}
+
+fn update_config<F:FnOnce(&mut Config)>(f:F) -> Result<(), Error> {
+    CONFIG.with(|config| {
+        let mut cell = config.borrow_mut();
+        let mut new_config = cell.get().clone();
+        f(&mut new_config);
+        match cell.set(new_config) {
+            Ok(_) => Ok(()),
+            Err(e) => error!(ERROR_UNKNOWN, format!("{:?}", e))
+        }
+    })
+}
+
+/// returns a new FileInfo seeded with the default permissions of the parent directory
+fn new_file_info(path:&String, creator:Principal, now:u64, mimetype:String) -> FileInfo {
+    let mut info = FileInfo::new(creator, now, mimetype);
+    if let Some(parent_info) = get_file_info(&parent_path(path)) {
+        info.manageable = parent_info.default_manageable;
+        info.readable = parent_info.default_readable;
+        info.writable = parent_info.default_writable;
+    }
+    info
+}
+
+/// returns an error if the mimetype is invalid for a file or refused by the policy
+fn check_mimetype(mimetype:&str) -> Result<(), Error> {
+    if mimetype.is_empty() || mimetype == MIMETYPE_DIRECTORY {
+        return error!(ERROR_INVALID_MIMETYPE, "Invalid mimetype");
+    }
+    let config = config();
+    if config.mimetype_deny.iter().any(|rule| matches_mimetype(rule, mimetype)) {
+        return error!(ERROR_INVALID_MIMETYPE, "Mimetype is not allowed");
+    }
+    if !config.mimetype_allow.is_empty() && !config.mimetype_allow.iter().any(|rule| matches_mimetype(rule, mimetype)) {
+        return error!(ERROR_INVALID_MIMETYPE, "Mimetype is not allowed");
+    }
+    Ok(())
+}
+
+/// returns whether the mimetype matches the rule ("type/subtype", "type/*" or "*")
+fn matches_mimetype(rule:&str, mimetype:&str) -> bool {
+    let essence = |text:&str| text.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+    let (rule, mimetype) = (essence(rule), essence(mimetype));
+    match rule.strip_suffix('*') {
+        Some(prefix) => mimetype.starts_with(prefix),
+        None => rule == mimetype
+    }
+}
+
+/// returns an error unless the parent of the path is an existing directory
+///
+/// A file in the middle of the path is reported as ERROR_INVALID_PATH with its path.
+fn check_parent_directory(path:&String) -> Result<(), Error> {
+    let root = root();
+    let parent = parent_path(path);
+    let mut ancestor = parent.clone();
+    while !ancestor.is_empty() {
+        match get_file_info(&ancestor) {
+            Some(info) if info.is_dir() => break,
+            Some(_) => return error!(ERROR_INVALID_PATH, format!("Not a directory: {}", ancestor)),
+            None => {}
+        }
+        if ancestor == root {
+            break;
+        }
+        ancestor = parent_path(&ancestor);
+    }
+    if get_file_info(&parent).is_none() {
+        return error!(ERROR_NOT_FOUND, "Parent directory not found");
+    }
+    Ok(())
+}
+
+/// returns an error if `to` is `from` itself or its descendant
+fn check_not_into_itself(from:&str, to:&str) -> Result<(), Error> {
+    if to == from || to.starts_with(&format!("{}/", from)) || *from == root() {
+        return error!(ERROR_INVALID_PATH, "Can't move into itself");
+    }
+    Ok(())
+}
+
+/// returns file info path (metadata of file kept on the filesystem by older versions)
+fn file_info_path(path:&String) -> String {
+    prefixed_path(path, METADATA_PREFIX)
+}
+
+/// returns the path with the prefix put on the last component ("/" gets the prefix as its name)
+fn prefixed_path(path:&String, prefix:&str) -> String {
+    if path == "/" {
+        return format!("/{}", prefix);
+    }
+    match path.rfind("/") {
+        Some(index) => {
+            format!("{}{}{}", &path[0..index +1], prefix, &path[index + 1..])
+        },
+        None => {
+            // FIXME Not expected
+            format!("{}{}", prefix, path)
+        }
+    }
+}
+
+fn parent_path(path:&String) -> String {
+    if *path == root() || path == "/" { // Not expected
+        "".to_string()
+    } else {
+        match path.rfind("/") {
+            Some(0) => "/".to_string(),
+            Some(index) => path[0..index].to_string(),
+            None => "".to_string() // not expected
+        }
+    }
+}
+
+fn get_file_info(path:&String) -> Option<FileInfo> {
+    FILE_INFOS.with(|infos| infos.borrow().get(path))
+}
+
+fn set_file_info(path:&str, info:&FileInfo) -> Result<(), Error> {
+    let old = FILE_INFOS.with(|infos| infos.borrow_mut().insert(path.to_string(), info.clone()));
+    update_usage(old.as_ref(), Some(info));
+    update_subtree_sizes(path, old.as_ref(), Some(info));
+    Ok(())
+}
+
+fn delete_file_info(path:&String) {
+    let old = FILE_INFOS.with(|infos| infos.borrow_mut().remove(path));
+    update_usage(old.as_ref(), None);
+    update_subtree_sizes(path, old.as_ref(), None);
+}
+
+/// returns paths of the file infos of the descendants
+fn descendant_file_info_paths(path:&String) -> Vec<String> {
+    let prefix = if path.ends_with('/') { path.clone() } else { format!("{}/", path) };
+    FILE_INFOS.with(|infos| {
+        infos.borrow()
+            .keys_range(prefix.clone()..)
+            .take_while(|key| key.starts_with(&prefix))
+            .collect()
+    })
+}
+
+/// deletes the file infos of the path and its descendants
+fn delete_file_infos(path:&String) {
+    for descendant in descendant_file_info_paths(path) {
+        delete_file_info(&descendant);
+    }
+    delete_file_info(path);
+}
+
+/// moves the file infos of the path and its descendants
+fn move_file_infos(from:&String, to:&String) {
+    let mut paths = vec![from.clone()];
+    paths.extend(descendant_file_info_paths(from));
+    for path in paths {
+        // parents first, so a moved directory with a quota counts its moved descendants
+        if let Some(info) = get_file_info(&path) {
+            delete_file_info(&path);
+            let _ = set_file_info(&format!("{}{}", to, &path[from.len()..]), &info);
+        }
+    }
+}
+
+/// imports metadata kept as backquote files by older versions into stable memory
+///
+/// Called on upgrade. The filesystem is walked only until the migration completes once.
+pub fn migrate_file_infos() {
+    if config().migrated {
+        return;
+    }
+    let root = root();
+    import_file_info(&root, &file_info_path(&root));
+    import_file_infos(&root);
+    let _ = update_config(|config| config.migrated = true);
+}
+
+fn import_file_infos(dir:&String) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let entries:Vec<(String, String, bool)> = entries
+        .flatten()
+        .map(|entry| (
+            entry.file_name().to_string_lossy().into_owned(),
+            entry.path().to_string_lossy().into_owned(),
+            entry.file_type().is_ok_and(|file_type| file_type.is_dir()),
+        ))
+        .collect();
+    for (name, entry_path, is_dir) in entries {
+        if is_dir {
+            if !name.starts_with(INTERNAL_PREFIX) {
+                import_file_infos(&entry_path);
+            }
+        } else if name.starts_with(METADATA_PREFIX) && !name.starts_with(TEMP_PREFIX) {
+            // `name -> name
+            let path = format!("{}{}", &entry_path[..entry_path.len() - name.len()], &name[METADATA_PREFIX.len()..]);
+            import_file_info(&path, &entry_path);
+        }
+    }
+}
+
+fn import_file_info(path:&str, info_path:&str) {
+    let Ok(data) = fs::read(info_path) else {
+        return;
+    };
+    if let Ok(info) = serde_cbor::from_slice::<FileInfo>(&data) {
+        if get_file_info(&path.to_string()).is_none() {
+            let _ = set_file_info(path, &info);
+        }
+        let _ = fs::remove_file(info_path);
+    }
+}
+
+/// returns paths of the files/directories in the specified directory (metadata excluded)
+fn child_paths(path:&String) -> Result<Vec<String>, Error> {
+    let entries = match fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(e) => return error!(ERROR_UNKNOWN, format!("{:?}", e))
+    };
+    let mut children:Vec<String> = Vec::new();
+    for entry in entries {
+        match entry {
+            Ok(entry) => {
+                let file_name = entry.file_name().to_string_lossy().into_owned();
+                if !file_name.starts_with(INTERNAL_PREFIX) {
+                    children.push(entry.path().to_string_lossy().into_owned());
+                }
+            },
+            Err(e) => return error!(ERROR_UNKNOWN, format!("{:?}", e))
+        }
+    }
+    children.sort();
+    Ok(children)
+}
+
+/// returns the prefix of the paths under the directory ("/" for "/", "dir/" for "dir")
+fn child_prefix(dir:&str) -> String {
+    format!("{}/", dir.trim_end_matches('/'))
+}
+
+/// returns names and file infos of the files/directories in the specified directory (metadata excluded)
+fn child_infos(path:&String) -> Result<Vec<(String, FileInfo)>, Error> {
+    Ok(child_paths(path)?
+        .iter()
+        .filter_map(|child| get_file_info(child).map(|info| {
+            (info.name.clone().unwrap_or_else(|| file_name(child)), info)
+        }))
+        .collect())
+}
+
+/// returns entries of the files/directories in the specified directory (metadata excluded)
+fn child_entries(path:&String) -> Result<Vec<DirEntry>, Error> {
+    Ok(child_infos(path)?
+        .into_iter()
+        .map(|(name, info)| DirEntry {
+            name,
+            is_dir: info.is_dir(),
+            size: info.size,
+            mimetype: info.mimetype,
+            updated_at: info.updated_at,
+        })
+        .collect())
+}
+
+/// returns the children of a directory readable by the caller with their metadata (sorted by name)
+fn readable_children(caller:&Principal, path:&String) -> Result<Vec<(String, FileInfo)>, Error> {
+    let mut children:Vec<(String, FileInfo)> = Vec::new();
+    for child in child_paths(path)? {
+        let Some(info) = get_file_info(&child) else {
+            return error!(ERROR_NOT_FOUND, "File not found");
+        };
+        if check_read_permission(caller, &child, Some(&info)) {
+            children.push((child, info));
+        }
+    }
+    Ok(children)
+}
+
+/// returns sha256 of the content of a file
+fn content_sha256(path:&String, info:&FileInfo) -> Result<[u8; 32], Error> {
+    if let Some(sha256) = info.sha256 {
+        Ok(sha256)
+    } else {
+        // saved before sha256 was recorded
+        match fs::read(path) {
+            Ok(data) => Ok(Sha256::digest(&data).into()),
+            Err(e) => error!(ERROR_UNKNOWN, format!("{:?}", e))
+        }
+    }
+}
+
+/// returns sha256 of a file or the Merkle root of a directory over the children readable by the caller
+///
+/// `remaining` is the number of entries still allowed to be hashed in the call.
+fn merkle_hash(caller:&Principal, path:&String, info:&FileInfo, remaining:&mut usize) -> Result<[u8; 32], Error> {
+    if !info.is_dir() {
+        return content_sha256(path, info);
+    }
+    let mut hashes:Vec<[u8; 32]> = Vec::new();
+    for (child, child_info) in readable_children(caller, path)? {
+        hashes.push(merkle_child_hash(caller, &child, &child_info, remaining)?);
+    }
+    if hashes.is_empty() {
+        return Ok(Sha256::digest(b"").into());
+    }
+    while hashes.len() > 1 {
+        hashes = merkle_combine(&hashes);
+    }
+    Ok(hashes[0])
+}
+
+/// returns sha256(name || hash) of a child
+fn merkle_child_hash(caller:&Principal, path:&String, info:&FileInfo, remaining:&mut usize) -> Result<[u8; 32], Error> {
+    if *remaining == 0 {
+        return error!(ERROR_INVALID_SIZE, "Too many entries to hash");
+    }
+    *remaining -= 1;
+    let mut hasher = Sha256::new();
+    hasher.update(file_name(path).as_bytes());
+    hasher.update(merkle_hash(caller, path, info, remaining)?);
+    Ok(hasher.finalize().into())
+}
+
+/// returns the tree hash of a directory over the children readable by the caller
+///
+/// `remaining` is the number of entries still allowed to be hashed in the call.
+/// The recursion is bounded by MAX_DEPTH (validate_path rejects deeper paths).
+fn subtree_hash(caller:&Principal, path:&String, remaining:&mut usize) -> Result<[u8; 32], Error> {
+    let mut hasher = Sha256::new();
+    for (child, info) in readable_children(caller, path)? {
+        if *remaining == 0 {
+            return error!(ERROR_INVALID_SIZE, "Too many entries to hash");
+        }
+        *remaining -= 1;
+        let name = file_name(&child);
+        hasher.update((name.len() as u64).to_be_bytes());
+        hasher.update(name.as_bytes());
+        if info.is_dir() {
+            hasher.update(b"d");
+            hasher.update(subtree_hash(caller, &child, remaining)?);
+        } else {
+            hasher.update(b"f");
+            hasher.update(content_sha256(&child, &info)?);
+        }
+    }
+    Ok(hasher.finalize().into())
+}
+
+/// combines hashes pairwise into the next level
+fn merkle_combine(hashes:&[[u8; 32]]) -> Vec<[u8; 32]> {
+    hashes.chunks(2)
+        .map(|pair| {
+            if pair.len() == 2 {
+                let mut hasher = Sha256::new();
+                hasher.update(pair[0]);
+                hasher.update(pair[1]);
+                hasher.finalize().into()
+            } else {
+                pair[0]
+            }
+        })
+        .collect()
+}
+
+/// returns the content of a file
+fn http_file(path:&str, info:&FileInfo, download:bool) -> HttpResponse {
+    if info.size > MAX_HTTP_SIZE {
+        return http_error(413, "Payload Too Large");
+    }
+    let body = match fs::read(content_path(path, info)) {
+        Ok(body) => body,
+        Err(_) => return http_error(500, "Internal Server Error")
+    };
+    let content_type = match info.mimetype.as_str() {
+        "" | MIMETYPE_OCTET_STREAM => sniff_mimetype(&body).to_string(),
+        mimetype => mimetype.to_string()
+    };
+    let mut headers = vec![("Content-Type".to_string(), content_type)];
+    if download {
+        let name = info.name.clone().unwrap_or_else(|| file_name(path));
+        headers.push(("Content-Disposition".to_string(), content_disposition(&name)));
+    }
+    HttpResponse {
+        status_code: 200,
+        headers,
+        body,
+    }
+}
+
+/// guesses the mimetype from the magic number of the content
+fn sniff_mimetype(body:&[u8]) -> &'static str {
+    const MAGIC_NUMBERS:[(&[u8], &str); 7] = [
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"GIF8", "image/gif"),
+        (b"%PDF-", "application/pdf"),
+        (b"\x1f\x8b", "application/gzip"),
+        (b"PK\x03\x04", "application/zip"),
+        (b"\0asm", "application/wasm"),
+    ];
+    if body.len() >= 12 && &body[0..4] == b"RIFF" && &body[8..12] == b"WEBP" {
+        return "image/webp";
+    }
+    MAGIC_NUMBERS.iter()
+        .find(|(magic, _)| body.starts_with(magic))
+        .map_or(MIMETYPE_OCTET_STREAM, |(_, mimetype)| mimetype)
+}
+
+/// returns `Content-Disposition` to save a file with the name
+///
+/// `filename` is an ASCII fallback with '"' and '\' escaped, and `filename*` keeps the name in UTF-8 (RFC 6266).
+fn content_disposition(name:&str) -> String {
+    let fallback:String = name.chars()
+        .map(|c| if c.is_ascii() && !c.is_ascii_control() { c } else { '_' })
+        .flat_map(|c| if c == '"' || c == '\\' { vec!['\\', c] } else { vec![c] })
+        .collect();
+    let encoded:String = name.bytes()
+        .map(|byte| if byte.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&byte) {
+            (byte as char).to_string()
+        } else {
+            format!("%{:02X}", byte)
+        })
+        .collect();
+    format!("attachment; filename=\"{}\"; filename*=UTF-8''{}", fallback, encoded)
+}
+
+/// returns the decoded value of the parameter in a query string
+fn query_param(query:&str, key:&str) -> Option<String> {
+    query.split('&')
+        .map(|pair| pair.split_once('=').unwrap_or((pair, "")))
+        .find(|(name, _)| percent_decode(name) == key)
+        .map(|(_, value)| percent_decode(value))
+}
+
+/// returns a plain text response of the HTTP status
+fn http_error(status_code:u16, message:&str) -> HttpResponse {
+    HttpResponse {
+        status_code,
+        headers: vec![("Content-Type".to_string(), "text/plain".to_string())],
+        body: message.as_bytes().to_vec(),
+    }
+}
+
+/// decodes %XX escapes of a URL path
+fn percent_decode(text:&str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded:Vec<u8> = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let hex = bytes.get(index + 1..index + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[index], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                index += 3;
+            },
+            (byte, _) => {
+                decoded.push(byte);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// returns the text as a JSON string literal
+fn json_string(text:&str) -> String {
+    let mut json = String::with_capacity(text.len() + 2);
+    json.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c)
+        }
+    }
+    json.push('"');
+    json
+}
+
+/// returns the last component of the path
+fn file_name(path:&str) -> String {
+    match path.rfind("/") {
+        Some(index) => path[index + 1..].to_string(),
+        None => path.to_string()
+    }
+}
+
+/// deletes the directory and everything in it bottom-up with the metadata
+///
+/// Entries which couldn't be deleted (and the directories holding them) are added to `failures`,
+/// including the ones the caller can't write or locked by another principal.
+fn remove_tree(caller:&Principal, dir:&String, failures:&mut Vec<String>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => {
+            failures.push(dir.clone());
+            return;
+        }
+    };
+    for entry in entries.flatten() {
+        let path = entry.path().to_string_lossy().into_owned();
+        let internal = entry.file_name().to_string_lossy().starts_with(INTERNAL_PREFIX);
+        if !internal && (!check_write_permission(caller, &path, get_file_info(&path).as_ref())
+            || lock_holder(&path).is_some_and(|owner| owner != *caller)) {
+            failures.push(path);
+        } else if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+            remove_tree(caller, &path, failures);
+        } else if remove_file(&path).is_ok() {
+            release_content(&path);
+            delete_file_info(&path);
+        } else {
+            failures.push(path);
+        }
+    }
+    match fs::remove_dir(dir) {
+        Ok(_) => delete_file_infos(dir),
+        Err(_) => failures.push(dir.clone())
+    }
+}
+
+/// returns the directory of shared blobs (hidden by the leading backquote)
+fn blob_directory() -> String {
+    format!("{}/{}", root().trim_end_matches('/'), BLOB_DIRECTORY_NAME)
+}
+
+/// returns the path of the shared blob
+fn blob_path(sha256:&[u8; 32]) -> String {
+    format!("{}/{}", blob_directory(), to_hex(sha256))
+}
+
+pub(crate) fn to_hex(bytes:&[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// returns the path holding the content of the file
+fn content_path(path:&str, info:&FileInfo) -> String {
+    match info.blob {
+        Some(sha256) => blob_path(&sha256),
+        None => path.to_string()
+    }
+}
+
+/// moves the written temp file into place
+///
+/// In content-addressed mode, the content becomes (or is deduplicated into) a shared blob and
+/// the path gets an empty placeholder. Returns the blob referenced by the file.
+///
+/// The file is replaced by a single rename, so if it fails, the temp file is removed and
+/// an existing file keeps its old content (its metadata isn't updated by the callers either).
+fn place_content(temp_path:&String, path:&String, sha256:&[u8; 32]) -> Result<Option<[u8; 32]>, Error> {
+    if !config().content_addressed {
+        return match rename_file(temp_path, path) {
+            Ok(_) => Ok(None),
+            Err(e) => {
+                let _ = fs::remove_file(temp_path);
+                error!(ERROR_UNKNOWN, format!("Failed to replace the file: {:?}", e))
+            }
+        };
+    }
+
+    if BLOBS.with(|blobs| blobs.borrow().contains_key(&to_hex(sha256))) {
+        let _ = fs::remove_file(temp_path);
+    } else if let Err(e) = fs::create_dir_all(blob_directory()).and_then(|_| rename_file(temp_path, &blob_path(sha256))) {
+        let _ = fs::remove_file(temp_path);
+        return error!(ERROR_UNKNOWN, format!("Failed to store the blob: {:?}", e));
+    }
+    retain_blob(sha256);
+    match File::create(path) {
+        Ok(_) => Ok(Some(*sha256)),
+        Err(e) => {
+            release_blob(sha256);
+            error!(ERROR_UNKNOWN, format!("{:?}", e))
+        }
+    }
+}
+
+/// adds a reference to the shared blob
+fn retain_blob(sha256:&[u8; 32]) {
+    BLOBS.with(|blobs| {
+        let mut blobs = blobs.borrow_mut();
+        let key = to_hex(sha256);
+        let count = blobs.get(&key).unwrap_or(0);
+        blobs.insert(key, count + 1);
+    })
+}
+
+/// removes a reference to the shared blob, deleting it at zero references
+fn release_blob(sha256:&[u8; 32]) {
+    BLOBS.with(|blobs| {
+        let mut blobs = blobs.borrow_mut();
+        let key = to_hex(sha256);
+        match blobs.get(&key) {
+            Some(count) if count > 1 => {
+                blobs.insert(key, count - 1);
+            },
+            _ => {
+                blobs.remove(&key);
+                let _ = fs::remove_file(blob_path(sha256));
+            }
+        }
+    })
+}
+
+// returns backup path for reverting a file in batch (unique per operation)
+fn backup_path(path:&String, op_index:usize) -> String {
+    prefixed_path(path, &format!("{}{}`", BACKUP_PREFIX, op_index))
+}
+
+// returns temporary path for saving a file
+fn temp_path(path:&String) -> String {
+    prefixed_path(path, TEMP_PREFIX)
+}
+
+
+/////////////////////////////////////////////////////////////////////////////
+//
+// Implementation for PoC only
+//
+// FIXME Remove before production
+#[derive(CandidType, Serialize, Deserialize)]
+pub struct FileInfoForPoC {
+    size: u64,
+    creator: Principal,
+    created_at: u64,
+    updater: Principal,
+    updated_at: u64,
+    mimetype: String,
+    path: String,
+    manageable: Vec<Principal>, // Grant or Revoke permission
+    readable: Vec<Principal>,
+    writable: Vec<Principal>,
+    children: Option<Vec<FileInfoForPoC>>,
+}
+
+impl FileInfoForPoC {
+    fn is_dir(&self) -> bool {
+        self.mimetype == MIMETYPE_DIRECTORY
+    }
+}
+
+// DEBUG logics for PoC
+#[ic_cdk::query(name="getAllInfoForPoC")]
+pub fn get_all_info_for_poc() -> Result<FileInfoForPoC, Error> {
+    get_info_for_poc(root())
+}
+
+pub fn get_info_for_poc(path:String) -> Result<FileInfoForPoC, Error> {
+
+    match get_file_info(&path) {
+        Some(info) => {
+            let children = if info.is_dir() {
+                // Directory
+                let mut children:Vec<FileInfoForPoC> = Vec::new();
+                let entries = fs::read_dir(&path).unwrap();
+                let _ = entries.map(| entry | {
+                    let entry = entry.unwrap();
+                    let file_name = entry.path().file_name().unwrap().to_string_lossy().into_owned();
+                    if !file_name.starts_with(INTERNAL_PREFIX) {
+                        let file_path = entry.path().to_string_lossy().into_owned();
+                        children.push(get_info_for_poc(file_path).unwrap());
+                    }
+                }).collect::<Vec<()>>();
+
+                children.sort_by(|a, b| 
+                    if a.is_dir() {
+                        if b.is_dir() {
+                            a.path.cmp(&b.path)
+                        } else {
+                            Ordering::Less
+                        }
+                    } else if b.is_dir() {
+                        Ordering::Greater
+                    } else {
+                        a.path.cmp(&b.path)
+                    }
+                );
+                Some(children)
+            } else {
+                // File
+                None
+            };
+
+            Ok(FileInfoForPoC {
+                path,
+                size: info.size,
+                creator: info.creator,
+                created_at: info.created_at,
+                updater: info.updater,
+                updated_at: info.updated_at,
+                mimetype: info.mimetype,
+                manageable: info.manageable,
+                readable: info.readable,
+                writable: info.writable,
+                children,
+            })
+        }
+        None => {
+            error!(ERROR_NOT_FOUND, "Directory not found")
+        }
+    }
+}
+
+// DEBUG logics for PoC (use `reset` instead)
+#[cfg(feature = "poc")]
+#[ic_cdk::update(name="forceResetForPoC")]
+pub fn force_reset_for_poc() -> Result<(), Error> {
+    check_writable()?;
+    if !is_admin(&caller()) {
+        return error!(ERROR_PERMISSION_DENIED, "Permission denied");
+    }
+    clear_storage()
+}
+
+
+/////////////////////////////////////////////////////////////////////////////
+// Unit Test
+/////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Mutex, MutexGuard};
+
+    // Tests share ROOT on the local filesystem, so run them one at a time
+    static TEST_LOCK: Mutex<()> = Mutex::new(());
+
+    struct TestContext {
+        _guard: MutexGuard<'static, ()>,
+    }
+    fn setup() -> TestContext {
+        let guard = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+        // owner
+        let owner = Principal::from_text("zebsi-6birt-enaic-v4hbv-zffiv-ft53g-u4gi3-og45y-tskzf-m6jus-xqe").unwrap(); // goddess x 12
+        set_caller(owner);
+
+        let _ = fs::remove_dir_all(format!("{}/", ROOT)); // Root is "./.test/" for unit test
+        let _ = fs::remove_file(file_info_path(&ROOT.to_string()));
+        FILE_INFOS.with(|infos| infos.borrow_mut().clear_new());
+        USAGE.with(|usage| usage.borrow_mut().clear_new());
+        SUBTREE_SIZES.with(|sizes| sizes.borrow_mut().clear_new());
+        let _ = fs::create_dir(format!("{}/", ROOT));
+        set_file_info(ROOT, &FileInfo {
+            manageable: vec![caller()],
+            readable: vec![caller()],
+            writable: vec![caller()],
+            ..FileInfo::new(caller(), 0, MIMETYPE_DIRECTORY.to_string())
+        }).unwrap();
+        TestContext {
+            _guard: guard,
+        }
+    }
+    impl Drop for TestContext {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(format!("{}/", ROOT));
+            let _ = fs::remove_file(file_info_path(&ROOT.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_save() {
+        let _context = setup();
+
+        // new file
+        let data = "Hello, World!".as_bytes().to_vec();
+        let result = save("./.test/file.txt".to_string(), "text/plain".to_string(), data.clone(), false);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), Sha256::digest(&data).as_slice());
+        let result = load("./.test/file.txt".to_string(), 0, None);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap().chunk, data);
+
+        // overwrite
+        let data = "Hello, World!".as_bytes().to_vec();
+        let result = save("./.test/file.txt".to_string(), "text/plain".to_string(), data.clone(), true);
+        assert!(result.is_ok());
+        let result = load("./.test/file.txt".to_string(), 0, None);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap().chunk, data);
+
+        // error
+        let result = save("./.test/file.txt".to_string(), "text/plain".to_string(), data.clone(), false);
+        assert!(result.is_err());
+        assert_eq!(result.unwrap_err().code, ERROR_ALREADY_EXISTS);
+    }
+
+    #[test]
+    fn test_save_over_directory() {
+        let _context = setup();
+
+        // file over directory
+        assert!(create_directory("./.test/dir".to_string()).is_ok());
+        let data = "Hello, World!".as_bytes().to_vec();
+        let result = save("./.test/dir".to_string(), "text/plain".to_string(), data.clone(), true);
+        assert_eq!(result.unwrap_err().code, ERROR_IS_DIRECTORY);
+        let result = begin_upload("./.test/dir".to_string(), "text/plain".to_string(), true, 0);
+        assert_eq!(result.unwrap_err().code, ERROR_IS_DIRECTORY);
+        assert!(get_file_info(&"./.test/dir".to_string()).unwrap().is_dir());
+
+        // directory over file
+        assert!(save("./.test/file.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+        let result = create_directory("./.test/file.txt".to_string());
+        let error = result.unwrap_err();
+        assert_eq!(error.code, ERROR_ALREADY_EXISTS);
+        assert_eq!(error.message, "File already exists");
+        assert_eq!(load("./.test/file.txt".to_string(), 0, None).unwrap().chunk, data);
+    }
+
+    #[test]
+    fn test_load_length() {
+        let _context = setup();
+
+        let data = "Hello, World!".as_bytes().to_vec();
+        assert!(save("./.test/file.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+
+        let download = load("./.test/file.txt".to_string(), 0, Some(5)).unwrap();
+        assert_eq!(download.chunk, "Hello".as_bytes());
+        assert_eq!(download.downloaded_at, 5);
+        assert_eq!(download.sha256, None);
+
+        let download = load("./.test/file.txt".to_string(), 7, Some(100)).unwrap();
+        assert_eq!(download.chunk, "World!".as_bytes());
+        assert_eq!(download.downloaded_at, data.len() as u64);
+        assert!(download.sha256.is_some());
+
+        let download = load("./.test/file.txt".to_string(), 7, Some(0)).unwrap();
+        assert!(download.chunk.is_empty());
+        assert_eq!(download.downloaded_at, 7);
+    }
+
+    #[test]
+    fn test_load_overwritten() {
+        let _context = setup();
+
+        let path = "./.test/file.txt".to_string();
+        let data = "Hello, World!".as_bytes().to_vec();
+        assert!(save(path.clone(), "text/plain".to_string(), data.clone(), false).is_ok());
+        let first = load(path.clone(), 0, Some(5)).unwrap();
+        assert_eq!(first.updated_at, get_info(path.clone()).unwrap().updated_at);
+        assert_eq!(first.version, 0);
+        assert_eq!(get_info(path.clone()).unwrap().version, 0);
+
+        // not changed
+        let download = load(path.clone(), first.downloaded_at, None).unwrap();
+        assert_eq!(download.updated_at, first.updated_at);
+        assert_eq!(download.version, first.version);
+
+        // overwritten during downloading (even within the same millisecond)
+        assert!(save(path.clone(), "text/plain".to_string(), data.clone(), true).is_ok());
+        let download = load(path.clone(), first.downloaded_at, None).unwrap();
+        assert_eq!(download.version, 1);
+        assert!(begin_upload(path.clone(), "text/plain".to_string(), true, data.len() as u64).is_ok());
+        assert!(send_data(path.clone(), 0, data.clone()).is_ok());
+        assert!(commit_upload(path.clone(), data.len() as u64, None).is_ok());
+        assert_eq!(load(path.clone(), first.downloaded_at, None).unwrap().version, 2);
+    }
+
+    #[test]
+    fn test_load_compressed() {
+        let _context = setup();
+
+        // compressible
+        let data = "Hello, World! ".repeat(1000).into_bytes();
+        assert!(save("./.test/file.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+        let download = load_compressed("./.test/file.txt".to_string(), 0).unwrap();
+        assert!(download.compressed);
+        assert!(download.chunk.len() < data.len());
+        assert_eq!(&download.chunk[0..2], &[0x1f, 0x8b]);
+        assert_eq!(download.downloaded_at, data.len() as u64);
+        assert_eq!(download.sha256.unwrap(), Sha256::digest(&data).as_slice());
+
+        // not compressible
+        let data = "Hi".as_bytes().to_vec();
+        assert!(save("./.test/small.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+        let download = load_compressed("./.test/small.txt".to_string(), 0).unwrap();
+        assert!(!download.compressed);
+        assert_eq!(download.chunk, data);
+    }
+
+    #[test]
+    fn test_delete() {
+        let _context = setup();
+
+        // new file
+        let data = "Hello, World!".as_bytes().to_vec();
+        let result = save("./.test/file.txt".to_string(), "text/plain".to_string(), data.clone(), false);
+        assert!(result.is_ok());
+        let result = load("./.test/file.txt".to_string(), 0, None);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap().chunk, data);
+
+        // delete
+        let result = delete("./.test/file.txt".to_string());
+        assert!(result.is_ok());
+
+        // delete (File not found)
+        let result = delete("./.test/file.txt".to_string());
+        assert_eq!(result.unwrap_err().code, ERROR_NOT_FOUND);
+
+        // delete (Directory)
+        assert!(create_directory("./.test/dir".to_string()).is_ok());
+        let result = delete("./.test/dir".to_string());
+        assert_eq!(result.unwrap_err().code, ERROR_IS_DIRECTORY);
+        assert!(get_file_info(&"./.test/dir".to_string()).is_some());
+    }
+
+    #[test]
+    fn test_file_info() {
+        let _context = setup();
+
+        // Root
+        let principal_readable = Principal::from_text("f3umm-tovgf-tf7o6-o3oqc-iqlir-f6ufh-3lvrh-5wlic-6dmnu-gg4q7-6ae").unwrap(); // abandon x 12
+        let principal_writable = Principal::from_text("ymtnq-243kz-shxxs-lfs7t-ihqhn-fntsv-wxvf3-kefpu-27hyr-wdczf-2ae").unwrap(); // ability x 12
+        let file_info = FileInfo {
+            readable: vec![principal_readable],
+            writable: vec![principal_writable],
+            ..FileInfo::new(caller(), 0, "".to_string())
+        };
+
+        // Check of root
+        let path = ROOT.to_string();
+        set_file_info(&path, &file_info).unwrap();
+        assert!(check_read_permission(&principal_readable, &path, Some(&file_info)));
+        assert!(!check_read_permission(&principal_writable, &path, Some(&file_info)));
+        assert!(!check_write_permission(&principal_readable, &path, Some(&file_info)));
+        assert!(check_write_permission(&principal_writable, &path, Some(&file_info)));
+
+        // Check children (no permission found; check parent)
+        let path = format!("{}/child", ROOT);
+        assert!(check_read_permission(&principal_readable, &path, None));
+        assert!(!check_read_permission(&principal_writable, &path, None));
+        assert!(!check_write_permission(&principal_readable, &path, None));
+        assert!(check_write_permission(&principal_writable, &path, None));
+
+        // Check children (has permision)
+        let principal_child_only = Principal::from_text("xm4xy-wgdl4-jhtba-hmdt7-kocg2-y47gj-wuwwg-oqbva-tydcp-6bvxn-7qe").unwrap(); // child x 12
+        let file_info = FileInfo {
+            readable: vec![principal_child_only],
+            writable: vec![principal_child_only],
+            ..FileInfo::new(caller(), 0, "".to_string())
+        };
+        set_file_info(&path, &file_info).unwrap();
+        assert!(check_read_permission(&principal_child_only, &path, Some(&file_info)));
+        assert!(check_write_permission(&principal_child_only, &path, Some(&file_info)));
+        // hasPermission because of parent (Inherited)
+        assert!(check_read_permission(&principal_readable, &path, Some(&file_info)));
+        assert!(check_write_permission(&principal_writable, &path, Some(&file_info)));
+        // No permission
+        assert!(!check_read_permission(&principal_writable, &path, Some(&file_info)));
+        assert!(!check_write_permission(&principal_readable, &path, Some(&file_info)));
+    }
+
+    #[test]
+    fn test_deny_permission() {
+        let _context = setup();
+        let owner = caller();
+        let user = Principal::from_text("aaikz-lv7jd-phj2u-t6r4n-6gne4-3rv3x-jus4j-zbiaz-llnsl-jvk5j-iqe").unwrap(); // actor x 12
+
+        assert!(create_directory("./.test/dir".to_string()).is_ok());
+        assert!(create_directory("./.test/dir/private".to_string()).is_ok());
+        assert!(create_directory("./.test/dir/private/public".to_string()).is_ok());
+        assert!(add_permission("./.test/dir".to_string(), user, false, true, true).is_ok());
+
+        // explicit deny on the exact path beats an inherited allow
+        assert!(deny_permission("./.test/dir/private".to_string(), user, true, true).is_ok());
+        set_caller(user);
+        let permission = has_permission("./.test/dir".to_string()).unwrap();
+        assert!(permission.readable);
+        assert!(permission.writable);
+        let permission = has_permission("./.test/dir/private".to_string()).unwrap();
+        assert!(!permission.readable);
+        assert!(!permission.writable);
+        // denial is inherited by descendants as well
+        let permission = has_permission("./.test/dir/private/public".to_string()).unwrap();
+        assert!(!permission.readable);
+
+        // explicit deny beats an explicit allow on the same path
+        set_caller(owner);
+        assert!(add_permission("./.test/dir/private".to_string(), user, false, true, false).is_ok());
+        set_caller(user);
+        assert!(!has_permission("./.test/dir/private".to_string()).unwrap().readable);
+
+        // an allow on a descendant is checked before the deny of its ancestor
+        set_caller(owner);
+        assert!(add_permission("./.test/dir/private/public".to_string(), user, false, true, false).is_ok());
+        set_caller(user);
+        assert!(has_permission("./.test/dir/private/public".to_string()).unwrap().readable);
+
+        // allow again
+        set_caller(owner);
+        assert!(allow_permission("./.test/dir/private".to_string(), user, true, false).is_ok());
+        set_caller(user);
+        let permission = has_permission("./.test/dir/private".to_string()).unwrap();
+        assert!(permission.readable);
+        assert!(!permission.writable);
+
+        // no manage permission
+        let result = deny_permission("./.test/dir".to_string(), owner, true, true);
+        assert_eq!(result.unwrap_err().code, ERROR_PERMISSION_DENIED);
+    }
+
+    #[test]
+    fn test_is_owner() {
+        let _context = setup();
+        let owner = caller();
+        let user = Principal::from_text("aaikz-lv7jd-phj2u-t6r4n-6gne4-3rv3x-jus4j-zbiaz-llnsl-jvk5j-iqe").unwrap(); // actor x 12
+
+        assert!(create_directory("./.test/dir".to_string()).is_ok());
+        assert!(add_permission("./.test/dir".to_string(), user, true, false, true).is_ok());
+        let permission = has_permission("./.test/dir".to_string()).unwrap();
+        assert!(permission.is_owner);
+        assert!(permission.manageable);
+
+        // a manager who isn't the creator
+        set_caller(user);
+        let permission = has_permission("./.test/dir".to_string()).unwrap();
+        assert!(!permission.is_owner);
+        assert!(permission.manageable);
+
+        // the creator of a child (manage permission is inherited)
+        assert!(create_directory("./.test/dir/sub".to_string()).is_ok());
+        let permission = has_permission("./.test/dir/sub".to_string()).unwrap();
+        assert!(permission.is_owner);
+        set_caller(owner);
+        let permission = has_permission("./.test/dir/sub".to_string()).unwrap();
+        assert!(!permission.is_owner);
+        assert!(permission.manageable);
+    }
+
+    #[test]
+    fn test_default_permissions() {
+        let _context = setup();
+        let user = Principal::from_text("aaikz-lv7jd-phj2u-t6r4n-6gne4-3rv3x-jus4j-zbiaz-llnsl-jvk5j-iqe").unwrap(); // actor x 12
+
+        assert!(create_directory("./.test/dir".to_string()).is_ok());
+        assert!(set_default_permissions("./.test/dir".to_string(), vec![], vec![user, user], vec![]).is_ok());
+
+        // seeded onto new children
+        let data = "Hello, World!".as_bytes().to_vec();
+        assert!(save("./.test/dir/file.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+        assert!(create_directory("./.test/dir/sub".to_string()).is_ok());
+        assert!(begin_upload("./.test/dir/large.bin".to_string(), "application/octet-stream".to_string(), false, 0).is_ok());
+        assert!(commit_upload("./.test/dir/large.bin".to_string(), 0, None).is_ok());
+        for path in ["./.test/dir/file.txt", "./.test/dir/sub", "./.test/dir/large.bin"] {
+            let info = get_file_info(&path.to_string()).unwrap();
+            assert_eq!(info.readable, vec![user]);
+            assert!(info.manageable.is_empty());
+            assert!(info.writable.is_empty());
+        }
+
+        // not seeded onto the directory itself nor grandchildren
+        assert!(get_file_info(&"./.test/dir".to_string()).unwrap().readable.is_empty());
+        assert!(create_directory("./.test/dir/sub/subsub".to_string()).is_ok());
+        assert!(get_file_info(&"./.test/dir/sub/subsub".to_string()).unwrap().readable.is_empty());
+
+        // directory only
+        let result = set_default_permissions("./.test/dir/file.txt".to_string(), vec![], vec![user], vec![]);
+        assert_eq!(result.unwrap_err().code, ERROR_NOT_A_DIRECTORY);
+    }
+
+    #[test]
+    fn test_ancestry() {
+        let _context = setup();
+
+        let result = ancestry("./.test/a/b/c.txt".to_string());
+        assert_eq!(result.unwrap(), vec!["./.test/a/b/c.txt", "./.test/a/b", "./.test/a", "./.test"]);
+        let result = ancestry(ROOT.to_string());
+        assert_eq!(result.unwrap(), vec![ROOT]);
+
+        // no read permission
+        set_caller(Principal::anonymous());
+        let result = ancestry("./.test/a".to_string());
+        assert_eq!(result.unwrap_err().code, ERROR_PERMISSION_DENIED);
+    }
+
+    #[test]
+    fn test_delete_many() {
+        let _context = setup();
+
+        let data = "Hello, World!".as_bytes().to_vec();
+        assert!(save("./.test/a.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+        assert!(save("./.test/b.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+        assert!(create_directory("./.test/dir".to_string()).is_ok());
+
+        let results = delete_many(vec![
+            "./.test/a.txt".to_string(),
+            "./.test/none.txt".to_string(),
+            "./.test/dir".to_string(),
+            "./.test/in`valid".to_string(),
+            "./.test/b.txt".to_string(),
+        ]);
+        assert_eq!(results.len(), 5);
+        assert!(results[0].is_ok());
+        assert_eq!(results[1].as_ref().unwrap_err().code, ERROR_NOT_FOUND);
+        assert_eq!(results[2].as_ref().unwrap_err().code, ERROR_IS_DIRECTORY);
+        assert_eq!(results[3].as_ref().unwrap_err().code, ERROR_INVALID_PATH);
+        assert!(results[4].is_ok());
+        assert_eq!(list_files("./.test".to_string()).unwrap(), vec!["dir/"]);
+
+        // too many paths
+        let results = delete_many(vec!["./.test/none.txt".to_string(); MAX_BATCH_SIZE + 1]);
+        assert_eq!(results[MAX_BATCH_SIZE - 1].as_ref().unwrap_err().code, ERROR_NOT_FOUND);
+        assert_eq!(results[MAX_BATCH_SIZE].as_ref().unwrap_err().code, ERROR_INVALID_SIZE);
+    }
+
+    #[test]
+    fn test_save_many() {
+        let _context = setup();
+        let owner = caller();
+        let data = "Hello, World!".as_bytes().to_vec();
+        assert!(create_directory("./.test/dir".to_string()).is_ok());
+        assert!(save("./.test/dir/exists.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+
+        let results = save_many(vec![
+            ("./.test/dir/a.txt".to_string(), "text/plain".to_string(), data.clone()),
+            ("./.test/dir/exists.txt".to_string(), "text/plain".to_string(), data.clone()),
+            ("./.test/none/b.txt".to_string(), "text/plain".to_string(), data.clone()),
+            ("./.test/`c.txt".to_string(), "text/plain".to_string(), data.clone()),
+            ("./.test/dir/d.html".to_string(), "text/html".to_string(), data.clone()),
+        ], false);
+        assert_eq!(results.len(), 5);
+        assert!(results[0].is_ok());
+        assert_eq!(results[1].as_ref().unwrap_err().code, ERROR_ALREADY_EXISTS);
+        assert_eq!(results[2].as_ref().unwrap_err().code, ERROR_NOT_FOUND);
+        assert_eq!(results[3].as_ref().unwrap_err().code, ERROR_INVALID_PATH);
+        assert!(results[4].is_ok());
+        assert_eq!(list_files("./.test/dir".to_string()).unwrap(), vec!["a.txt", "d.html", "exists.txt"]);
+        assert_eq!(get_info("./.test/dir/d.html".to_string()).unwrap().mimetype, "text/html");
+
+        // overwrite
+        let results = save_many(vec![("./.test/dir/exists.txt".to_string(), "text/plain".to_string(), vec![0; 3])], true);
+        assert!(results[0].is_ok());
+        assert_eq!(get_info("./.test/dir/exists.txt".to_string()).unwrap().size, 3);
+
+        // permission is checked for each file
+        let user = Principal::from_text("aaikz-lv7jd-phj2u-t6r4n-6gne4-3rv3x-jus4j-zbiaz-llnsl-jvk5j-iqe").unwrap(); // actor x 12
+        set_caller(user);
+        let results = save_many(vec![("./.test/dir/e.txt".to_string(), "text/plain".to_string(), data.clone())], false);
+        assert_eq!(results[0].as_ref().unwrap_err().code, ERROR_PERMISSION_DENIED);
+        set_caller(owner);
+
+        // too many files
+        let files:Vec<_> = (0..=MAX_BATCH_SIZE).map(|i| (format!("./.test/dir/{}.txt", i), "text/plain".to_string(), vec![])).collect();
+        let results = save_many(files, false);
+        assert!(results[MAX_BATCH_SIZE - 1].is_ok());
+        assert_eq!(results[MAX_BATCH_SIZE].as_ref().unwrap_err().code, ERROR_INVALID_SIZE);
+
+        // too large in total
+        let results = save_many(vec![
+            ("./.test/big1.bin".to_string(), "application/octet-stream".to_string(), vec![0; MAX_INGRESS_SIZE / 2]),
+            ("./.test/big2.bin".to_string(), "application/octet-stream".to_string(), vec![0; MAX_INGRESS_SIZE / 2 + 1]),
+        ], false);
+        assert!(results[0].is_ok());
+        assert_eq!(results[1].as_ref().unwrap_err().code, ERROR_INVALID_SIZE);
+    }
+
+    #[test]
+    fn test_signature() {
+        let _context = setup();
+
+        let data = "Hello, World!".as_bytes().to_vec();
+        assert!(save("./.test/file.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+        assert_eq!(get_signature("./.test/file.txt".to_string()).unwrap(), None);
+
+        assert!(set_signature("./.test/file.txt".to_string(), vec![1, 2, 3]).is_ok());
+        assert_eq!(get_signature("./.test/file.txt".to_string()).unwrap(), Some(vec![1, 2, 3]));
+
+        // cleared by writing content
+        assert!(save("./.test/file.txt".to_string(), "text/plain".to_string(), data.clone(), true).is_ok());
+        assert_eq!(get_signature("./.test/file.txt".to_string()).unwrap(), None);
+
+        // not found / directory
+        assert_eq!(set_signature("./.test/none.txt".to_string(), vec![1]).unwrap_err().code, ERROR_NOT_FOUND);
+        assert_eq!(get_signature("./.test/none.txt".to_string()).unwrap_err().code, ERROR_NOT_FOUND);
+        assert!(create_directory("./.test/dir".to_string()).is_ok());
+        assert_eq!(set_signature("./.test/dir".to_string(), vec![1]).unwrap_err().code, ERROR_IS_DIRECTORY);
+
+        // too large
+        assert!(set_signature("./.test/file.txt".to_string(), vec![1; MAX_SIGNATURE_SIZE]).is_ok());
+        let result = set_signature("./.test/file.txt".to_string(), vec![1; MAX_SIGNATURE_SIZE + 1]);
+        assert_eq!(result.unwrap_err().code, ERROR_INVALID_SIZE);
+    }
+
+    #[test]
+    fn test_verified_signature() {
+        let _context = setup();
+        let from_hex = |hex:&str| -> Vec<u8> {
+            (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap()).collect()
+        };
+        let pubkey = from_hex("03a107bff3ce10be1d70dd18e74bc09967e4d6309ba50d5f1ddc8664125531b8");
+        let signature = from_hex("12ef1053d0d322f715842b7650a95d3f780cd1047285fa6b7b22f0533e947d63596ea73a28b9631b3dc92c005bc03cbfeeb80a2f6c15e674b30caf1fad83ae09");
+
+        let data = "Hello, World!".as_bytes().to_vec();
+        assert!(save("./.test/file.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+
+        // signed over another content
+        assert!(save("./.test/other.txt".to_string(), "text/plain".to_string(), b"other".to_vec(), false).is_ok());
+        let result = set_verified_signature("./.test/other.txt".to_string(), signature.clone(), pubkey.clone());
+        assert_eq!(result.unwrap_err().code, ERROR_INVALID_SIGNATURE);
+        assert_eq!(get_signature("./.test/other.txt".to_string()).unwrap(), None);
+
+        // invalid length
+        let result = set_verified_signature("./.test/file.txt".to_string(), signature[0..63].to_vec(), pubkey.clone());
+        assert_eq!(result.unwrap_err().code, ERROR_INVALID_SIGNATURE);
+
+        assert!(set_verified_signature("./.test/file.txt".to_string(), signature.clone(), pubkey.clone()).is_ok());
+        assert_eq!(get_signature("./.test/file.txt".to_string()).unwrap(), Some(signature));
+        assert_eq!(get_info("./.test/file.txt".to_string()).unwrap().pubkey, Some(pubkey));
+
+        // cleared by writing content
+        assert!(save("./.test/file.txt".to_string(), "text/plain".to_string(), data.clone(), true).is_ok());
+        assert_eq!(get_info("./.test/file.txt".to_string()).unwrap().pubkey, None);
+    }
+
+    #[test]
+    fn test_merkle() {
+        let _context = setup();
+
+        assert!(create_directory("./.test/dir".to_string()).is_ok());
+        let empty:[u8; 32] = Sha256::digest(b"").into();
+        assert_eq!(directory_merkle_root("./.test/dir".to_string()).unwrap(), empty);
+
+        assert!(create_directory("./.test/dir/sub".to_string()).is_ok());
+        for name in ["a", "b", "c"] {
+            assert!(save(format!("./.test/dir/{}", name), "text/plain".to_string(), name.as_bytes().to_vec(), false).is_ok());
+        }
+        assert!(save("./.test/dir/sub/x".to_string(), "text/plain".to_string(), b"x".to_vec(), false).is_ok());
+
+        let root = directory_merkle_root("./.test/dir".to_string()).unwrap();
+        assert_ne!(root, empty);
+
+        // verify every file with its proof
+        let verify = |dir:&str, path:&str, data:&[u8]| {
+            let proof = merkle_proof(dir.to_string(), path.to_string()).unwrap();
+            let mut hash:[u8; 32] = Sha256::digest(data).into();
+            for level in proof {
+                hash = Sha256::digest([level.name.as_bytes(), &hash].concat()).into();
+                for sibling in level.siblings {
+                    hash = if sibling.is_left {
+                        Sha256::digest([sibling.hash, hash].concat()).into()
+                    } else {
+                        Sha256::digest([hash, sibling.hash].concat()).into()
+                    };
+                }
+            }
+            hash
+        };
+        for name in ["a", "b", "c"] {
+            assert_eq!(verify("./.test/dir", &format!("./.test/dir/{}", name), name.as_bytes()), root);
+        }
+        assert_eq!(verify("./.test/dir", "./.test/dir/sub/x", b"x"), root);
+
+        // a file directly under ROOT
+        assert!(save("./.test/top".to_string(), "text/plain".to_string(), b"top".to_vec(), false).is_ok());
+        assert_eq!(verify(ROOT, "./.test/top", b"top"), directory_merkle_root(ROOT.to_string()).unwrap());
+        assert_eq!(verify(ROOT, "./.test/dir/a", b"a"), directory_merkle_root(ROOT.to_string()).unwrap());
+        assert_eq!(child_prefix("/"), "/");
+        assert_eq!(child_prefix("./.test"), "./.test/");
+        assert_eq!(child_prefix("./.test/"), "./.test/");
+
+        // changes with the content
+        assert!(save("./.test/dir/sub/x".to_string(), "text/plain".to_string(), b"y".to_vec(), true).is_ok());
+        assert_ne!(directory_merkle_root("./.test/dir".to_string()).unwrap(), root);
+
+        // errors
+        assert_eq!(directory_merkle_root("./.test/dir/a".to_string()).unwrap_err().code, ERROR_NOT_A_DIRECTORY);
+        assert_eq!(directory_merkle_root("./.test/none".to_string()).unwrap_err().code, ERROR_NOT_FOUND);
+        assert_eq!(merkle_proof("./.test/dir".to_string(), "./.test/dir/sub".to_string()).unwrap_err().code, ERROR_IS_DIRECTORY);
+        assert_eq!(merkle_proof("./.test/dir/sub".to_string(), "./.test/dir/a".to_string()).unwrap_err().code, ERROR_INVALID_PATH);
+
+        // denied children are left out
+        let owner = caller();
+        let user = Principal::from_text("aaikz-lv7jd-phj2u-t6r4n-6gne4-3rv3x-jus4j-zbiaz-llnsl-jvk5j-iqe").unwrap(); // actor x 12
+        assert!(add_permission("./.test/dir".to_string(), user, false, true, false).is_ok());
+        assert!(deny_permission("./.test/dir/b".to_string(), user, true, false).is_ok());
+        assert!(deny_permission("./.test/dir/sub".to_string(), user, true, false).is_ok());
+        let root = directory_merkle_root("./.test/dir".to_string()).unwrap();
+        set_caller(user);
+        let visible = directory_merkle_root("./.test/dir".to_string()).unwrap();
+        assert_ne!(visible, root);
+        assert_eq!(verify("./.test/dir", "./.test/dir/a", b"a"), visible);
+        assert_eq!(verify("./.test/dir", "./.test/dir/c", b"c"), visible);
+        assert_eq!(merkle_proof("./.test/dir".to_string(), "./.test/dir/b".to_string()).unwrap_err().code, ERROR_PERMISSION_DENIED);
+        assert_eq!(merkle_proof("./.test/dir".to_string(), "./.test/dir/sub/x".to_string()).unwrap_err().code, ERROR_PERMISSION_DENIED);
+        set_caller(owner);
+        assert!(delete("./.test/dir/b".to_string()).is_ok());
+        assert!(delete_directory("./.test/dir/sub".to_string(), true).is_ok());
+        assert_eq!(directory_merkle_root("./.test/dir".to_string()).unwrap(), visible);
+
+        // bounded
+        let info = get_file_info(&ROOT.to_string()).unwrap();
+        assert_eq!(merkle_hash(&owner, &ROOT.to_string(), &info, &mut 2).unwrap_err().code, ERROR_INVALID_SIZE);
+        assert!(merkle_hash(&owner, &ROOT.to_string(), &info, &mut 4).is_ok());
+    }
+
+    #[test]
+    fn test_tree_hash() {
+        let _context = setup();
+        let owner = caller();
+
+        for dir in ["./.test/a", "./.test/b"] {
+            assert!(create_directory(dir.to_string()).is_ok());
+        }
+        let empty = tree_hash("./.test/a".to_string()).unwrap();
+        assert!(save("./.test/a/x".to_string(), "text/plain".to_string(), b"x".to_vec(), false).is_ok());
+        let hash = tree_hash("./.test/a".to_string()).unwrap();
+        assert_ne!(hash, empty);
+
+        // same structure and content
+        assert!(save("./.test/b/x".to_string(), "text/plain".to_string(), b"x".to_vec(), false).is_ok());
+        assert_eq!(tree_hash("./.test/b".to_string()).unwrap(), hash);
+
+        // changes with the content, names and descendants
+        assert!(save("./.test/b/x".to_string(), "text/plain".to_string(), b"y".to_vec(), true).is_ok());
+        assert_ne!(tree_hash("./.test/b".to_string()).unwrap(), hash);
+        assert!(rename("./.test/a/x".to_string(), "z".to_string(), false).is_ok());
+        assert_ne!(tree_hash("./.test/a".to_string()).unwrap(), hash);
+        let hash = tree_hash(ROOT.to_string()).unwrap();
+        assert!(create_directory("./.test/a/sub".to_string()).is_ok());
+        assert_ne!(tree_hash(ROOT.to_string()).unwrap(), hash);
+
+        // an empty file differs from an empty directory
+        assert!(touch("./.test/b/sub".to_string(), "text/plain".to_string()).is_ok());
+        assert!(delete("./.test/a/z".to_string()).is_ok());
+        assert!(delete("./.test/b/x".to_string()).is_ok());
+        assert_ne!(tree_hash("./.test/a".to_string()).unwrap(), tree_hash("./.test/b".to_string()).unwrap());
+
+        // errors
+        assert_eq!(tree_hash("./.test/b/sub".to_string()).unwrap_err().code, ERROR_NOT_A_DIRECTORY);
+        assert_eq!(tree_hash("./.test/none".to_string()).unwrap_err().code, ERROR_NOT_FOUND);
+        set_caller(Principal::anonymous());
+        assert_eq!(tree_hash("./.test/a".to_string()).unwrap_err().code, ERROR_PERMISSION_DENIED);
+        set_caller(owner);
+
+        // denied children are left out
+        let user = Principal::from_text("aaikz-lv7jd-phj2u-t6r4n-6gne4-3rv3x-jus4j-zbiaz-llnsl-jvk5j-iqe").unwrap(); // actor x 12
+        assert!(add_permission("./.test/a".to_string(), user, false, true, false).is_ok());
+        assert!(save("./.test/a/secret".to_string(), "text/plain".to_string(), b"s".to_vec(), false).is_ok());
+        assert!(deny_permission("./.test/a/secret".to_string(), user, true, false).is_ok());
+        let hash = tree_hash("./.test/a".to_string()).unwrap();
+        set_caller(user);
+        let visible = tree_hash("./.test/a".to_string()).unwrap();
+        assert_ne!(visible, hash);
+        set_caller(owner);
+        assert!(delete("./.test/a/secret".to_string()).is_ok());
+        assert_eq!(tree_hash("./.test/a".to_string()).unwrap(), visible);
+
+        // bounded
+        assert_eq!(subtree_hash(&owner, &ROOT.to_string(), &mut 2).unwrap_err().code, ERROR_INVALID_SIZE);
+        assert!(subtree_hash(&owner, &ROOT.to_string(), &mut 4).is_ok());
+    }
+
+    #[test]
+    fn test_touch() {
+        let _context = setup();
+
+        // create
+        assert!(touch("./.test/file.txt".to_string(), "text/plain".to_string()).is_ok());
+        let info = get_info("./.test/file.txt".to_string()).unwrap();
+        assert_eq!(info.size, 0);
+        assert_eq!(info.mimetype, "text/plain");
+        assert_eq!(load("./.test/file.txt".to_string(), 0, None).unwrap().chunk, b"");
+
+        // update the modification time only
+        let data = "Hello, World!".as_bytes().to_vec();
+        assert!(save("./.test/file.txt".to_string(), "text/plain".to_string(), data.clone(), true).is_ok());
+        let updated_at = get_info("./.test/file.txt".to_string()).unwrap().updated_at;
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        assert!(touch("./.test/file.txt".to_string(), "text/html".to_string()).is_ok());
+        let info = get_info("./.test/file.txt".to_string()).unwrap();
+        assert!(info.updated_at > updated_at);
+        assert_eq!(info.mimetype, "text/plain");
+        assert_eq!(load("./.test/file.txt".to_string(), 0, None).unwrap().chunk, data);
+
+        // errors
+        assert!(create_directory("./.test/dir".to_string()).is_ok());
+        assert_eq!(touch("./.test/dir".to_string(), "text/plain".to_string()).unwrap_err().code, ERROR_IS_DIRECTORY);
+        assert_eq!(touch("./.test/none/file.txt".to_string(), "text/plain".to_string()).unwrap_err().code, ERROR_NOT_FOUND);
+        assert_eq!(touch("./.test/new.txt".to_string(), MIMETYPE_DIRECTORY.to_string()).unwrap_err().code, ERROR_INVALID_MIMETYPE);
+        set_caller(Principal::from_text("aaikz-lv7jd-phj2u-t6r4n-6gne4-3rv3x-jus4j-zbiaz-llnsl-jvk5j-iqe").unwrap()); // actor x 12
+        assert_eq!(touch("./.test/file.txt".to_string(), "text/plain".to_string()).unwrap_err().code, ERROR_PERMISSION_DENIED);
+    }
+
+    #[test]
+    fn test_not_found_or_denied() {
+        let _context = setup();
+        let user = Principal::from_text("aaikz-lv7jd-phj2u-t6r4n-6gne4-3rv3x-jus4j-zbiaz-llnsl-jvk5j-iqe").unwrap(); // actor x 12
+
+        let data = "Hello, World!".as_bytes().to_vec();
+        for dir in ["public", "private"] {
+            assert!(create_directory(format!("./.test/{}", dir)).is_ok());
+            assert!(create_directory(format!("./.test/{}/dir", dir)).is_ok());
+            assert!(save(format!("./.test/{}/file.txt", dir), "text/plain".to_string(), data.clone(), false).is_ok());
+        }
+        assert!(add_permission("./.test/public".to_string(), user, false, true, false).is_ok());
+        set_caller(user);
+
+        // exists and authorized
+        assert!(load("./.test/public/file.txt".to_string(), 0, None).is_ok());
+        assert!(get_info("./.test/public/file.txt".to_string()).is_ok());
+        assert!(list_files("./.test/public/dir".to_string()).is_ok());
+
+        // not exists and authorized
+        assert_eq!(load("./.test/public/none.txt".to_string(), 0, None).unwrap_err().code, ERROR_NOT_FOUND);
+        assert_eq!(get_info("./.test/public/none.txt".to_string()).unwrap_err().code, ERROR_NOT_FOUND);
+        assert_eq!(list_files("./.test/public/none".to_string()).unwrap_err().code, ERROR_NOT_FOUND);
+
+        // exists and unauthorized
+        assert_eq!(load("./.test/private/file.txt".to_string(), 0, None).unwrap_err().code, ERROR_PERMISSION_DENIED);
+        assert_eq!(get_info("./.test/private/file.txt".to_string()).unwrap_err().code, ERROR_PERMISSION_DENIED);
+        assert_eq!(list_files("./.test/private/dir".to_string()).unwrap_err().code, ERROR_PERMISSION_DENIED);
+
+        // not exists and unauthorized (same as exists)
+        assert_eq!(load("./.test/private/none.txt".to_string(), 0, None).unwrap_err().code, ERROR_PERMISSION_DENIED);
+        assert_eq!(get_info("./.test/private/none.txt".to_string()).unwrap_err().code, ERROR_PERMISSION_DENIED);
+        assert_eq!(list_files("./.test/private/none".to_string()).unwrap_err().code, ERROR_PERMISSION_DENIED);
+    }
+
+    #[test]
+    fn test_get_info_anonymous() {
+        let _context = setup();
+        let data = "Hello, World!".as_bytes().to_vec();
+        for dir in ["public", "private"] {
+            assert!(create_directory(format!("./.test/{}", dir)).is_ok());
+            assert!(save(format!("./.test/{}/file.txt", dir), "text/plain".to_string(), data.clone(), false).is_ok());
+        }
+        assert!(add_permission("./.test/public".to_string(), Principal::anonymous(), false, true, false).is_ok());
+        set_caller(Principal::anonymous());
+
+        // public
+        let info = get_info("./.test/public/file.txt".to_string()).unwrap();
+        assert_eq!(info.size, data.len() as u64);
+        assert!(info.acl.is_none());
+        assert!(get_info("./.test/public".to_string()).unwrap().is_dir);
+
+        // private or missing paths are indistinguishable
+        for path in ["./.test/private/file.txt", "./.test/private/none.txt", "./.test/private", "./.test/public/none.txt", "./.test/none/file.txt"] {
+            let error = get_info(path.to_string()).unwrap_err();
+            assert_eq!((error.code, error.message), (ERROR_PERMISSION_DENIED, "Permission denied".to_string()));
+        }
+        let results = get_infos(vec!["./.test/public/file.txt".to_string(), "./.test/public/none.txt".to_string()]);
+        assert!(results[0].is_ok());
+        assert_eq!(results[1].as_ref().unwrap_err().code, ERROR_PERMISSION_DENIED);
+    }
+
+    #[test]
+    fn test_parent_update() {
+        let _context = setup();
+        let owner = caller();
+        let user = Principal::from_text("aaikz-lv7jd-phj2u-t6r4n-6gne4-3rv3x-jus4j-zbiaz-llnsl-jvk5j-iqe").unwrap(); // actor x 12
+        let data = "Hello, World!".as_bytes().to_vec();
+        assert!(create_directory("./.test/dir".to_string()).is_ok());
+        let updated_at = |path:&str| get_file_info(&path.to_string()).unwrap().updated_at;
+        let reset = || for path in [ROOT, "./.test/dir"] {
+            let mut info = get_file_info(&path.to_string()).unwrap();
+            info.updated_at = 0;
+            set_file_info(path, &info).unwrap();
+        };
+
+        // admin only
+        set_caller(user);
+        assert_eq!(set_parent_update(ParentUpdate::Parent).unwrap_err().code, ERROR_PERMISSION_DENIED);
+        set_caller(owner);
+
+        // off by default
+        assert_eq!(get_parent_update(), ParentUpdate::Off);
+        reset();
+        assert!(save("./.test/dir/a.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+        assert_eq!(updated_at("./.test/dir"), 0);
+
+        // the immediate parent
+        assert!(set_parent_update(ParentUpdate::Parent).is_ok());
+        assert_eq!(get_parent_update(), ParentUpdate::Parent);
+        assert!(save("./.test/dir/a.txt".to_string(), "text/plain".to_string(), data.clone(), true).is_ok());
+        assert!(updated_at("./.test/dir") > 0);
+        assert_eq!(updated_at(ROOT), 0);
+        for op in [
+            || delete("./.test/dir/a.txt".to_string()),
+            || create_directory("./.test/dir/sub".to_string()),
+        ] {
+            reset();
+            assert!(op().is_ok());
+            assert!(updated_at("./.test/dir") > 0);
+            assert_eq!(updated_at(ROOT), 0);
+        }
+        reset();
+        assert!(begin_upload("./.test/dir/b.bin".to_string(), "application/octet-stream".to_string(), false, 3).is_ok());
+        assert!(send_data("./.test/dir/b.bin".to_string(), 0, vec![0; 3]).is_ok());
+        assert!(commit_upload("./.test/dir/b.bin".to_string(), 3, None).is_ok());
+        assert!(updated_at("./.test/dir") > 0);
+
+        // every ancestor
+        assert!(set_parent_update(ParentUpdate::Ancestors).is_ok());
+        reset();
+        assert!(save("./.test/dir/sub/c.txt".to_string(), "text/plain".to_string(), data, false).is_ok());
+        assert!(updated_at("./.test/dir/sub") > 0);
+        assert!(updated_at("./.test/dir") > 0);
+        assert!(updated_at(ROOT) > 0);
+        assert!(set_parent_update(ParentUpdate::Off).is_ok());
+    }
+
+    #[test]
+    fn test_content_addressed() {
+        let _context = setup();
+        let refcount = |data:&[u8]| -> u64 {
+            let sha256:[u8; 32] = Sha256::digest(data).into();
+            BLOBS.with(|blobs| blobs.borrow().get(&to_hex(&sha256))).unwrap_or(0)
+        };
+        let blob_count = || fs::read_dir(blob_directory()).map(|entries| entries.count()).unwrap_or(0);
+
+        // admin only
+        set_caller(Principal::from_text("aaikz-lv7jd-phj2u-t6r4n-6gne4-3rv3x-jus4j-zbiaz-llnsl-jvk5j-iqe").unwrap()); // actor x 12
+        assert_eq!(set_content_addressed(true).unwrap_err().code, ERROR_PERMISSION_DENIED);
+        set_caller(Principal::from_text("zebsi-6birt-enaic-v4hbv-zffiv-ft53g-u4gi3-og45y-tskzf-m6jus-xqe").unwrap()); // goddess x 12
+        assert!(set_content_addressed(true).is_ok());
+        assert!(is_content_addressed());
+
+        // the same content is stored once
+        let data = "Hello, World!".as_bytes().to_vec();
+        assert!(save("./.test/a.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+        assert!(save("./.test/b.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+        assert_eq!(blob_count(), 1);
+        assert_eq!(refcount(&data), 2);
+        assert_eq!(fs::metadata("./.test/a.txt").unwrap().len(), 0); // placeholder
+        assert_eq!(load("./.test/a.txt".to_string(), 0, None).unwrap().chunk, data);
+        assert_eq!(get_info("./.test/b.txt".to_string()).unwrap().size, data.len() as u64);
+        assert_eq!(list_files(ROOT.to_string()).unwrap(), vec!["a.txt", "b.txt"]);
+
+        // overwrite and delete release the reference
+        assert!(save("./.test/a.txt".to_string(), "text/plain".to_string(), b"other".to_vec(), true).is_ok());
+        assert_eq!(refcount(&data), 1);
+        assert_eq!(load("./.test/a.txt".to_string(), 0, None).unwrap().chunk, b"other");
+        assert!(delete("./.test/b.txt".to_string()).is_ok());
+        assert_eq!(refcount(&data), 0);
+        assert_eq!(blob_count(), 1);
+
+        // upload links to the existing blob
+        assert!(begin_upload("./.test/c.txt".to_string(), "text/plain".to_string(), false, 5).is_ok());
+        assert!(send_data("./.test/c.txt".to_string(), 0, b"other".to_vec()).is_ok());
+        assert!(commit_upload("./.test/c.txt".to_string(), 5, None).is_ok());
+        assert_eq!(refcount(b"other"), 2);
+        assert_eq!(blob_count(), 1);
+
+        // rollback of batch keeps the blobs
+        let result = batch(vec![
+            Op::Save { path: "./.test/c.txt".to_string(), mimetype: "text/plain".to_string(), data: data.clone(), overwrite: true },
+            Op::Delete { path: "./.test/a.txt".to_string() },
+            Op::Delete { path: "./.test/none.txt".to_string() },
+        ]);
+        assert_eq!(result.unwrap_err().code, ERROR_NOT_FOUND);
+        assert_eq!(refcount(b"other"), 2);
+        assert_eq!(refcount(&data), 0);
+        assert_eq!(load("./.test/a.txt".to_string(), 0, None).unwrap().chunk, b"other");
+        assert_eq!(load("./.test/c.txt".to_string(), 0, None).unwrap().chunk, b"other");
+
+        // recursive deletion releases the descendants
+        assert!(create_directory("./.test/dir".to_string()).is_ok());
+        assert!(save("./.test/dir/d.txt".to_string(), "text/plain".to_string(), b"other".to_vec(), false).is_ok());
+        assert_eq!(refcount(b"other"), 3);
+        assert!(delete_directory("./.test/dir".to_string(), true).is_ok());
+        assert_eq!(refcount(b"other"), 2);
+
+        // files stay readable after disabling
+        assert!(set_content_addressed(false).is_ok());
+        assert!(save("./.test/a.txt".to_string(), "text/plain".to_string(), data.clone(), true).is_ok());
+        assert_eq!(refcount(b"other"), 1);
+        assert_eq!(load("./.test/a.txt".to_string(), 0, None).unwrap().chunk, data);
+        assert_eq!(load("./.test/c.txt".to_string(), 0, None).unwrap().chunk, b"other");
+    }
+
+    #[test]
+    fn test_access_tracking() {
+        let _context = setup();
+
+        let data = "Hello, World!".as_bytes().to_vec();
+        assert!(create_directory("./.test/dir".to_string()).is_ok());
+        assert!(create_directory("./.test/dir/sub".to_string()).is_ok());
+        assert!(save("./.test/dir/sub/file.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+        assert!(save("./.test/other.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+
+        // not tracked
+        let results = record_access(vec!["./.test/dir/sub/file.txt".to_string()]);
+        assert!(results[0].is_ok());
+        assert_eq!(get_info("./.test/dir/sub/file.txt".to_string()).unwrap().accessed_at, None);
+
+        // tracked under the directory
+        assert!(set_access_tracking("./.test/dir".to_string(), true).is_ok());
+        let results = record_access(vec![
+            "./.test/dir/sub/file.txt".to_string(),
+            "./.test/other.txt".to_string(),
+            "./.test/none.txt".to_string(),
+            "./.test/dir/sub".to_string(),
+        ]);
+        assert!(results[0].is_ok());
+        assert!(results[1].is_ok());
+        assert_eq!(results[2].as_ref().unwrap_err().code, ERROR_NOT_FOUND);
+        assert_eq!(results[3].as_ref().unwrap_err().code, ERROR_IS_DIRECTORY);
+        assert!(get_info("./.test/dir/sub/file.txt".to_string()).unwrap().accessed_at.is_some());
+        assert_eq!(get_info("./.test/other.txt".to_string()).unwrap().accessed_at, None);
+
+        // errors
+        assert_eq!(set_access_tracking("./.test/other.txt".to_string(), true).unwrap_err().code, ERROR_NOT_A_DIRECTORY);
+        set_caller(Principal::from_text("aaikz-lv7jd-phj2u-t6r4n-6gne4-3rv3x-jus4j-zbiaz-llnsl-jvk5j-iqe").unwrap()); // actor x 12
+        assert_eq!(set_access_tracking("./.test/dir".to_string(), false).unwrap_err().code, ERROR_PERMISSION_DENIED);
+        let results = record_access(vec!["./.test/dir/sub/file.txt".to_string()]);
+        assert_eq!(results[0].as_ref().unwrap_err().code, ERROR_PERMISSION_DENIED);
+    }
+
+    #[test]
+    fn test_migrate_file_infos() {
+        let _context = setup();
+
+        let data = "Hello, World!".as_bytes().to_vec();
+        assert!(create_directory("./.test/dir".to_string()).is_ok());
+        assert!(save("./.test/dir/file.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+
+        // metadata kept as backquote files by older versions
+        for path in [ROOT, "./.test/dir", "./.test/dir/file.txt"] {
+            let path = path.to_string();
+            fs::write(file_info_path(&path), serde_cbor::to_vec(&get_file_info(&path).unwrap()).unwrap()).unwrap();
+        }
+        FILE_INFOS.with(|infos| infos.borrow_mut().clear_new());
+        fs::write(temp_path(&"./.test/dir/file.txt".to_string()), b"temp").unwrap(); // not metadata
+        assert!(get_file_info(&ROOT.to_string()).is_none());
+
+        migrate_file_infos();
+        assert!(get_file_info(&ROOT.to_string()).unwrap().is_dir());
+        assert!(get_file_info(&"./.test/dir".to_string()).unwrap().is_dir());
+        assert_eq!(load("./.test/dir/file.txt".to_string(), 0, None).unwrap().chunk, data);
+        assert!(!fs::exists(file_info_path(&"./.test/dir/file.txt".to_string())).unwrap());
+        assert!(get_file_info(&"./.test/dir/`file.txt".to_string()).is_none());
+
+        // no-op once migrated
+        let path = "./.test/dir/file.txt".to_string();
+        fs::write(file_info_path(&path), serde_cbor::to_vec(&get_file_info(&path).unwrap()).unwrap()).unwrap();
+        migrate_file_infos();
+        assert!(fs::exists(file_info_path(&path)).unwrap()); // not walked again
+        assert_eq!(list_files("./.test/dir".to_string()).unwrap(), vec!["file.txt"]);
+    }
+
+    #[test]
+    fn test_case_insensitive() {
+        let _context = setup();
+
+        // admin only
+        set_caller(Principal::from_text("aaikz-lv7jd-phj2u-t6r4n-6gne4-3rv3x-jus4j-zbiaz-llnsl-jvk5j-iqe").unwrap()); // actor x 12
+        assert_eq!(set_case_insensitive(true).unwrap_err().code, ERROR_PERMISSION_DENIED);
+        set_caller(Principal::from_text("zebsi-6birt-enaic-v4hbv-zffiv-ft53g-u4gi3-og45y-tskzf-m6jus-xqe").unwrap()); // goddess x 12
+        assert!(set_case_insensitive(true).is_ok());
+        assert!(is_case_insensitive());
+
+        // lookups are case-folded
+        let data = "Hello, World!".as_bytes().to_vec();
+        assert!(create_directory("./.test/Docs".to_string()).is_ok());
+        assert!(save("./.test/DOCS/File.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+        assert!(fs::exists("./.test/docs/file.txt").unwrap());
+        assert_eq!(load("./.test/docs/FILE.TXT".to_string(), 0, None).unwrap().chunk, data);
+        assert_eq!(save("./.test/docs/file.txt".to_string(), "text/plain".to_string(), data.clone(), false).unwrap_err().code, ERROR_ALREADY_EXISTS);
+        assert_eq!(create_directory("./.test/DOCS".to_string()).unwrap_err().code, ERROR_ALREADY_EXISTS);
+
+        // original casing is listed
+        assert_eq!(list_files("./.test".to_string()).unwrap(), vec!["Docs/"]);
+        assert_eq!(list_files("./.test/docs".to_string()).unwrap(), vec!["File.txt"]);
+        assert!(move_path("./.test/docs/file.txt".to_string(), "./.test/Docs/Renamed.TXT".to_string()).is_ok());
+        assert_eq!(list_files("./.test/Docs".to_string()).unwrap(), vec!["Renamed.TXT"]);
+
+        // uploading
+        assert!(begin_upload("./.test/Docs/Upload.txt".to_string(), "text/plain".to_string(), false, data.len() as u64).is_ok());
+        assert!(send_data("./.test/docs/upload.txt".to_string(), 0, data.clone()).is_ok());
+        assert!(commit_upload("./.test/Docs/Upload.txt".to_string(), data.len() as u64, None).is_ok());
+        assert_eq!(load("./.test/docs/upload.txt".to_string(), 0, None).unwrap().chunk, data);
+        assert_eq!(list_files("./.test/Docs".to_string()).unwrap(), vec!["Renamed.TXT", "Upload.txt"]);
+
+        // can't be changed unless empty
+        assert_eq!(set_case_insensitive(false).unwrap_err().code, ERROR_ALREADY_EXISTS);
+        assert!(delete_directory("./.test/DOCS".to_string(), true).is_ok());
+        assert!(set_case_insensitive(false).is_ok());
+        assert!(!is_case_insensitive());
+    }
+
+    #[test]
+    fn test_child_count() {
+        let _context = setup();
+
+        assert!(create_directory("./.test/dir".to_string()).is_ok());
+        assert_eq!(get_info("./.test/dir".to_string()).unwrap().child_count, 0);
+
+        let data = "Hello, World!".as_bytes().to_vec();
+        assert!(save("./.test/dir/a.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+        assert!(create_directory("./.test/dir/sub".to_string()).is_ok());
+        assert!(save("./.test/dir/sub/b.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+        fs::write(temp_path(&"./.test/dir/c.txt".to_string()), b"temp").unwrap(); // temp file isn't counted
+
+        assert_eq!(get_info("./.test/dir".to_string()).unwrap().child_count, 2);
+        assert_eq!(get_info("./.test/dir/a.txt".to_string()).unwrap().child_count, 0);
+        assert!(get_info("./.test/dir".to_string()).unwrap().is_dir);
+        assert!(!get_info("./.test/dir/a.txt".to_string()).unwrap().is_dir);
+        assert_eq!(get_info(ROOT.to_string()).unwrap().child_count, 1);
+    }
+
+    #[test]
+    fn test_save_under_file() {
+        let _context = setup();
+
+        let data = "Hello, World!".as_bytes().to_vec();
+        assert!(create_directory("./.test/a".to_string()).is_ok());
+        assert!(save("./.test/a/b".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+
+        // the parent is a file
+        let result = save("./.test/a/b/c".to_string(), "text/plain".to_string(), data.clone(), false);
+        assert_eq!(result.as_ref().unwrap_err().code, ERROR_INVALID_PATH);
+        assert!(result.unwrap_err().message.contains("./.test/a/b"));
+
+        // a middle component is a file
+        let result = save("./.test/a/b/c/d".to_string(), "text/plain".to_string(), data.clone(), false);
+        assert_eq!(result.as_ref().unwrap_err().code, ERROR_INVALID_PATH);
+        assert!(result.unwrap_err().message.contains("./.test/a/b"));
+        assert_eq!(create_directory("./.test/a/b/c".to_string()).unwrap_err().code, ERROR_INVALID_PATH);
+        assert_eq!(begin_upload("./.test/a/b/c/d".to_string(), "text/plain".to_string(), false, 0).unwrap_err().code, ERROR_INVALID_PATH);
+
+        // missing parent is still not found
+        assert_eq!(save("./.test/x/y/z".to_string(), "text/plain".to_string(), data.clone(), false).unwrap_err().code, ERROR_NOT_FOUND);
+    }
+
+    #[test]
+    fn test_relative_path() {
+        let _context = setup();
+
+        assert_eq!(canonical_path("./.test/docs"), "./.test/docs");
+        assert_eq!(canonical_path("docs/file.txt"), "./.test/docs/file.txt");
+        assert_eq!(canonical_path("/docs/file.txt"), "./.test/docs/file.txt");
+        assert_eq!(canonical_path("/"), ROOT);
+
+        // "/" in production
+        assert_eq!(canonical_path_in("/", "/"), "/");
+        assert_eq!(canonical_path_in("/", "/docs/file.txt"), "/docs/file.txt");
+        assert_eq!(canonical_path_in("/", "docs/file.txt"), "/docs/file.txt");
+        assert_eq!(canonical_path_in("/", "docs"), "/docs");
+        assert_eq!(canonical_path_in("./.test", "./.test"), "./.test");
+        assert_eq!(canonical_path_in("./.test", "./.testing"), "./.test/./.testing"); // not under ROOT
+        assert_eq!(canonical_path_in("./.test", "//docs"), "./.test/docs");
+
+        let data = "Hello, World!".as_bytes().to_vec();
+        assert!(create_directory("docs".to_string()).is_ok());
+        assert!(save("/docs/file.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+        assert_eq!(load("./.test/docs/file.txt".to_string(), 0, None).unwrap().chunk, data);
+        assert_eq!(load("docs/file.txt".to_string(), 0, None).unwrap().chunk, data);
+        assert_eq!(list_files("/".to_string()).unwrap(), vec!["docs/"]);
+
+        assert!(begin_upload("docs/upload.txt".to_string(), "text/plain".to_string(), false, data.len() as u64).is_ok());
+        assert!(send_data("/docs/upload.txt".to_string(), 0, data.clone()).is_ok());
+        assert!(commit_upload("./.test/docs/upload.txt".to_string(), data.len() as u64, None).is_ok());
+        assert_eq!(list_files("docs".to_string()).unwrap(), vec!["file.txt", "upload.txt"]);
+
+        assert_eq!(validate_path("").unwrap_err().code, ERROR_INVALID_PATH);
+        assert_eq!(validate_path("docs/").unwrap_err().code, ERROR_INVALID_PATH);
+        assert_eq!(validate_path("../docs").unwrap_err().code, ERROR_INVALID_PATH);
+    }
+
+    #[test]
+    fn test_path_escape() {
+        let _context = setup();
+
+        assert_eq!(validate_path(ROOT).unwrap(), ROOT);
+        assert_eq!(validate_path("./.test/docs/file.txt").unwrap(), "./.test/docs/file.txt");
+        assert_eq!(validate_path("//docs").unwrap(), "./.test/docs"); // leading separators are relative to ROOT
+
+        // traversal
+        for path in ["./.test/..", "./.test/../etc", "./.test/docs/../../etc", "/../etc", "..", "docs/.."] {
+            assert_eq!(validate_path(path).unwrap_err().code, ERROR_INVALID_PATH, "{}", path);
+        }
+
+        // components other than plain names
+        for path in ["./.test/./docs", "./.test/docs/.", "./.test//docs", "./.test/docs//file.txt", ".", "./docs", "docs/./file.txt"] {
+            assert_eq!(validate_path(path).unwrap_err().code, ERROR_INVALID_PATH, "{}", path);
+        }
+
+        // siblings of ROOT are not under ROOT
+        assert_eq!(validate_path("./.testing/docs").unwrap_err().code, ERROR_INVALID_PATH);
+
+        // control characters
+        for path in ["./.test/docs\0", "./.test/do\ncs", "./.test/\u{7f}"] {
+            assert_eq!(validate_path(path).unwrap_err().code, ERROR_INVALID_PATH, "{:?}", path);
+        }
+        assert_eq!(save("./.test/./file.txt".to_string(), "text/plain".to_string(), vec![], false).unwrap_err().code, ERROR_INVALID_PATH);
+    }
+
+    #[test]
+    fn test_list_entries() {
+        let _context = setup();
+
+        let data = "Hello, World!".as_bytes().to_vec();
+        assert!(create_directory("./.test/dir".to_string()).is_ok());
+        assert!(create_directory("./.test/dir/sub".to_string()).is_ok());
+        assert!(save("./.test/dir/a.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+
+        let entries = list_entries("./.test/dir".to_string()).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].name, "a.txt");
+        assert!(!entries[0].is_dir);
+        assert_eq!(entries[0].size, data.len() as u64);
+        assert_eq!(entries[0].mimetype, "text/plain");
+        assert_eq!(entries[0].updated_at, get_file_info(&"./.test/dir/a.txt".to_string()).unwrap().updated_at);
+        assert_eq!(entries[1].name, "sub");
+        assert!(entries[1].is_dir);
+        assert_eq!(entries[1].mimetype, MIMETYPE_DIRECTORY);
+
+        assert_eq!(list_entries("./.test/dir/a.txt".to_string()).unwrap_err().code, ERROR_NOT_A_DIRECTORY);
+        assert_eq!(list_entries("./.test/none".to_string()).unwrap_err().code, ERROR_NOT_FOUND);
+        set_caller(Principal::from_text("aaikz-lv7jd-phj2u-t6r4n-6gne4-3rv3x-jus4j-zbiaz-llnsl-jvk5j-iqe").unwrap()); // actor x 12
+        assert_eq!(list_entries("./.test/dir".to_string()).unwrap_err().code, ERROR_PERMISSION_DENIED);
+    }
+
+    #[test]
+    fn test_list_files_sorted() {
+        let _context = setup();
+
+        assert!(create_directory("./.test/dir".to_string()).is_ok());
+        assert!(save("./.test/dir/b.txt".to_string(), "text/plain".to_string(), vec![0; 3], false).is_ok());
+        assert!(save("./.test/dir/a.txt".to_string(), "text/plain".to_string(), vec![0; 5], false).is_ok());
+        assert!(create_directory("./.test/dir/c".to_string()).is_ok());
+        for (path, created_at, updated_at) in [("./.test/dir/b.txt", 1, 30), ("./.test/dir/a.txt", 2, 10), ("./.test/dir/c", 3, 20)] {
+            let mut info = get_file_info(&path.to_string()).unwrap();
+            info.created_at = created_at;
+            info.updated_at = updated_at;
+            set_file_info(path, &info).unwrap();
+        }
+
+        let list = |by:SortKey, desc:bool| list_files_sorted("./.test/dir".to_string(), by, desc, false).unwrap();
+        assert_eq!(list(SortKey::Name, false), vec!["a.txt", "b.txt", "c/"]);
+        assert_eq!(list(SortKey::Name, true), vec!["c/", "b.txt", "a.txt"]);
+        assert_eq!(list(SortKey::Size, false), vec!["c/", "b.txt", "a.txt"]);
+        assert_eq!(list(SortKey::Size, true), vec!["a.txt", "b.txt", "c/"]);
+        assert_eq!(list(SortKey::Updated, false), vec!["a.txt", "c/", "b.txt"]);
+        assert_eq!(list(SortKey::Created, true), vec!["c/", "a.txt", "b.txt"]);
+        assert_eq!(list(SortKey::Name, false), list_files("./.test/dir".to_string()).unwrap());
+
+        // directories first
+        let list = |by:SortKey, desc:bool| list_files_sorted("./.test/dir".to_string(), by, desc, true).unwrap();
+        assert_eq!(list(SortKey::Name, false), vec!["c/", "a.txt", "b.txt"]);
+        assert_eq!(list(SortKey::Name, true), vec!["c/", "b.txt", "a.txt"]);
+        assert_eq!(list(SortKey::Size, true), vec!["c/", "a.txt", "b.txt"]);
+
+        assert_eq!(list_files_sorted("./.test/dir/a.txt".to_string(), SortKey::Name, false, false).unwrap_err().code, ERROR_NOT_A_DIRECTORY);
+    }
+
+    #[test]
+    fn test_init_canistorage() {
+        let _context = setup();
+        let owner = Principal::from_text("zebsi-6birt-enaic-v4hbv-zffiv-ft53g-u4gi3-og45y-tskzf-m6jus-xqe").unwrap(); // goddess x 12
+        let admin = Principal::from_text("aaikz-lv7jd-phj2u-t6r4n-6gne4-3rv3x-jus4j-zbiaz-llnsl-jvk5j-iqe").unwrap(); // actor x 12
+        let proxy = Principal::from_slice(&[1; 29]);
+
+        assert_eq!(init_canistorage(None, vec![], None).unwrap_err().code, ERROR_ALREADY_INITIALIZED);
+        assert!(is_initialized());
+        delete_file_info(&ROOT.to_string());
+        assert!(!is_initialized());
+
+        // anonymous
+        set_caller(Principal::anonymous());
+        assert_eq!(init_canistorage(Some(owner), vec![], None).unwrap_err().code, ERROR_PERMISSION_DENIED);
+        set_caller(proxy);
+        assert_eq!(init_canistorage(Some(Principal::anonymous()), vec![], None).unwrap_err().code, ERROR_PERMISSION_DENIED);
+        assert_eq!(init_canistorage(Some(owner), vec![Principal::anonymous()], None).unwrap_err().code, ERROR_PERMISSION_DENIED);
+
+        // provisioned by a proxy
+        assert!(!is_initialized());
+        assert!(init_canistorage(Some(owner), vec![admin, owner], None).is_ok());
+        assert!(is_initialized());
+        let info = get_file_info(&ROOT.to_string()).unwrap();
+        assert_eq!(info.creator, owner);
+        assert_eq!(info.readable, vec![owner]);
+        assert_eq!(info.writable, vec![owner]);
+        assert_eq!(info.manageable.len(), 2);
+        assert!(is_admin(&owner));
+        assert!(is_admin(&admin));
+        assert!(!is_admin(&proxy));
+        assert_eq!(init_canistorage(None, vec![], None).unwrap_err().code, ERROR_ALREADY_INITIALIZED);
+    }
+
+    #[test]
+    fn test_mount_root() {
+        let _context = setup();
+        let owner = caller();
+        delete_file_info(&ROOT.to_string());
+
+        // must be ROOT or under it
+        for root in ["/etc", "./.test/../mnt", "./.test/a//b", "./.test/`mnt"] {
+            assert_eq!(init_canistorage(None, vec![], Some(root.to_string())).unwrap_err().code, ERROR_INVALID_PATH);
+        }
+        assert!(get_file_info(&ROOT.to_string()).is_none());
+
+        assert!(init_canistorage(None, vec![], Some("./.test/mnt/".to_string())).is_ok());
+        assert_eq!(root(), "./.test/mnt");
+        assert!(is_admin(&owner));
+        assert_eq!(init_canistorage(None, vec![], None).unwrap_err().code, ERROR_ALREADY_INITIALIZED);
+
+        // paths are relative to the mounted root
+        assert_eq!(validate_path("/docs").unwrap(), "./.test/mnt/docs");
+        assert_eq!(validate_path("./.test/mnt").unwrap(), "./.test/mnt");
+        assert_eq!(validate_path("./.test/other").unwrap_err().code, ERROR_INVALID_PATH);
+        assert!(create_directory("/docs".to_string()).is_ok());
+        assert!(save("docs/a.txt".to_string(), "text/plain".to_string(), vec![0; 10], false).is_ok());
+        assert!(fs::exists("./.test/mnt/docs/a.txt").unwrap());
+        assert_eq!(parent_path(&"./.test/mnt/docs".to_string()), "./.test/mnt");
+        assert_eq!(parent_path(&"./.test/mnt".to_string()), "");
+        assert_eq!(list_files("/".to_string()).unwrap(), vec!["docs/"]);
+        assert_eq!(delete_directory("/".to_string(), true).unwrap_err().code, ERROR_INVALID_PATH);
+    }
+
+    #[test]
+    fn test_delete_non_empty_directory() {
+        let _context = setup();
+
+        let data = "Hello, World!".as_bytes().to_vec();
+        assert!(create_directory("./.test/dir".to_string()).is_ok());
+        assert!(save("./.test/dir/file.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+
+        assert_eq!(delete_directory("./.test/dir".to_string(), false).unwrap_err().code, ERROR_DIRECTORY_NOT_EMPTY);
+        assert!(get_file_info(&"./.test/dir".to_string()).is_some());
+        assert!(delete_directory("./.test/dir".to_string(), true).is_ok());
+    }
+
+    #[test]
+    fn test_delete_directory_partially() {
+        let _context = setup();
+
+        let data = "Hello, World!".as_bytes().to_vec();
+        assert!(create_directory("./.test/dir".to_string()).is_ok());
+        assert!(create_directory("./.test/dir/a".to_string()).is_ok());
+        assert!(create_directory("./.test/dir/b".to_string()).is_ok());
+        assert!(save("./.test/dir/a/1.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+        assert!(save("./.test/dir/b/2.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+        assert!(save("./.test/dir/b/3.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+
+        set_remove_failure(Some("./.test/dir/b/2.txt".to_string()));
+        let result = delete_directory("./.test/dir".to_string(), true);
+        set_remove_failure(None);
+        let error = result.unwrap_err();
+        assert_eq!(error.code, ERROR_UNKNOWN);
+        assert_eq!(error.message, "Couldn't delete: ./.test/dir/b/2.txt, ./.test/dir/b, ./.test/dir");
+
+        // the rest is deleted with the metadata
+        assert!(get_file_info(&"./.test/dir/a".to_string()).is_none());
+        assert!(get_file_info(&"./.test/dir/a/1.txt".to_string()).is_none());
+        assert!(get_file_info(&"./.test/dir/b/3.txt".to_string()).is_none());
+        assert_eq!(list_files("./.test/dir".to_string()).unwrap(), vec!["b/"]);
+        assert_eq!(list_files("./.test/dir/b".to_string()).unwrap(), vec!["2.txt"]);
+        assert_eq!(load("./.test/dir/b/2.txt".to_string(), 0, None).unwrap().chunk, data);
+
+        // retry
+        assert!(delete_directory("./.test/dir".to_string(), true).is_ok());
+        assert!(get_file_info(&"./.test/dir".to_string()).is_none());
+        assert!(get_file_info(&"./.test/dir/b/2.txt".to_string()).is_none());
+    }
+
+    #[test]
+    fn test_delete_directory_permission() {
+        let _context = setup();
+        let owner = caller();
+        let user = Principal::from_text("aaikz-lv7jd-phj2u-t6r4n-6gne4-3rv3x-jus4j-zbiaz-llnsl-jvk5j-iqe").unwrap(); // actor x 12
+
+        let data = "Hello, World!".as_bytes().to_vec();
+        assert!(create_directory("./.test/dir".to_string()).is_ok());
+        assert!(create_directory("./.test/dir/sub".to_string()).is_ok());
+        for path in ["./.test/dir/a.txt", "./.test/dir/b.txt", "./.test/dir/sub/c.txt", "./.test/dir/sub/d.txt"] {
+            assert!(save(path.to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+        }
+
+        // read permission isn't enough
+        assert!(add_permission("./.test/dir".to_string(), user, false, true, false).is_ok());
+        set_caller(user);
+        assert_eq!(delete_directory("./.test/dir".to_string(), true).unwrap_err().code, ERROR_PERMISSION_DENIED);
+        set_caller(owner);
+        assert_eq!(list_files("./.test/dir".to_string()).unwrap(), vec!["a.txt", "b.txt", "sub/"]);
+
+        // denied or locked descendants are left
+        assert!(add_permission("./.test/dir".to_string(), user, false, false, true).is_ok());
+        assert!(deny_permission("./.test/dir/a.txt".to_string(), user, false, true).is_ok());
+        assert!(lock("./.test/dir/sub/c.txt".to_string()).is_ok());
+        set_caller(user);
+        let error = delete_directory("./.test/dir".to_string(), true).unwrap_err();
+        set_caller(owner);
+        assert_eq!(error.code, ERROR_UNKNOWN);
+        assert!(error.message.contains("./.test/dir/a.txt"));
+        assert!(error.message.contains("./.test/dir/sub/c.txt"));
+        assert!(!error.message.contains("b.txt"));
+        assert_eq!(list_files("./.test/dir".to_string()).unwrap(), vec!["a.txt", "sub/"]);
+        assert_eq!(list_files("./.test/dir/sub".to_string()).unwrap(), vec!["c.txt"]);
+
+        // the lock holder can delete them
+        assert!(delete_directory("./.test/dir".to_string(), true).is_ok());
+        assert!(get_file_info(&"./.test/dir".to_string()).is_none());
+    }
+
+    #[test]
+    fn test_delete_root() {
+        let _context = setup();
+
+        assert!(create_directory("./.test/dir".to_string()).is_ok());
+        assert_eq!(delete_directory(ROOT.to_string(), true).unwrap_err().code, ERROR_INVALID_PATH);
+        assert_eq!(delete_directory(ROOT.to_string(), false).unwrap_err().code, ERROR_INVALID_PATH);
+        assert!(get_file_info(&ROOT.to_string()).is_some());
+        assert_eq!(list_files(ROOT.to_string()).unwrap(), vec!["dir/"]);
+    }
+
+    #[cfg(feature = "poc")]
+    #[test]
+    fn test_force_reset_for_poc() {
+        let _context = setup();
+        let user = Principal::from_text("aaikz-lv7jd-phj2u-t6r4n-6gne4-3rv3x-jus4j-zbiaz-llnsl-jvk5j-iqe").unwrap(); // actor x 12
+        let owner = caller();
+        assert!(save("./.test/file.txt".to_string(), "text/plain".to_string(), vec![0; 3], false).is_ok());
+
+        // admin only
+        for principal in [Principal::anonymous(), user] {
+            set_caller(principal);
+            assert_eq!(force_reset_for_poc().unwrap_err().code, ERROR_PERMISSION_DENIED);
+        }
+        set_caller(owner);
+        assert!(get_file_info(&"./.test/file.txt".to_string()).is_some());
+        assert!(force_reset_for_poc().is_ok());
+        assert!(get_file_info(&"./.test/file.txt".to_string()).is_none());
+    }
+
+    #[test]
+    fn test_reset() {
+        let _context = setup();
+        let owner = Principal::from_text("zebsi-6birt-enaic-v4hbv-zffiv-ft53g-u4gi3-og45y-tskzf-m6jus-xqe").unwrap(); // goddess x 12
+        let user = Principal::from_text("aaikz-lv7jd-phj2u-t6r4n-6gne4-3rv3x-jus4j-zbiaz-llnsl-jvk5j-iqe").unwrap(); // actor x 12
+        let canister = canister_id().to_text();
+
+        let data = "Hello, World!".as_bytes().to_vec();
+        assert!(create_directory("./.test/dir".to_string()).is_ok());
+        assert!(save("./.test/dir/file.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+        assert!(lock("./.test/dir/file.txt".to_string()).is_ok());
+        assert!(begin_upload("./.test/upload.txt".to_string(), "text/plain".to_string(), false, data.len() as u64).is_ok());
+
+        // admin only, with the canister id
+        set_caller(user);
+        assert_eq!(reset(canister.clone()).unwrap_err().code, ERROR_PERMISSION_DENIED);
+        set_caller(owner);
+        assert_eq!(reset("aaaaa-aa".to_string()).unwrap_err().code, ERROR_PERMISSION_DENIED);
+        assert_eq!(list_files(ROOT.to_string()).unwrap(), vec!["dir/"]);
+
+        assert!(reset(canister).is_ok());
+        assert!(list_files(ROOT.to_string()).unwrap().is_empty());
+        assert!(get_file_info(&"./.test/dir".to_string()).is_none());
+        assert!(who_holds_lock("./.test/dir/file.txt".to_string()).unwrap().is_none());
+        assert_eq!(send_data("./.test/upload.txt".to_string(), 0, data.clone()).unwrap_err().code, ERROR_INVALID_SEQUENCE);
+        assert!(is_admin(&owner));
+        assert!(create_directory("./.test/dir".to_string()).is_ok());
+    }
+
+    #[test]
+    fn test_sha256_hex() {
+        let _context = setup();
+
+        let data = "Hello, World!".as_bytes().to_vec();
+        let hex = "dffd6021bb2bd5b0af676290809ec3a53191dd81c7f70a4b28688a362182986f";
+        assert!(create_directory("./.test/dir".to_string()).is_ok());
+        assert!(save("./.test/dir/file.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+
+        assert_eq!(sha256_hex("./.test/dir/file.txt".to_string()).unwrap().unwrap(), hex);
+        assert_eq!(get_info("./.test/dir/file.txt".to_string()).unwrap().sha256_hex.unwrap(), hex);
+        assert_eq!(load("./.test/dir/file.txt".to_string(), 0, None).unwrap().sha256_hex.unwrap(), hex);
+        assert!(load("./.test/dir/file.txt".to_string(), 0, Some(1)).unwrap().sha256_hex.is_none());
+        assert!(get_info("./.test/dir".to_string()).unwrap().sha256_hex.is_none());
+        assert_eq!(sha256_hex("./.test/dir".to_string()).unwrap_err().code, ERROR_IS_DIRECTORY);
+        assert_eq!(sha256_hex("./.test/none".to_string()).unwrap_err().code, ERROR_NOT_FOUND);
+    }
+
+    #[test]
+    fn test_http_directory_index() {
+        let _context = setup();
+        let get = |url:&str| http_request(HttpRequest {
+            method: "GET".to_string(),
+            url: url.to_string(),
+            headers: vec![],
+            body: vec![],
+        });
+
+        let data = "Hello, World!".as_bytes().to_vec();
+        assert!(create_directory("./.test/pub".to_string()).is_ok());
+        assert!(create_directory("./.test/pub/sub dir".to_string()).is_ok());
+        assert!(save("./.test/pub/a \"b\".txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+        assert!(add_permission("./.test/pub".to_string(), Principal::anonymous(), false, true, false).is_ok());
+
+        let response = get("/pub/?v=1");
+        assert_eq!(response.status_code, 200);
+        assert!(response.headers.contains(&("Content-Type".to_string(), "application/json".to_string())));
+        assert_eq!(
+            String::from_utf8(response.body).unwrap(),
+            r#"[{"name":"a \"b\".txt","is_dir":false,"size":13,"mimetype":"text/plain"},{"name":"sub dir","is_dir":true,"size":0,"mimetype":"canistorage/directory"}]"#
+        );
+        assert_eq!(String::from_utf8(get("/pub/sub%20dir/").body).unwrap(), "[]");
+
+        assert_eq!(get("/").status_code, 403); // not public
+        assert_eq!(get("/none/").status_code, 403);
+        assert_eq!(get("/pub/none/").status_code, 404);
+        assert_eq!(get("/pub/a%20%22b%22.txt/").status_code, 404); // not a directory
+        assert_eq!(get("/pub/../").status_code, 400);
+    }
+
+    #[test]
+    fn test_http_download() {
+        let _context = setup();
+        let get = |url:&str| http_request(HttpRequest {
+            method: "GET".to_string(),
+            url: url.to_string(),
+            headers: vec![],
+            body: vec![],
+        });
+        let header = |response:&HttpResponse, name:&str| response.headers.iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.clone());
+
+        let data = "Hello, World!".as_bytes().to_vec();
+        assert!(create_directory("./.test/pub".to_string()).is_ok());
+        assert!(save("./.test/pub/a \"b\".txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+        assert!(save("./.test/pub/日本.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+        assert!(add_permission("./.test/pub".to_string(), Principal::anonymous(), false, true, false).is_ok());
+
+        // inline
+        let response = get("/pub/a%20%22b%22.txt");
+        assert_eq!(response.status_code, 200);
+        assert_eq!(response.body, data);
+        assert_eq!(header(&response, "Content-Type").unwrap(), "text/plain");
+        assert!(header(&response, "Content-Disposition").is_none());
+        assert!(header(&get("/pub/a%20%22b%22.txt?download=0"), "Content-Disposition").is_none());
+
+        // download
+        let response = get("/pub/a%20%22b%22.txt?v=2&download=1");
+        assert_eq!(response.body, data);
+        assert_eq!(header(&response, "Content-Disposition").unwrap(), r#"attachment; filename="a \"b\".txt"; filename*=UTF-8''a%20%22b%22.txt"#);
+        let response = get("/pub/%E6%97%A5%E6%9C%AC.txt?download=1");
+        assert_eq!(header(&response, "Content-Disposition").unwrap(), "attachment; filename=\"__.txt\"; filename*=UTF-8''%E6%97%A5%E6%9C%AC.txt");
+
+        assert_eq!(get("/pub").status_code, 404); // directory without '/'
+        assert_eq!(get("/pub/none.txt").status_code, 404);
+        assert!(save("./.test/secret.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+        assert_eq!(get("/secret.txt?download=1").status_code, 403);
+    }
+
+    #[test]
+    fn test_http_sniff() {
+        let _context = setup();
+        let content_type = |url:&str| http_request(HttpRequest {
+            method: "GET".to_string(),
+            url: url.to_string(),
+            headers: vec![],
+            body: vec![],
+        }).headers.iter().find(|(key, _)| key == "Content-Type").map(|(_, value)| value.clone()).unwrap();
+
+        assert!(create_directory("./.test/pub".to_string()).is_ok());
+        assert!(add_permission("./.test/pub".to_string(), Principal::anonymous(), false, true, false).is_ok());
+        let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec();
+        assert!(save("./.test/pub/image".to_string(), MIMETYPE_OCTET_STREAM.to_string(), png.clone(), false).is_ok());
+        assert!(save("./.test/pub/doc".to_string(), MIMETYPE_OCTET_STREAM.to_string(), b"%PDF-1.7".to_vec(), false).is_ok());
+        assert!(save("./.test/pub/data".to_string(), MIMETYPE_OCTET_STREAM.to_string(), b"data".to_vec(), false).is_ok());
+        assert!(save("./.test/pub/typed".to_string(), "text/plain".to_string(), png, false).is_ok());
+
+        assert_eq!(content_type("/pub/image"), "image/png");
+        assert_eq!(content_type("/pub/doc"), "application/pdf");
+        assert_eq!(content_type("/pub/data"), MIMETYPE_OCTET_STREAM);
+        assert_eq!(content_type("/pub/typed"), "text/plain"); // the stored mimetype wins
+
+        assert_eq!(sniff_mimetype(b"\xff\xd8\xff\xe0"), "image/jpeg");
+        assert_eq!(sniff_mimetype(b"\x1f\x8b\x08"), "application/gzip");
+        assert_eq!(sniff_mimetype(b"RIFF\0\0\0\0WEBPVP8 "), "image/webp");
+        assert_eq!(sniff_mimetype(b""), MIMETYPE_OCTET_STREAM);
+    }
+
+    #[test]
+    fn test_http_cors() {
+        let _context = setup();
+        let request = |method:&str, origin:&str| http_request(HttpRequest {
+            method: method.to_string(),
+            url: "/pub/".to_string(),
+            headers: vec![("origin".to_string(), origin.to_string())],
+            body: vec![],
+        });
+        let header = |response:&HttpResponse, name:&str| response.headers.iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.clone());
+
+        assert!(create_directory("./.test/pub".to_string()).is_ok());
+        assert!(add_permission("./.test/pub".to_string(), Principal::anonymous(), false, true, false).is_ok());
+
+        // same-origin only by default
+        assert!(get_cors_origins().is_empty());
+        let response = request("GET", "https://example.com");
+        assert_eq!(response.status_code, 200);
+        assert!(header(&response, "Access-Control-Allow-Origin").is_none());
+
+        // admin only
+        set_caller(Principal::from_text("aaikz-lv7jd-phj2u-t6r4n-6gne4-3rv3x-jus4j-zbiaz-llnsl-jvk5j-iqe").unwrap()); // actor x 12
+        assert_eq!(set_cors_origins(vec!["*".to_string()]).unwrap_err().code, ERROR_PERMISSION_DENIED);
+        set_caller(Principal::from_text("zebsi-6birt-enaic-v4hbv-zffiv-ft53g-u4gi3-og45y-tskzf-m6jus-xqe").unwrap()); // goddess x 12
+        assert!(set_cors_origins(vec!["https://example.com".to_string()]).is_ok());
+        assert_eq!(get_cors_origins(), vec!["https://example.com"]);
+
+        let response = request("GET", "https://example.com");
+        assert_eq!(header(&response, "Access-Control-Allow-Origin").unwrap(), "https://example.com");
+        assert_eq!(header(&response, "Vary").unwrap(), "Origin");
+        assert!(header(&request("GET", "https://evil.example"), "Access-Control-Allow-Origin").is_none());
+
+        // preflight
+        let response = request("OPTIONS", "https://example.com");
+        assert_eq!(response.status_code, 204);
+        assert!(response.body.is_empty());
+        assert_eq!(header(&response, "Access-Control-Allow-Methods").unwrap(), "GET, HEAD, OPTIONS");
+        assert!(header(&response, "Access-Control-Allow-Headers").is_some());
+
+        // any origin
+        assert!(set_cors_origins(vec!["*".to_string()]).is_ok());
+        assert_eq!(header(&request("GET", "https://evil.example"), "Access-Control-Allow-Origin").unwrap(), "*");
+        assert!(set_cors_origins(vec![]).is_ok());
+    }
+
+    #[test]
+    fn test_base64() {
+        let _context = setup();
+
+        let data = "Hello, World!".as_bytes().to_vec();
+        assert!(create_directory("./.test/dir".to_string()).is_ok());
+        let sha256 = save_base64("./.test/dir/file.txt".to_string(), "text/plain".to_string(), "SGVsbG8sIFdvcmxkIQ==".to_string(), false).unwrap();
+        assert_eq!(sha256, <[u8; 32]>::from(Sha256::digest(&data)));
+        assert_eq!(load("./.test/dir/file.txt".to_string(), 0, None).unwrap().chunk, data);
+
+        let (chunk, downloaded_at, sha256_hex) = load_base64("./.test/dir/file.txt".to_string(), 0).unwrap();
+        assert_eq!(chunk, "SGVsbG8sIFdvcmxkIQ==");
+        assert_eq!(downloaded_at, data.len() as u64);
+        assert_eq!(sha256_hex.unwrap(), to_hex(&sha256));
+        let (chunk, downloaded_at, sha256_hex) = load_base64("./.test/dir/file.txt".to_string(), 7).unwrap();
+        assert_eq!(chunk, "V29ybGQh"); // "World!"
+        assert_eq!(downloaded_at, data.len() as u64);
+        assert!(sha256_hex.is_some());
+
+        assert_eq!(save_base64("./.test/dir/bad.txt".to_string(), "text/plain".to_string(), "SGVsbG8*".to_string(), false).unwrap_err().code, ERROR_INVALID_ENCODING);
+        assert!(get_file_info(&"./.test/dir/bad.txt".to_string()).is_none());
+        assert_eq!(load_base64("./.test/dir/none.txt".to_string(), 0).unwrap_err().code, ERROR_NOT_FOUND);
+    }
+
+    #[test]
+    fn test_has_active_uploads() {
+        let _context = setup();
+
+        let data = "Hello, World!".as_bytes().to_vec();
+        assert!(!has_active_uploads());
+        assert!(begin_upload("./.test/a.txt".to_string(), "text/plain".to_string(), false, data.len() as u64).is_ok());
+        assert!(begin_upload("./.test/b.txt".to_string(), "text/plain".to_string(), false, data.len() as u64).is_ok());
+        assert!(has_active_uploads());
+        assert_eq!(active_upload_count(), 2);
+
+        // expired sessions are not counted
+        UPLOADING.with(|uploading| uploading.borrow_mut().get_mut("./.test/b.txt").unwrap().updated_at = 0);
+        assert_eq!(active_upload_count(), 1);
+
+        assert!(cancel_upload("./.test/a.txt".to_string()).is_ok());
+        assert!(!has_active_uploads());
+    }
+
+    #[test]
+    fn test_limits() {
+        let limits = limits();
+        assert_eq!(limits.max_read_size, MAX_READ_SIZE as u64);
+        assert_eq!(limits.max_file_size, MAX_FILE_SIZE);
+        assert_eq!(limits.max_path, MAX_PATH as u64);
+        assert_eq!(limits.max_name, MAX_NAME as u64);
+        assert_eq!(limits.max_depth, MAX_DEPTH as u64);
+        assert_eq!(limits.chunk_size, CHUNK_SIZE);
+        assert_eq!(limits.upload_timeout_ms, UPLOAD_TIMEOUT);
+        assert_eq!(limits.max_batch_size, MAX_BATCH_SIZE as u64);
+        assert_eq!(limits.max_signature_size, MAX_SIGNATURE_SIZE as u64);
+        assert!(limits.max_dir_entries.is_none());
+    }
+
+    #[test]
+    fn test_upload_status() {
+        let _context = setup();
+
+        let data = "Hello, World!".as_bytes().to_vec();
+        let upload = begin_upload("./.test/a.txt".to_string(), "text/plain".to_string(), false, data.len() as u64).unwrap();
+        assert!(upload.expires_at >= time() + UPLOAD_TIMEOUT - 1000);
+
+        assert!(send_data("./.test/a.txt".to_string(), 0, data[..5].to_vec()).is_ok());
+        let status = upload_status("./.test/a.txt".to_string()).unwrap();
+        assert_eq!(status.size, 5);
+        assert_eq!(status.expected_size, data.len() as u64);
+        assert!(status.expires_at >= upload.expires_at);
+        assert!(status.remaining_ms <= UPLOAD_TIMEOUT);
+        assert!(status.remaining_ms > UPLOAD_TIMEOUT - 1000);
+
+        // others' or no session
+        set_caller(Principal::from_text("aaikz-lv7jd-phj2u-t6r4n-6gne4-3rv3x-jus4j-zbiaz-llnsl-jvk5j-iqe").unwrap()); // actor x 12
+        assert_eq!(upload_status("./.test/a.txt".to_string()).unwrap_err().code, ERROR_INVALID_SEQUENCE);
+        set_caller(Principal::from_text("zebsi-6birt-enaic-v4hbv-zffiv-ft53g-u4gi3-og45y-tskzf-m6jus-xqe").unwrap()); // goddess x 12
+        assert_eq!(upload_status("./.test/none.txt".to_string()).unwrap_err().code, ERROR_INVALID_SEQUENCE);
+
+        // expired
+        UPLOADING.with(|uploading| uploading.borrow_mut().get_mut("./.test/a.txt").unwrap().updated_at = 0);
+        assert_eq!(upload_status("./.test/a.txt".to_string()).unwrap_err().code, ERROR_PERMISSION_DENIED);
+    }
+
+    #[test]
+    fn test_probe_foreign_upload() {
+        let _context = setup();
+        let owner = Principal::from_text("zebsi-6birt-enaic-v4hbv-zffiv-ft53g-u4gi3-og45y-tskzf-m6jus-xqe").unwrap(); // goddess x 12
+        let other = Principal::from_text("aaikz-lv7jd-phj2u-t6r4n-6gne4-3rv3x-jus4j-zbiaz-llnsl-jvk5j-iqe").unwrap(); // actor x 12
+
+        let data = "Hello, World!".as_bytes().to_vec();
+        assert!(begin_upload("./.test/a.txt".to_string(), "text/plain".to_string(), false, data.len() as u64).is_ok());
+        assert!(send_data("./.test/a.txt".to_string(), 0, data.clone()).is_ok());
+
+        // a foreign session looks the same as no session
+        set_caller(other);
+        let same = |foreign:Error, none:Error| {
+            assert_eq!(foreign.code, ERROR_INVALID_SEQUENCE);
+            assert_eq!((foreign.code, foreign.message), (none.code, none.message));
+        };
+        same(send_data("./.test/a.txt".to_string(), 0, data.clone()).unwrap_err(), send_data("./.test/b.txt".to_string(), 0, data.clone()).unwrap_err());
+        same(commit_upload("./.test/a.txt".to_string(), data.len() as u64, None).unwrap_err(), commit_upload("./.test/b.txt".to_string(), data.len() as u64, None).unwrap_err());
+        same(upload_status("./.test/a.txt".to_string()).unwrap_err(), upload_status("./.test/b.txt".to_string()).unwrap_err());
+        same(cancel_upload("./.test/a.txt".to_string()).unwrap_err(), cancel_upload("./.test/b.txt".to_string()).unwrap_err());
+
+        // even after the session expired
+        UPLOADING.with(|uploading| uploading.borrow_mut().get_mut("./.test/a.txt").unwrap().updated_at = 0);
+        same(send_data("./.test/a.txt".to_string(), 0, data.clone()).unwrap_err(), send_data("./.test/b.txt".to_string(), 0, data.clone()).unwrap_err());
+        same(commit_upload("./.test/a.txt".to_string(), data.len() as u64, None).unwrap_err(), commit_upload("./.test/b.txt".to_string(), data.len() as u64, None).unwrap_err());
+        UPLOADING.with(|uploading| uploading.borrow_mut().get_mut("./.test/a.txt").unwrap().updated_at = time());
+
+        // the session is intact
+        set_caller(owner);
+        assert_eq!(upload_status("./.test/a.txt".to_string()).unwrap().size, data.len() as u64);
+        assert!(commit_upload("./.test/a.txt".to_string(), data.len() as u64, None).is_ok());
+    }
+
+    #[test]
+    fn test_upload_with_chunks() {
+        let _context = setup();
+
+        let data = "Hello, World!".as_bytes().to_vec();
+        let size = data.len() as u64;
+        let path = "./.test/a.txt".to_string();
+
+        // invalid layouts
+        let begin = |offsets:Vec<u64>| begin_upload_with_chunks(path.clone(), "text/plain".to_string(), false, size, offsets);
+        assert_eq!(begin(vec![]).unwrap_err().code, ERROR_INVALID_SEQUENCE);
+        assert_eq!(begin(vec![1, 5]).unwrap_err().code, ERROR_INVALID_SEQUENCE);
+        assert_eq!(begin(vec![0, 5, 5]).unwrap_err().code, ERROR_INVALID_SEQUENCE);
+        assert_eq!(begin(vec![0, 5, size]).unwrap_err().code, ERROR_INVALID_SEQUENCE);
+        assert_eq!(begin_upload_with_chunks(path.clone(), "text/plain".to_string(), false, CHUNK_SIZE + 1, vec![0]).unwrap_err().code, ERROR_INVALID_SIZE);
+
+        let upload = begin(vec![0, 5, 10]).unwrap();
+        assert_eq!(upload.chunk_count, 3);
+
+        // only declared chunks, once each
+        assert_eq!(send_data(path.clone(), 3, data[3..5].to_vec()).unwrap_err().code, ERROR_INVALID_SEQUENCE);
+        assert_eq!(send_data(path.clone(), 0, data[0..4].to_vec()).unwrap_err().code, ERROR_INVALID_SIZE);
+        assert!(send_data(path.clone(), 0, data[0..5].to_vec()).is_ok());
+        assert_eq!(send_data(path.clone(), 0, data[0..5].to_vec()).unwrap_err().code, ERROR_INVALID_SEQUENCE);
+        assert!(send_data(path.clone(), 10, data[10..].to_vec()).is_ok());
+
+        // missing chunk is named
+        let result = commit_upload(path.clone(), size, None);
+        assert_eq!(result.as_ref().unwrap_err().code, ERROR_INVALID_SEQUENCE);
+        assert!(result.unwrap_err().message.contains("at 5"));
+
+        assert!(send_data(path.clone(), 5, data[5..10].to_vec()).is_ok());
+        assert!(commit_upload(path.clone(), size, None).is_ok());
+        assert_eq!(load(path.clone(), 0, None).unwrap().chunk, data);
+
+        // empty file
+        assert!(begin_upload_with_chunks("./.test/empty.txt".to_string(), "text/plain".to_string(), false, 0, vec![]).is_ok());
+        assert!(commit_upload("./.test/empty.txt".to_string(), 0, None).is_ok());
+    }
+
+    #[test]
+    fn test_mimetype_policy() {
+        let _context = setup();
+        let data = "Hello, World!".as_bytes().to_vec();
+        let policy = |allow:&[&str], deny:&[&str]| MimetypePolicy {
+            allow: allow.iter().map(|rule| rule.to_string()).collect(),
+            deny: deny.iter().map(|rule| rule.to_string()).collect(),
+        };
+
+        // allow-all by default
+        assert!(get_mimetype_policy().allow.is_empty());
+        assert!(save("./.test/a.exe".to_string(), "application/x-msdownload".to_string(), data.clone(), false).is_ok());
+
+        // admin only
+        set_caller(Principal::from_text("aaikz-lv7jd-phj2u-t6r4n-6gne4-3rv3x-jus4j-zbiaz-llnsl-jvk5j-iqe").unwrap()); // actor x 12
+        assert_eq!(set_mimetype_policy(policy(&[], &["application/x-msdownload"])).unwrap_err().code, ERROR_PERMISSION_DENIED);
+        set_caller(Principal::from_text("zebsi-6birt-enaic-v4hbv-zffiv-ft53g-u4gi3-og45y-tskzf-m6jus-xqe").unwrap()); // goddess x 12
+
+        // deny
+        assert!(set_mimetype_policy(policy(&[], &["application/x-msdownload"])).is_ok());
+        assert_eq!(save("./.test/b.exe".to_string(), "Application/X-MSDownload".to_string(), data.clone(), false).unwrap_err().code, ERROR_INVALID_MIMETYPE);
+        assert_eq!(begin_upload("./.test/b.exe".to_string(), "application/x-msdownload".to_string(), false, 0).unwrap_err().code, ERROR_INVALID_MIMETYPE);
+        assert_eq!(touch("./.test/b.exe".to_string(), "application/x-msdownload".to_string()).unwrap_err().code, ERROR_INVALID_MIMETYPE);
+        assert!(save("./.test/b.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+
+        // allow with prefix (deny takes precedence)
+        assert!(set_mimetype_policy(policy(&["image/*", "text/plain"], &["image/svg+xml"])).is_ok());
+        assert!(save("./.test/c.png".to_string(), "image/png".to_string(), data.clone(), false).is_ok());
+        assert!(save("./.test/c.txt".to_string(), "text/plain; charset=utf-8".to_string(), data.clone(), false).is_ok());
+        assert_eq!(save("./.test/c.html".to_string(), "text/html".to_string(), data.clone(), false).unwrap_err().code, ERROR_INVALID_MIMETYPE);
+        assert_eq!(save("./.test/c.svg".to_string(), "image/svg+xml".to_string(), data.clone(), false).unwrap_err().code, ERROR_INVALID_MIMETYPE);
+        assert!(create_directory("./.test/dir".to_string()).is_ok()); // directories aren't affected
+
+        assert!(set_mimetype_policy(policy(&[], &[])).is_ok());
+    }
+
+    #[test]
+    fn test_rename_failure() {
+        let _context = setup();
+        let old = "Hello, World!".as_bytes().to_vec();
+        let new = "Goodbye, World!".as_bytes().to_vec();
+        assert!(save("./.test/file.txt".to_string(), "text/plain".to_string(), old.clone(), false).is_ok());
+
+        // save
+        set_rename_failure(true);
+        let result = save("./.test/file.txt".to_string(), "text/plain".to_string(), new.clone(), true);
+        set_rename_failure(false);
+        assert_eq!(result.unwrap_err().code, ERROR_UNKNOWN);
+        assert_eq!(load("./.test/file.txt".to_string(), 0, None).unwrap().chunk, old);
+        assert_eq!(get_info("./.test/file.txt".to_string()).unwrap().size, old.len() as u64);
+        assert!(!fs::exists(temp_path(&"./.test/file.txt".to_string())).unwrap());
+
+        // commit_upload
+        assert!(begin_upload("./.test/file.txt".to_string(), "text/plain".to_string(), true, new.len() as u64).is_ok());
+        assert!(send_data("./.test/file.txt".to_string(), 0, new.clone()).is_ok());
+        set_rename_failure(true);
+        let result = commit_upload("./.test/file.txt".to_string(), new.len() as u64, None);
+        set_rename_failure(false);
+        assert_eq!(result.unwrap_err().code, ERROR_UNKNOWN);
+        assert_eq!(load("./.test/file.txt".to_string(), 0, None).unwrap().chunk, old);
+        assert!(!fs::exists(temp_path(&"./.test/file.txt".to_string())).unwrap());
+        assert!(!is_uploading(&"./.test/file.txt".to_string()));
+
+        // a new file isn't created
+        set_rename_failure(true);
+        let result = save("./.test/new.txt".to_string(), "text/plain".to_string(), new.clone(), false);
+        set_rename_failure(false);
+        assert_eq!(result.unwrap_err().code, ERROR_UNKNOWN);
+        assert_eq!(get_info("./.test/new.txt".to_string()).unwrap_err().code, ERROR_NOT_FOUND);
+    }
+
+    #[test]
+    fn test_empty_file_sha256() {
+        let _context = setup();
+        let empty:[u8; 32] = Sha256::digest([]).into();
+
+        assert_eq!(save("./.test/save.txt".to_string(), "text/plain".to_string(), vec![], false).unwrap(), empty);
+        assert_eq!(get_info("./.test/save.txt".to_string()).unwrap().sha256, Some(empty));
+
+        assert!(begin_upload("./.test/upload.txt".to_string(), "text/plain".to_string(), false, 0).is_ok());
+        assert!(commit_upload("./.test/upload.txt".to_string(), 0, None).is_ok());
+        assert_eq!(get_info("./.test/upload.txt".to_string()).unwrap().sha256, Some(empty));
+
+        assert!(touch("./.test/touch.txt".to_string(), "text/plain".to_string()).is_ok());
+        assert_eq!(get_info("./.test/touch.txt".to_string()).unwrap().sha256, Some(empty));
+
+        assert!(create_directory("./.test/dir".to_string()).is_ok());
+        assert_eq!(get_info("./.test/dir".to_string()).unwrap().sha256, None);
+    }
+
+    #[test]
+    fn test_load_with_prefix_hash() {
+        let _context = setup();
+
+        let data = "Hello, World!".as_bytes().to_vec();
+        assert!(save("./.test/file.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+
+        let download = load_with_prefix_hash("./.test/file.txt".to_string(), 0, Some(5)).unwrap();
+        assert_eq!(download.chunk, b"Hello");
+        assert_eq!(download.sha256, None);
+        assert_eq!(download.prefix_sha256, Some(Sha256::digest(b"Hello").into()));
+
+        let download = load_with_prefix_hash("./.test/file.txt".to_string(), 5, Some(2)).unwrap();
+        assert_eq!(download.prefix_sha256, Some(Sha256::digest(b"Hello, ").into()));
+
+        // end of file
+        let download = load_with_prefix_hash("./.test/file.txt".to_string(), 7, None).unwrap();
+        assert_eq!(download.prefix_sha256, Some(Sha256::digest(&data).into()));
+        assert_eq!(download.prefix_sha256, download.sha256);
+
+        // plain load doesn't hash the prefix
+        assert_eq!(load("./.test/file.txt".to_string(), 0, Some(5)).unwrap().prefix_sha256, None);
+
+        assert_eq!(load_with_prefix_hash("./.test/none.txt".to_string(), 0, None).unwrap_err().code, ERROR_NOT_FOUND);
+
+        // too long to hash (sparse file)
+        let path = "./.test/large.bin".to_string();
+        assert!(save(path.clone(), "application/octet-stream".to_string(), data.clone(), false).is_ok());
+        let size = MAX_PREFIX_HASH_SIZE + 10;
+        File::options().write(true).open(&path).unwrap().set_len(size).unwrap();
+        let mut info = get_file_info(&path).unwrap();
+        info.size = size;
+        set_file_info(&path, &info).unwrap();
+        assert!(load_with_prefix_hash(path.clone(), MAX_PREFIX_HASH_SIZE - 5, Some(5)).is_ok());
+        let result = load_with_prefix_hash(path.clone(), MAX_PREFIX_HASH_SIZE - 5, Some(6));
+        assert_eq!(result.unwrap_err().code, ERROR_INVALID_SIZE);
+        assert!(load(path.clone(), MAX_PREFIX_HASH_SIZE - 5, Some(6)).is_ok());
+    }
+
+    #[test]
+    fn test_create_file() {
+        let _context = setup();
+
+        let data = "Hello, World!".as_bytes().to_vec();
+        assert!(create_file("./.test/file.txt".to_string(), "text/plain".to_string(), data.clone()).is_ok());
+        assert_eq!(load("./.test/file.txt".to_string(), 0, None).unwrap().chunk, data);
+
+        // already exists
+        let result = create_file("./.test/file.txt".to_string(), "text/plain".to_string(), b"new".to_vec());
+        assert_eq!(result.unwrap_err().code, ERROR_ALREADY_EXISTS);
+        assert_eq!(load("./.test/file.txt".to_string(), 0, None).unwrap().chunk, data);
+        assert!(create_directory("./.test/dir".to_string()).is_ok());
+        let result = create_file("./.test/dir".to_string(), "text/plain".to_string(), data.clone());
+        assert_eq!(result.unwrap_err().code, ERROR_ALREADY_EXISTS);
+
+        // parent not found
+        let result = create_file("./.test/none/file.txt".to_string(), "text/plain".to_string(), data);
+        assert_eq!(result.unwrap_err().code, ERROR_NOT_FOUND);
+    }
+
+    #[test]
+    fn test_quota() {
+        let _context = setup();
+        let owner = caller();
+        let user = Principal::from_text("aaikz-lv7jd-phj2u-t6r4n-6gne4-3rv3x-jus4j-zbiaz-llnsl-jvk5j-iqe").unwrap(); // actor x 12
+        assert!(create_directory("./.test/dir".to_string()).is_ok());
+        assert!(add_permission("./.test/dir".to_string(), user, false, true, true).is_ok());
+
+        // admin only
+        set_caller(user);
+        assert_eq!(set_quota(user, Some(100)).unwrap_err().code, ERROR_PERMISSION_DENIED);
+        assert_eq!(get_quota(user).unwrap_err().code, ERROR_PERMISSION_DENIED);
+        set_caller(owner);
+        assert_eq!(get_quota(user).unwrap(), None);
+        assert!(set_quota(user, Some(100)).is_ok());
+        assert_eq!(get_quota(user).unwrap(), Some(100));
+
+        set_caller(user);
+        assert!(save("./.test/dir/a.txt".to_string(), "text/plain".to_string(), vec![0; 60], false).is_ok());
+        assert_eq!(usage(&user), 60);
+        let result = save("./.test/dir/b.txt".to_string(), "text/plain".to_string(), vec![0; 41], false);
+        assert_eq!(result.unwrap_err().code, ERROR_QUOTA_EXCEEDED);
+        // overwriting own file replaces its bytes
+        assert!(save("./.test/dir/a.txt".to_string(), "text/plain".to_string(), vec![0; 100], true).is_ok());
+        assert_eq!(usage(&user), 100);
+        assert!(delete("./.test/dir/a.txt".to_string()).is_ok());
+        assert_eq!(usage(&user), 0);
+
+        // declared size is rejected by beginUpload
+        let result = begin_upload("./.test/dir/c.bin".to_string(), "application/octet-stream".to_string(), false, 101);
+        assert_eq!(result.unwrap_err().code, ERROR_QUOTA_EXCEEDED);
+
+        // chunks are rejected as soon as the running total exceeds the remaining quota
+        assert!(begin_upload("./.test/dir/c.bin".to_string(), "application/octet-stream".to_string(), false, 80).is_ok());
+        assert!(send_data("./.test/dir/c.bin".to_string(), 0, vec![0; 40]).is_ok());
+        set_caller(owner);
+        assert!(set_quota(user, Some(50)).is_ok());
+        set_caller(user);
+        let result = send_data("./.test/dir/c.bin".to_string(), 40, vec![0; 40]);
+        assert_eq!(result.unwrap_err().code, ERROR_QUOTA_EXCEEDED);
+        assert!(!is_uploading(&"./.test/dir/c.bin".to_string())); // evicted
+        assert_eq!(usage(&user), 0);
+
+        // other principals aren't limited
+        set_caller(owner);
+        assert!(save("./.test/dir/d.txt".to_string(), "text/plain".to_string(), vec![0; 200], false).is_ok());
+        assert_eq!(usage(&owner), 200);
+        rebuild_usage();
+        assert_eq!(usage(&owner), 200);
+
+        // built once on upgrade
+        USAGE.with(|usage| usage.borrow_mut().clear_new());
+        build_usage();
+        assert_eq!(usage(&owner), 200);
+        USAGE.with(|usage| usage.borrow_mut().clear_new());
+        build_usage(); // kept in stable memory, so not walked again
+        assert_eq!(usage(&owner), 0);
+        rebuild_usage();
+
+        assert!(set_quota(user, None).is_ok());
+        assert_eq!(get_quota(user).unwrap(), None);
+
+        // own usage and quota
+        assert_eq!(my_quota(), (200, u64::MAX));
+        assert!(set_quota(user, Some(100)).is_ok());
+        set_caller(user);
+        assert!(save("./.test/dir/e.txt".to_string(), "text/plain".to_string(), vec![0; 30], false).is_ok());
+        assert_eq!(my_quota(), (30, 100));
+    }
+
+    #[test]
+    fn test_ensure_directory() {
+        let _context = setup();
+        let owner = caller();
+        let user = Principal::from_text("aaikz-lv7jd-phj2u-t6r4n-6gne4-3rv3x-jus4j-zbiaz-llnsl-jvk5j-iqe").unwrap(); // actor x 12
+
+        assert!(ensure_directory("./.test/dir".to_string()).is_ok());
+        assert!(get_file_info(&"./.test/dir".to_string()).unwrap().is_dir());
+        let created_at = get_file_info(&"./.test/dir".to_string()).unwrap().created_at;
+        assert!(ensure_directory("./.test/dir".to_string()).is_ok());
+        assert_eq!(get_file_info(&"./.test/dir".to_string()).unwrap().created_at, created_at);
+
+        // file
+        let data = "Hello, World!".as_bytes().to_vec();
+        assert!(save("./.test/dir/file.txt".to_string(), "text/plain".to_string(), data, false).is_ok());
+        assert_eq!(ensure_directory("./.test/dir/file.txt".to_string()).unwrap_err().code, ERROR_NOT_A_DIRECTORY);
+
+        // parent not found
+        assert_eq!(ensure_directory("./.test/none/dir".to_string()).unwrap_err().code, ERROR_NOT_FOUND);
+
+        // write permission is required even if it exists
+        set_caller(user);
+        assert_eq!(ensure_directory("./.test/dir".to_string()).unwrap_err().code, ERROR_PERMISSION_DENIED);
+        set_caller(owner);
+    }
+
+    #[test]
+    fn test_copy_directory() {
+        let _context = setup();
+
+        let data = "Hello, World!".as_bytes().to_vec();
+        assert!(create_directory("./.test/from".to_string()).is_ok());
+        assert!(create_directory("./.test/from/sub".to_string()).is_ok());
+        for i in 0..MAX_COPY_STEP + 20 {
+            assert!(save(format!("./.test/from/sub/{:03}.txt", i), "text/plain".to_string(), data.clone(), false).is_ok());
+        }
+        assert!(save("./.test/from/top.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+
+        // first step
+        let progress = copy_directory("./.test/from".to_string(), "./.test/to".to_string(), None).unwrap();
+        assert_eq!(progress.copied, MAX_COPY_STEP as u64);
+        assert!(!progress.done);
+        let token = progress.token.unwrap();
+        assert_eq!(token, format!("sub/{:03}.txt", MAX_COPY_STEP - 2));
+        assert!(get_file_info(&"./.test/to/sub".to_string()).unwrap().is_dir());
+        assert!(get_file_info(&"./.test/to/top.txt".to_string()).is_none());
+
+        // can't start again over the partial copy
+        let result = copy_directory("./.test/from".to_string(), "./.test/to".to_string(), None);
+        assert_eq!(result.unwrap_err().code, ERROR_ALREADY_EXISTS);
+
+        // resume
+        let progress = copy_directory("./.test/from".to_string(), "./.test/to".to_string(), Some(token)).unwrap();
+        assert_eq!(progress.copied, 22);
+        assert!(progress.done);
+        assert_eq!(progress.token, None);
+        assert_eq!(list_files("./.test/to/sub".to_string()).unwrap().len(), MAX_COPY_STEP + 20);
+        assert_eq!(load("./.test/to/top.txt".to_string(), 0, None).unwrap().chunk, data);
+        assert_eq!(get_info("./.test/to/sub/000.txt".to_string()).unwrap().sha256, Some(Sha256::digest(&data).into()));
+        assert_eq!(load("./.test/from/top.txt".to_string(), 0, None).unwrap().chunk, data);
+
+        // errors
+        let result = copy_directory("./.test/from".to_string(), "./.test/from/sub/copy".to_string(), None);
+        assert_eq!(result.unwrap_err().code, ERROR_INVALID_PATH);
+        let result = copy_directory("./.test/from/top.txt".to_string(), "./.test/copy".to_string(), None);
+        assert_eq!(result.unwrap_err().code, ERROR_NOT_A_DIRECTORY);
+        let result = copy_directory("./.test/from".to_string(), "./.test/none".to_string(), Some("top.txt".to_string()));
+        assert_eq!(result.unwrap_err().code, ERROR_INVALID_SEQUENCE);
+    }
+
+    #[test]
+    fn test_move_directory() {
+        let _context = setup();
+
+        let data = "Hello, World!".as_bytes().to_vec();
+        assert!(create_directory("./.test/from".to_string()).is_ok());
+        assert!(create_directory("./.test/from/sub".to_string()).is_ok());
+        assert!(create_directory("./.test/from/sub/deep".to_string()).is_ok());
+        for i in 0..MAX_COPY_STEP + 20 {
+            assert!(save(format!("./.test/from/sub/{:03}.txt", i), "text/plain".to_string(), data.clone(), false).is_ok());
+        }
+        assert!(save("./.test/from/top.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+        let created_at = get_info("./.test/from/sub/000.txt".to_string()).unwrap().created_at;
+
+        // first step
+        let progress = move_directory("./.test/from".to_string(), "./.test/to".to_string(), None).unwrap();
+        assert_eq!(progress.moved, MAX_COPY_STEP as u64);
+        assert!(!progress.done);
+        let token = progress.token.unwrap();
+        assert_eq!(token, format!("sub/{:03}.txt", MAX_COPY_STEP - 2));
+
+        // every file is found on either side in the middle of the move
+        assert!(get_file_info(&"./.test/to/sub".to_string()).unwrap().is_dir());
+        assert!(get_file_info(&"./.test/from/sub".to_string()).unwrap().is_dir());
+        assert_eq!(load("./.test/to/sub/000.txt".to_string(), 0, None).unwrap().chunk, data);
+        assert_eq!(get_info("./.test/to/sub/000.txt".to_string()).unwrap().created_at, created_at);
+        assert_eq!(get_info("./.test/from/sub/000.txt".to_string()).unwrap_err().code, ERROR_NOT_FOUND);
+        assert_eq!(load(format!("./.test/from/sub/{:03}.txt", MAX_COPY_STEP - 1), 0, None).unwrap().chunk, data);
+        assert_eq!(load("./.test/from/top.txt".to_string(), 0, None).unwrap().chunk, data);
+
+        // can't start again over the partial move
+        let result = move_directory("./.test/from".to_string(), "./.test/to".to_string(), None);
+        assert_eq!(result.unwrap_err().code, ERROR_ALREADY_EXISTS);
+
+        // resume
+        let progress = move_directory("./.test/from".to_string(), "./.test/to".to_string(), Some(token)).unwrap();
+        assert_eq!(progress.moved, 23 + 2); // the rest and the directories left under `from`
+        assert!(progress.done);
+        assert_eq!(progress.token, None);
+        assert!(get_file_info(&"./.test/from".to_string()).is_none());
+        assert!(!fs::exists("./.test/from").unwrap());
+        assert_eq!(list_files("./.test".to_string()).unwrap(), vec!["to/"]);
+        assert_eq!(list_files("./.test/to/sub".to_string()).unwrap().len(), MAX_COPY_STEP + 20 + 1);
+        assert_eq!(load("./.test/to/top.txt".to_string(), 0, None).unwrap().chunk, data);
+        assert!(get_file_info(&"./.test/to/sub/deep".to_string()).unwrap().is_dir());
+
+        // errors
+        let result = move_directory("./.test/to".to_string(), "./.test/to/sub/moved".to_string(), None);
+        assert_eq!(result.unwrap_err().code, ERROR_INVALID_PATH);
+        let result = move_directory("./.test/to/top.txt".to_string(), "./.test/moved".to_string(), None);
+        assert_eq!(result.unwrap_err().code, ERROR_NOT_A_DIRECTORY);
+        let result = move_directory("./.test/to".to_string(), "./.test/none".to_string(), Some("top.txt".to_string()));
+        assert_eq!(result.unwrap_err().code, ERROR_INVALID_SEQUENCE);
+    }
+
+    #[test]
+    fn test_max_name() {
+        let _context = setup();
+        let data = "Hello, World!".as_bytes().to_vec();
+
+        let name = "a".repeat(MAX_NAME);
+        let path = format!("./.test/{}", name);
+        assert!(save(path.clone(), "text/plain".to_string(), data.clone(), false).is_ok());
+        assert_eq!(list_files(ROOT.to_string()).unwrap(), vec![name.clone()]);
+
+        // one more byte in a component, well within MAX_PATH
+        let longer = format!("./.test/{}b", name);
+        assert!(longer.len() < MAX_PATH);
+        let error = save(longer.clone(), "text/plain".to_string(), data.clone(), false).unwrap_err();
+        assert_eq!((error.code, error.message), (ERROR_INVALID_PATH, "Name is too long".to_string()));
+        assert_eq!(create_directory(format!("./.test/{}b/dir", name)).unwrap_err().code, ERROR_INVALID_PATH);
+        assert_eq!(rename(path, format!("{}b", name), false).unwrap_err().code, ERROR_INVALID_PATH);
+        // bytes, not characters
+        assert_eq!(validate_path(&format!("./.test/{}", "\u{3042}".repeat(MAX_NAME / 3 + 1))).unwrap_err().code, ERROR_INVALID_PATH);
+    }
+
+    #[test]
+    fn test_max_depth() {
+        let _context = setup();
+        let owner = caller();
+        let user = Principal::from_text("aaikz-lv7jd-phj2u-t6r4n-6gne4-3rv3x-jus4j-zbiaz-llnsl-jvk5j-iqe").unwrap(); // actor x 12
+
+        let mut path = ROOT.to_string();
+        for _ in 0..MAX_DEPTH {
+            path = format!("{}/d", path);
+            assert!(create_directory(path.clone()).is_ok());
+        }
+        assert_eq!(validate_path(&path).unwrap(), path);
+
+        // one more component
+        let deeper = format!("{}/d", path);
+        let error = create_directory(deeper.clone()).unwrap_err();
+        assert_eq!(error.code, ERROR_INVALID_PATH);
+        assert_eq!(error.message, "Path is too deep");
+        assert_eq!(save(deeper.clone(), "text/plain".to_string(), vec![], false).unwrap_err().code, ERROR_INVALID_PATH);
+        assert_eq!(has_permission(deeper).unwrap_err().code, ERROR_INVALID_PATH);
+
+        // permission is inherited from ROOT through every level
+        assert!(has_permission(path.clone()).unwrap().writable);
+        set_caller(user);
+        assert!(!has_permission(path.clone()).unwrap().readable);
+        set_caller(owner);
+        assert!(add_permission(format!("{}/d", ROOT), user, false, true, false).is_ok());
+        set_caller(user);
+        assert!(has_permission(path.clone()).unwrap().readable);
+        set_caller(owner);
+    }
+
+    #[test]
+    fn test_read_all() {
+        let _context = setup();
+
+        let data = "Hello, World!".as_bytes().to_vec();
+        assert!(save("./.test/file.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+        let (content, mimetype, sha256) = read_all("./.test/file.txt".to_string()).unwrap();
+        assert_eq!(content, data);
+        assert_eq!(mimetype, "text/plain");
+        assert_eq!(sha256, <[u8; 32]>::from(Sha256::digest(&data)));
+
+        // too large
+        assert!(save("./.test/large.bin".to_string(), "application/octet-stream".to_string(), vec![0; MAX_READ_SIZE + 1], false).is_ok());
+        assert_eq!(read_all("./.test/large.bin".to_string()).unwrap_err().code, ERROR_INVALID_SIZE);
+
+        assert!(create_directory("./.test/dir".to_string()).is_ok());
+        assert_eq!(read_all("./.test/dir".to_string()).unwrap_err().code, ERROR_IS_DIRECTORY);
+        assert_eq!(read_all("./.test/none.txt".to_string()).unwrap_err().code, ERROR_NOT_FOUND);
+    }
+
+    #[test]
+    fn test_directory_quota() {
+        let _context = setup();
+        let owner = caller();
+        let user = Principal::from_text("aaikz-lv7jd-phj2u-t6r4n-6gne4-3rv3x-jus4j-zbiaz-llnsl-jvk5j-iqe").unwrap(); // actor x 12
+
+        assert!(create_directory("./.test/tenant".to_string()).is_ok());
+        assert!(create_directory("./.test/tenant/sub".to_string()).is_ok());
+        assert!(save("./.test/tenant/a.bin".to_string(), "application/octet-stream".to_string(), vec![0; 30], false).is_ok());
+        assert!(save("./.test/other.bin".to_string(), "application/octet-stream".to_string(), vec![0; 500], false).is_ok());
+
+        // manage permission on a directory is required
+        assert!(add_permission("./.test/tenant".to_string(), user, false, true, true).is_ok());
+        set_caller(user);
+        assert_eq!(set_directory_quota("./.test/tenant".to_string(), Some(100)).unwrap_err().code, ERROR_PERMISSION_DENIED);
+        set_caller(owner);
+        assert_eq!(set_directory_quota("./.test/other.bin".to_string(), Some(100)).unwrap_err().code, ERROR_NOT_A_DIRECTORY);
+        assert!(set_directory_quota("./.test/tenant".to_string(), Some(100)).is_ok());
+        assert_eq!(get_directory_quota("./.test/tenant".to_string()).unwrap(), (30, Some(100)));
 
-fn set_file_info(path:&String, info:&FileInfo) -> Result<(), Error> {
-    let info_path = file_info_path(path);
-    let file = OpenOptions::new().write(true).create(true).truncate(true).open(&info_path);
-    match file {
-        Ok(mut file) => {
-            match file.write_all(&serde_cbor::to_vec(info).unwrap()) {
-                Ok(()) => Ok(()),
-                Err(e) => error!(ERROR_UNKNOWN, format!("{:?}", e))
-            }
-        },
-        Err(e) => error!(ERROR_UNKNOWN, format!("{:?}", e))
+        // enforced for every writer and nested directories
+        set_caller(user);
+        assert!(save("./.test/tenant/sub/b.bin".to_string(), "application/octet-stream".to_string(), vec![0; 50], false).is_ok());
+        assert_eq!(get_directory_quota("./.test/tenant".to_string()).unwrap(), (80, Some(100)));
+        let result = save("./.test/tenant/sub/c.bin".to_string(), "application/octet-stream".to_string(), vec![0; 21], false);
+        assert_eq!(result.unwrap_err().code, ERROR_QUOTA_EXCEEDED);
+        let result = begin_upload("./.test/tenant/c.bin".to_string(), "application/octet-stream".to_string(), false, 21);
+        assert_eq!(result.unwrap_err().code, ERROR_QUOTA_EXCEEDED);
+        assert!(save("./.test/tenant/sub/b.bin".to_string(), "application/octet-stream".to_string(), vec![0; 70], true).is_ok()); // replaces 50 bytes
+        set_caller(owner);
+
+        // the cache follows deletes and moves
+        assert!(delete("./.test/tenant/a.bin".to_string()).is_ok());
+        assert_eq!(get_directory_quota("./.test/tenant".to_string()).unwrap(), (70, Some(100)));
+        let result = move_path("./.test/other.bin".to_string(), "./.test/tenant/other.bin".to_string());
+        assert_eq!(result.unwrap_err().code, ERROR_QUOTA_EXCEEDED);
+        let result = swap("./.test/other.bin".to_string(), "./.test/tenant/sub/b.bin".to_string());
+        assert_eq!(result.unwrap_err().code, ERROR_QUOTA_EXCEEDED);
+        let result = batch(vec![Op::Move { from: "./.test/other.bin".to_string(), to: "./.test/tenant/other.bin".to_string() }]);
+        assert_eq!(result.unwrap_err().code, ERROR_QUOTA_EXCEEDED);
+        assert!(save("./.test/small.bin".to_string(), "application/octet-stream".to_string(), vec![0; 20], false).is_ok());
+        assert!(move_path("./.test/small.bin".to_string(), "./.test/tenant/small.bin".to_string()).is_ok());
+        assert_eq!(get_directory_quota("./.test/tenant".to_string()).unwrap(), (90, Some(100)));
+        assert!(move_path("./.test/tenant/small.bin".to_string(), "./.test/tenant/sub/small.bin".to_string()).is_ok()); // within the quota
+        assert!(move_path("./.test/tenant/sub/small.bin".to_string(), "./.test/small.bin".to_string()).is_ok());
+        assert_eq!(get_directory_quota("./.test/tenant".to_string()).unwrap(), (70, Some(100)));
+
+        // a re-sent chunk is charged once
+        assert!(begin_upload("./.test/tenant/c.bin".to_string(), "application/octet-stream".to_string(), false, 30).is_ok());
+        assert!(send_data("./.test/tenant/c.bin".to_string(), 0, vec![0; 30]).is_ok());
+        assert_eq!(send_data("./.test/tenant/c.bin".to_string(), 0, vec![0; 30]).unwrap(), 30);
+        assert!(commit_upload("./.test/tenant/c.bin".to_string(), 30, None).is_ok());
+        assert!(delete("./.test/tenant/c.bin".to_string()).is_ok());
+        assert!(move_path("./.test/tenant".to_string(), "./.test/moved".to_string()).is_ok());
+        assert_eq!(get_directory_quota("./.test/moved".to_string()).unwrap(), (70, Some(100)));
+        rebuild_usage();
+        assert_eq!(get_directory_quota("./.test/moved".to_string()).unwrap(), (70, Some(100)));
+        assert!(delete_directory("./.test/moved/sub".to_string(), true).is_ok());
+        assert_eq!(get_directory_quota("./.test/moved".to_string()).unwrap(), (0, Some(100)));
+
+        // no limit
+        assert!(set_directory_quota("./.test/moved".to_string(), None).is_ok());
+        assert!(save("./.test/moved/big.bin".to_string(), "application/octet-stream".to_string(), vec![0; 200], false).is_ok());
+        assert_eq!(get_directory_quota("./.test/moved".to_string()).unwrap(), (200, None));
     }
-}
 
-fn delete_file_info(path:&String) -> () {
-    // TODO Error handling
-    let _ = fs::remove_file(file_info_path(path));
-}
+    #[test]
+    fn test_clean_temp_files() {
+        let _context = setup();
+        let owner = caller();
+        let user = Principal::from_text("aaikz-lv7jd-phj2u-t6r4n-6gne4-3rv3x-jus4j-zbiaz-llnsl-jvk5j-iqe").unwrap(); // actor x 12
 
-// returns temporary path for saving a file
-fn temp_path(path:&String) -> String {
-    if path == "/" {
-        return "/``".to_string();
-    }
-    match path.rfind("/") {
-        Some(index) => {
-            format!("{}``{}", &path[0..index +1], &path[index + 1..])
-        },
-        None => {
-            // FIXME Not expected
-            format!("``{}", path)
+        let data = "Hello, World!".as_bytes().to_vec();
+        assert!(create_directory("./.test/dir".to_string()).is_ok());
+        assert!(create_directory("./.test/dir/sub".to_string()).is_ok());
+        assert!(save("./.test/dir/file.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+        let old = SystemTime::now() - std::time::Duration::from_millis(UPLOAD_TIMEOUT + 1000);
+        for path in ["./.test/dir/a.txt", "./.test/dir/sub/b.txt", "./.test/c.txt"] {
+            let temp = temp_path(&path.to_string());
+            fs::write(&temp, b"temp").unwrap();
+            File::options().write(true).open(&temp).unwrap().set_modified(old).unwrap();
         }
-    }
-}
+        fs::write(temp_path(&"./.test/dir/new.txt".to_string()), b"temp").unwrap(); // may be in progress
 
+        // admin only
+        set_caller(user);
+        assert_eq!(clean_temp_files(ROOT.to_string()).unwrap_err().code, ERROR_PERMISSION_DENIED);
+        set_caller(owner);
 
-/////////////////////////////////////////////////////////////////////////////
-//
-// Implementation for PoC only
-//
-// FIXME Remove before production
-#[derive(CandidType, Serialize, Deserialize)]
-pub struct FileInfoForPoC {
-    size: u64,
-    creator: Principal,
-    created_at: u64,
-    updater: Principal,
-    updated_at: u64,
-    mimetype: String,
-    path: String,
-    manageable: Vec<Principal>, // Grant or Revoke permission
-    readable: Vec<Principal>,
-    writable: Vec<Principal>,
-    children: Option<Vec<FileInfoForPoC>>,
-}
+        assert_eq!(clean_temp_files("./.test/dir".to_string()).unwrap(), 2);
+        assert!(!fs::exists(temp_path(&"./.test/dir/sub/b.txt".to_string())).unwrap());
+        assert!(fs::exists(temp_path(&"./.test/dir/new.txt".to_string())).unwrap());
+        assert!(fs::exists(temp_path(&"./.test/c.txt".to_string())).unwrap()); // out of the subtree
+        assert_eq!(clean_temp_files(ROOT.to_string()).unwrap(), 1);
+        assert_eq!(clean_temp_files(ROOT.to_string()).unwrap(), 0);
+        assert_eq!(load("./.test/dir/file.txt".to_string(), 0, None).unwrap().chunk, data);
 
-impl FileInfoForPoC {
-    fn is_dir(&self) -> bool {
-        self.mimetype == MIMETYPE_DIRECTORY
+        assert_eq!(clean_temp_files("./.test/dir/file.txt".to_string()).unwrap_err().code, ERROR_NOT_A_DIRECTORY);
     }
-}
 
-// DEBUG logics for PoC
-#[ic_cdk::query(name="getAllInfoForPoC")]
-pub fn get_all_info_for_poc() -> Result<FileInfoForPoC, Error> {
-    get_info_for_poc(ROOT.to_string())
-}
+    #[test]
+    fn test_info_acl() {
+        let _context = setup();
+        let owner = caller();
+        let user = Principal::from_text("aaikz-lv7jd-phj2u-t6r4n-6gne4-3rv3x-jus4j-zbiaz-llnsl-jvk5j-iqe").unwrap(); // actor x 12
 
-pub fn get_info_for_poc(path:String) -> Result<FileInfoForPoC, Error> {
+        assert!(create_directory("./.test/dir".to_string()).is_ok());
+        assert!(add_permission("./.test/dir".to_string(), user, false, true, false).is_ok());
+        assert!(deny_permission("./.test/dir".to_string(), Principal::anonymous(), true, false).is_ok());
+
+        let info = get_info("./.test/dir".to_string()).unwrap();
+        assert!(info.is_dir);
+        assert_eq!(info.creator, owner);
+        assert_eq!(info.acl.unwrap(), Acl {
+            manageable: vec![],
+            readable: vec![user],
+            writable: vec![],
+            denied_readable: vec![Principal::anonymous()],
+            denied_writable: vec![],
+            listable: vec![],
+        });
 
-    match get_file_info(&path) {
-        Some(info) => {
-            let children = if info.is_dir() {
-                // Directory
-                let mut children:Vec<FileInfoForPoC> = Vec::new();
-                let entries = fs::read_dir(&path).unwrap();
-                let _ = entries.map(| entry | {
-                    let entry = entry.unwrap();
-                    let file_name = entry.path().file_name().unwrap().to_string_lossy().into_owned();
-                    if !file_name.starts_with("`") {
-                        let file_path = entry.path().to_string_lossy().into_owned();
-                        children.push(get_info_for_poc(file_path).unwrap());
-                    }
-                }).collect::<Vec<()>>();
+        // a reader without manage permission
+        set_caller(user);
+        let info = get_info("./.test/dir".to_string()).unwrap();
+        assert!(info.is_dir);
+        assert!(info.acl.is_none());
+        set_caller(owner);
+    }
 
-                children.sort_by(|a, b| 
-                    if a.is_dir() {
-                        if b.is_dir() {
-                            a.path.cmp(&b.path)
-                        } else {
-                            Ordering::Less
-                        }
-                    } else if b.is_dir() {
-                        Ordering::Greater
-                    } else {
-                        a.path.cmp(&b.path)
-                    }
-                );
-                Some(children)
-            } else {
-                // File
-                None
-            };
+    #[test]
+    fn test_delete_if_match() {
+        let _context = setup();
+        let owner = caller();
+        let user = Principal::from_text("aaikz-lv7jd-phj2u-t6r4n-6gne4-3rv3x-jus4j-zbiaz-llnsl-jvk5j-iqe").unwrap(); // actor x 12
 
-            Ok(FileInfoForPoC {
-                path,
-                size: info.size,
-                creator: info.creator,
-                created_at: info.created_at,
-                updater: info.updater,
-                updated_at: info.updated_at,
-                mimetype: info.mimetype,
-                manageable: info.manageable,
-                readable: info.readable,
-                writable: info.writable,
-                children,
-            })
-        }
-        None => {
-            return error!(ERROR_NOT_FOUND, "Directory not found");
-        }
-    }
-}
+        let path = "./.test/file.txt".to_string();
+        assert!(save(path.clone(), "text/plain".to_string(), "Hello".as_bytes().to_vec(), false).is_ok());
+        let old_sha256 = get_info(path.clone()).unwrap().sha256.unwrap();
+        assert!(save(path.clone(), "text/plain".to_string(), "World".as_bytes().to_vec(), true).is_ok());
+        let sha256 = get_info(path.clone()).unwrap().sha256.unwrap();
 
-// DEBUG logics for PoC
-#[ic_cdk::update(name="forceResetForPoC")]
-pub fn force_reset_for_poc() -> Result<(), Error> {
-    // Remove all directories
-    let entries = fs::read_dir(&ROOT.to_string()).unwrap();
-    let _ = entries.map(| entry | {
-        let entry = entry.unwrap();
-        let child_path = entry.path().to_string_lossy().into_owned();
-        if entry.file_type().unwrap().is_dir() { 
-            fs::remove_dir_all(&child_path).unwrap();
-        } else {
-            fs::remove_file(&child_path).unwrap();
-        }
-    }).collect::<Vec<()>>();
-    Ok(())
-}
+        // mismatch
+        assert_eq!(delete_if_match(path.clone(), old_sha256).unwrap_err().code, ERROR_VERSION_CONFLICT);
+        assert!(get_info(path.clone()).is_ok());
 
+        // permission is checked before the content
+        set_caller(user);
+        assert_eq!(delete_if_match(path.clone(), sha256).unwrap_err().code, ERROR_PERMISSION_DENIED);
+        set_caller(owner);
 
-/////////////////////////////////////////////////////////////////////////////
-// Unit Test
-/////////////////////////////////////////////////////////////////////////////
-#[cfg(test)]
-mod tests {
-    use super::*;
+        // match
+        assert!(delete_if_match(path.clone(), sha256).is_ok());
+        assert_eq!(get_info(path.clone()).unwrap_err().code, ERROR_NOT_FOUND);
+        assert_eq!(delete_if_match(path, sha256).unwrap_err().code, ERROR_NOT_FOUND);
 
-    struct TestContext {
+        // directory
+        assert!(create_directory("./.test/dir".to_string()).is_ok());
+        assert_eq!(delete_if_match("./.test/dir".to_string(), sha256).unwrap_err().code, ERROR_IS_DIRECTORY);
     }
-    fn setup() -> TestContext {
-        // owner
-        let owner = Principal::from_text("zebsi-6birt-enaic-v4hbv-zffiv-ft53g-u4gi3-og45y-tskzf-m6jus-xqe").unwrap(); // goddess x 12
-        set_caller(owner);
 
-        let _ = fs::remove_dir_all(format!("{}/", ROOT)); // Root is "./.test/" for unit test
-        let _ = fs::remove_file(file_info_path(&ROOT.to_string()));
-        let _ = fs::create_dir(format!("{}/", ROOT));
-        set_file_info(&ROOT.to_string(), &FileInfo {
-            size: 0,
-            creator: caller(),
-            created_at: 0,
-            updater: caller(),
-            updated_at: 0,
-            mimetype: MIMETYPE_DIRECTORY.to_string(),
-            manageable: vec![caller()],
-            readable: vec![caller()],
-            writable: vec![caller()],
-            sha256: None,
-            signature: None,
-        }).unwrap();
-        TestContext {
+    #[test]
+    fn test_validate() {
+        let _context = setup();
+        let data = "Hello, World!".as_bytes().to_vec();
+
+        assert!(validate("./.test/none/file.txt".to_string(), None).is_ok());
+        assert!(validate("./.test/file.txt".to_string(), Some("text/plain".to_string())).is_ok());
+        for path in ["./.test/`file.txt", "./.test/../file.txt", "./.test/dir/", "./.test/a\u{7}b.txt"] {
+            let expected = save(path.to_string(), "text/plain".to_string(), data.clone(), false).unwrap_err();
+            let error = validate(path.to_string(), None).unwrap_err();
+            assert_eq!((error.code, error.message), (expected.code, expected.message));
         }
+
+        // mimetype policy
+        assert!(set_mimetype_policy(MimetypePolicy { allow: vec![], deny: vec!["application/x-msdownload".to_string()] }).is_ok());
+        let result = validate("./.test/a.exe".to_string(), Some("application/x-msdownload".to_string()));
+        let expected = save("./.test/a.exe".to_string(), "application/x-msdownload".to_string(), data, false);
+        assert!(set_mimetype_policy(MimetypePolicy { allow: vec![], deny: vec![] }).is_ok());
+        assert_eq!(result.unwrap_err().code, ERROR_INVALID_MIMETYPE);
+        assert_eq!(expected.unwrap_err().code, ERROR_INVALID_MIMETYPE);
+        assert!(!fs::exists("./.test/a.exe").unwrap());
     }
-    impl Drop for TestContext {
-        fn drop(&mut self) {
-            let _ = fs::remove_dir_all(format!("{}/", ROOT));
-            let _ = fs::remove_file(file_info_path(&ROOT.to_string()));
+
+    #[test]
+    fn test_swap() {
+        let _context = setup();
+        let user = Principal::from_text("aaikz-lv7jd-phj2u-t6r4n-6gne4-3rv3x-jus4j-zbiaz-llnsl-jvk5j-iqe").unwrap(); // actor x 12
+        assert!(save("./.test/live".to_string(), "text/plain".to_string(), b"blue".to_vec(), false).is_ok());
+        assert!(save("./.test/staging".to_string(), "text/html".to_string(), b"green!".to_vec(), false).is_ok());
+        assert_eq!(load("./.test/live".to_string(), 0, None).unwrap().chunk, b"blue");
+        assert_eq!(load("./.test/staging".to_string(), 0, None).unwrap().chunk, b"green!");
+
+        assert!(swap("./.test/live".to_string(), "./.test/staging".to_string()).is_ok());
+        assert_eq!(load("./.test/live".to_string(), 0, None).unwrap().chunk, b"green!");
+        assert_eq!(load("./.test/staging".to_string(), 0, None).unwrap().chunk, b"blue");
+        let info = get_info("./.test/live".to_string()).unwrap();
+        assert_eq!((info.size, info.mimetype), (6, "text/html".to_string()));
+        assert_eq!(get_info("./.test/staging".to_string()).unwrap().sha256, Some(Sha256::digest(b"blue").into()));
+        assert_eq!(list_files(ROOT.to_string()).unwrap(), vec!["live", "staging"]);
+
+        // directories with their descendants
+        for (dir, data) in [("./.test/a", b"a"), ("./.test/b", b"b")] {
+            assert!(create_directory(dir.to_string()).is_ok());
+            assert!(save(format!("{}/{}.txt", dir, String::from_utf8_lossy(data)), "text/plain".to_string(), data.to_vec(), false).is_ok());
         }
+        assert!(swap("./.test/a".to_string(), "./.test/b".to_string()).is_ok());
+        assert_eq!(list_files("./.test/a".to_string()).unwrap(), vec!["b.txt"]);
+        assert_eq!(list_files("./.test/b".to_string()).unwrap(), vec!["a.txt"]);
+        assert_eq!(load("./.test/a/b.txt".to_string(), 0, None).unwrap().chunk, b"b");
+        assert!(get_file_info(&"./.test/a/a.txt".to_string()).is_none());
+
+        // errors
+        assert_eq!(swap("./.test/live".to_string(), "./.test/a".to_string()).unwrap_err().code, ERROR_NOT_A_DIRECTORY);
+        assert_eq!(swap("./.test/a".to_string(), "./.test/live".to_string()).unwrap_err().code, ERROR_IS_DIRECTORY);
+        assert_eq!(swap("./.test/live".to_string(), "./.test/none".to_string()).unwrap_err().code, ERROR_NOT_FOUND);
+        assert_eq!(swap("./.test/a".to_string(), "./.test/a/b.txt".to_string()).unwrap_err().code, ERROR_INVALID_PATH);
+        assert_eq!(swap("./.test/a/b.txt".to_string(), "./.test/a".to_string()).unwrap_err().code, ERROR_INVALID_PATH);
+        assert!(add_permission("./.test/a".to_string(), user, false, true, true).is_ok());
+        set_caller(user);
+        assert_eq!(swap("./.test/a".to_string(), "./.test/b".to_string()).unwrap_err().code, ERROR_PERMISSION_DENIED);
+        assert_eq!(list_files("./.test/a".to_string()).unwrap(), vec!["b.txt"]);
     }
 
     #[test]
-    fn test_save() {
+    fn test_move() {
         let _context = setup();
 
-        // new file
-        let data = "Hello, World!".as_bytes().to_vec();
-        let result = save("./.test/file.txt".to_string(), "text/plain".to_string(), data.clone(), false);
-        assert!(result.is_ok());
-        let result = load("./.test/file.txt".to_string(), 0);
-        assert!(result.is_ok());
-        assert_eq!(result.unwrap().chunk, data);
-
-        // overwrite
+        assert!(create_directory("./.test/a".to_string()).is_ok());
+        assert!(create_directory("./.test/a/b".to_string()).is_ok());
         let data = "Hello, World!".as_bytes().to_vec();
-        let result = save("./.test/file.txt".to_string(), "text/plain".to_string(), data.clone(), true);
-        assert!(result.is_ok());
-        let result = load("./.test/file.txt".to_string(), 0);
-        assert!(result.is_ok());
-        assert_eq!(result.unwrap().chunk, data);
-
-        // error
-        let result = save("./.test/file.txt".to_string(), "text/plain".to_string(), data.clone(), false);
-        assert!(result.is_err());
+        assert!(save("./.test/a/b/file.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+
+        // into itself
+        let result = move_path("./.test/a".to_string(), "./.test/a/b/a".to_string());
+        assert_eq!(result.unwrap_err().code, ERROR_INVALID_PATH);
+        let result = move_path("./.test/a".to_string(), "./.test/a".to_string());
+        assert_eq!(result.unwrap_err().code, ERROR_INVALID_PATH);
+        assert!(check_not_into_itself("./.test/a", "./.test/ab").is_ok());
+
+        // file
+        assert!(move_path("./.test/a/b/file.txt".to_string(), "./.test/a/file.txt".to_string()).is_ok());
+        assert_eq!(load("./.test/a/file.txt".to_string(), 0, None).unwrap().chunk, data);
+        assert_eq!(load("./.test/a/b/file.txt".to_string(), 0, None).unwrap_err().code, ERROR_NOT_FOUND);
+
+        // directory
+        assert!(move_path("./.test/a".to_string(), "./.test/c".to_string()).is_ok());
+        assert_eq!(load("./.test/c/file.txt".to_string(), 0, None).unwrap().chunk, data);
+        assert!(get_file_info(&"./.test/c/b".to_string()).unwrap().is_dir());
+        assert!(get_file_info(&"./.test/a".to_string()).is_none());
+
+        // already exists
+        assert!(create_directory("./.test/d".to_string()).is_ok());
+        let result = move_path("./.test/c".to_string(), "./.test/d".to_string());
         assert_eq!(result.unwrap_err().code, ERROR_ALREADY_EXISTS);
     }
 
     #[test]
-    fn test_delete() {
+    fn test_rename() {
         let _context = setup();
+        let owner = caller();
+        let user = Principal::from_text("aaikz-lv7jd-phj2u-t6r4n-6gne4-3rv3x-jus4j-zbiaz-llnsl-jvk5j-iqe").unwrap(); // actor x 12
 
-        // new file
+        assert!(create_directory("./.test/dir".to_string()).is_ok());
+        assert!(create_directory("./.test/dir/sub".to_string()).is_ok());
         let data = "Hello, World!".as_bytes().to_vec();
-        let result = save("./.test/file.txt".to_string(), "text/plain".to_string(), data.clone(), false);
-        assert!(result.is_ok());
-        let result = load("./.test/file.txt".to_string(), 0);
-        assert!(result.is_ok());
-        assert_eq!(result.unwrap().chunk, data);
+        assert!(save("./.test/dir/sub/file.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+        assert!(save("./.test/dir/other.txt".to_string(), "text/plain".to_string(), b"other".to_vec(), false).is_ok());
+
+        // file
+        assert!(rename("./.test/dir/sub/file.txt".to_string(), "renamed.txt".to_string(), false).is_ok());
+        assert_eq!(load("./.test/dir/sub/renamed.txt".to_string(), 0, None).unwrap().chunk, data);
+        assert!(get_file_info(&"./.test/dir/sub/file.txt".to_string()).is_none());
+
+        // directory (with descendants)
+        assert!(rename("./.test/dir/sub".to_string(), "moved".to_string(), false).is_ok());
+        assert_eq!(load("./.test/dir/moved/renamed.txt".to_string(), 0, None).unwrap().chunk, data);
+        assert!(get_file_info(&"./.test/dir/sub".to_string()).is_none());
+
+        // invalid names
+        for name in ["", "a/b", ".", "..", "`meta"] {
+            assert_eq!(rename("./.test/dir/other.txt".to_string(), name.to_string(), false).unwrap_err().code, ERROR_INVALID_PATH, "{}", name);
+        }
+        assert_eq!(rename(ROOT.to_string(), "root".to_string(), false).unwrap_err().code, ERROR_INVALID_PATH);
 
-        // delete
-        let result = delete("./.test/file.txt".to_string());
-        assert!(result.is_ok());
+        // already exists
+        assert_eq!(rename("./.test/dir/other.txt".to_string(), "moved".to_string(), true).unwrap_err().code, ERROR_IS_DIRECTORY);
+        assert!(save("./.test/dir/moved/other.txt".to_string(), "text/plain".to_string(), b"old".to_vec(), false).is_ok());
+        assert_eq!(rename("./.test/dir/moved/renamed.txt".to_string(), "other.txt".to_string(), false).unwrap_err().code, ERROR_ALREADY_EXISTS);
+        assert!(rename("./.test/dir/moved/renamed.txt".to_string(), "other.txt".to_string(), true).is_ok());
+        assert_eq!(load("./.test/dir/moved/other.txt".to_string(), 0, None).unwrap().chunk, data);
+        assert_eq!(list_files("./.test/dir/moved".to_string()).unwrap(), vec!["other.txt"]);
 
-        // delete (File not found)
-        let result = delete("./.test/file.txt".to_string());
-        assert_eq!(result.unwrap_err().code, ERROR_NOT_FOUND);
+        // not found
+        assert_eq!(rename("./.test/dir/none.txt".to_string(), "new.txt".to_string(), false).unwrap_err().code, ERROR_NOT_FOUND);
+
+        // write permission on the parent is required
+        assert!(add_permission("./.test/dir/moved/other.txt".to_string(), user, false, true, true).is_ok());
+        set_caller(user);
+        assert_eq!(rename("./.test/dir/moved/other.txt".to_string(), "mine.txt".to_string(), false).unwrap_err().code, ERROR_PERMISSION_DENIED);
+        set_caller(owner);
+        assert!(add_permission("./.test/dir/moved".to_string(), user, false, true, true).is_ok());
+        set_caller(user);
+        assert!(rename("./.test/dir/moved/other.txt".to_string(), "mine.txt".to_string(), false).is_ok());
+        set_caller(owner);
     }
 
     #[test]
-    fn test_file_info() {
+    fn test_reserved_names() {
         let _context = setup();
+        let data = "Hello, World!".as_bytes().to_vec();
+        assert!(create_directory("./.test/dir".to_string()).is_ok());
+        assert!(save("./.test/dir/file.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+
+        for name in ["`file", "``file"] {
+            let path = format!("./.test/dir/{}", name);
+            let error = save(path.clone(), "text/plain".to_string(), data.clone(), false).unwrap_err();
+            assert_eq!(error.code, ERROR_INVALID_PATH);
+            assert_eq!(error.message, "Names starting with ` are reserved");
+            assert_eq!(touch(path.clone(), "text/plain".to_string()).unwrap_err().code, ERROR_INVALID_PATH);
+            assert_eq!(begin_upload(path.clone(), "text/plain".to_string(), false, 0).unwrap_err().code, ERROR_INVALID_PATH);
+            assert_eq!(create_directory(path.clone()).unwrap_err().code, ERROR_INVALID_PATH);
+            assert_eq!(create_directory(format!("{}/sub", path)).unwrap_err().code, ERROR_INVALID_PATH);
+            assert_eq!(move_path("./.test/dir/file.txt".to_string(), path.clone()).unwrap_err().code, ERROR_INVALID_PATH);
+            assert_eq!(rename("./.test/dir/file.txt".to_string(), name.to_string(), false).unwrap_err().code, ERROR_INVALID_PATH);
+        }
+        assert_eq!(list_files("./.test/dir".to_string()).unwrap(), vec!["file.txt"]);
+    }
 
-        // Root
-        let principal_readable = Principal::from_text("f3umm-tovgf-tf7o6-o3oqc-iqlir-f6ufh-3lvrh-5wlic-6dmnu-gg4q7-6ae").unwrap(); // abandon x 12
-        let principal_writable = Principal::from_text("ymtnq-243kz-shxxs-lfs7t-ihqhn-fntsv-wxvf3-kefpu-27hyr-wdczf-2ae").unwrap(); // ability x 12
-        let file_info = FileInfo {
-            size: 0,
-            creator: caller(),
-            created_at: 0,
-            updater: caller(),
-            updated_at: 0,
-            mimetype: "".to_string(),
-            manageable: Vec::new(),
-            readable: vec![principal_readable.clone()],
-            writable: vec![principal_writable.clone()],
-            sha256: None,
-            signature: None,
-        };
+    #[test]
+    fn test_move_during_upload() {
+        let _context = setup();
+        let data = "Hello, World!".as_bytes().to_vec();
+        assert!(create_directory("./.test/dir".to_string()).is_ok());
+        assert!(save("./.test/dir/file.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+        assert!(begin_upload("./.test/dir/file.txt".to_string(), "text/plain".to_string(), true, data.len() as u64).is_ok());
+        assert!(begin_upload("./.test/new.txt".to_string(), "text/plain".to_string(), false, data.len() as u64).is_ok());
+
+        // source
+        let error = move_path("./.test/dir/file.txt".to_string(), "./.test/moved.txt".to_string()).unwrap_err();
+        assert_eq!(error.code, ERROR_ALREADY_EXISTS);
+        assert_eq!(error.message, "Upload in progress");
+        assert_eq!(rename("./.test/dir/file.txt".to_string(), "renamed.txt".to_string(), false).unwrap_err().code, ERROR_ALREADY_EXISTS);
+        // a directory containing the source
+        assert_eq!(move_path("./.test/dir".to_string(), "./.test/dir2".to_string()).unwrap_err().code, ERROR_ALREADY_EXISTS);
+        assert_eq!(rename("./.test/dir".to_string(), "dir2".to_string(), false).unwrap_err().code, ERROR_ALREADY_EXISTS);
+        // destination
+        assert!(save("./.test/other.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+        assert_eq!(move_path("./.test/other.txt".to_string(), "./.test/new.txt".to_string()).unwrap_err().code, ERROR_ALREADY_EXISTS);
+        assert_eq!(rename("./.test/other.txt".to_string(), "new.txt".to_string(), true).unwrap_err().code, ERROR_ALREADY_EXISTS);
+
+        // the upload isn't affected
+        assert!(send_data("./.test/dir/file.txt".to_string(), 0, b"Goodbye, All!".to_vec()).is_ok());
+        assert!(commit_upload("./.test/dir/file.txt".to_string(), data.len() as u64, None).is_ok());
+        assert_eq!(load("./.test/dir/file.txt".to_string(), 0, None).unwrap().chunk, b"Goodbye, All!");
+        assert!(move_path("./.test/dir".to_string(), "./.test/dir2".to_string()).is_ok());
+        assert!(cancel_upload("./.test/new.txt".to_string()).is_ok());
+        assert!(rename("./.test/other.txt".to_string(), "new.txt".to_string(), false).is_ok());
+    }
 
-        // Check of root
-        let path = ROOT.to_string();
-        set_file_info(&path, &file_info).unwrap();
-        assert_eq!(check_read_permission(&principal_readable, &path, Some(&file_info)), true);
-        assert_eq!(check_read_permission(&principal_writable, &path, Some(&file_info)), false);
-        assert_eq!(check_write_permission(&principal_readable, &path, Some(&file_info)), false);
-        assert_eq!(check_write_permission(&principal_writable, &path, Some(&file_info)), true);
+    #[test]
+    fn test_internal_names() {
+        let _context = setup();
 
-        // Check children (no permission found; check parent)
-        let path = format!("{}/child", ROOT);
-        assert_eq!(check_read_permission(&principal_readable, &path, None), true);
-        assert_eq!(check_read_permission(&principal_writable, &path, None), false);
-        assert_eq!(check_write_permission(&principal_readable, &path, None), false);
-        assert_eq!(check_write_permission(&principal_writable, &path, None), true);
+        // every internal name starts with INTERNAL_PREFIX
+        for prefix in [METADATA_PREFIX, TEMP_PREFIX, BACKUP_PREFIX, BLOB_DIRECTORY_NAME] {
+            assert!(prefix.starts_with(INTERNAL_PREFIX));
+        }
+        let path = "./.test/dir/file.txt".to_string();
+        let internal_paths = [
+            file_info_path(&path),
+            temp_path(&path),
+            backup_path(&path, 3),
+            blob_directory(),
+            blob_path(&[0; 32]),
+        ];
+        for internal in internal_paths {
+            assert_eq!(validate_path(&internal).unwrap_err().code, ERROR_INVALID_PATH, "{}", internal);
+        }
+        assert_eq!(file_info_path(&path), "./.test/dir/`file.txt");
+        assert_eq!(temp_path(&path), "./.test/dir/``file.txt");
+        assert_eq!(backup_path(&path, 3), "./.test/dir/```3`file.txt");
+        assert_eq!(blob_directory(), "./.test/`blobs");
+        assert_eq!(file_info_path(&"/".to_string()), "/`");
+    }
 
-        // Check children (has permision)
-        let principal_child_only = Principal::from_text("xm4xy-wgdl4-jhtba-hmdt7-kocg2-y47gj-wuwwg-oqbva-tydcp-6bvxn-7qe").unwrap(); // child x 12
-        let file_info = FileInfo {
-            size: 0,
-            creator: caller(),
-            created_at: 0,
-            updater: caller(),
-            updated_at: 0,
-            mimetype: "".to_string(),
-            manageable: Vec::new(),
-            readable: vec![principal_child_only.clone()],
-            writable: vec![principal_child_only.clone()],
-            sha256: None,
-            signature: None,
-        };
-        set_file_info(&path, &file_info).unwrap();
-        assert_eq!(check_read_permission(&principal_child_only, &path, Some(&file_info)), true);
-        assert_eq!(check_write_permission(&principal_child_only, &path, Some(&file_info)), true);
-        // hasPermission because of parent (Inherited)
-        assert_eq!(check_read_permission(&principal_readable, &path, Some(&file_info)), true);
-        assert_eq!(check_write_permission(&principal_writable, &path, Some(&file_info)), true);
-        // No permission
-        assert_eq!(check_read_permission(&principal_writable, &path, Some(&file_info)), false);
-        assert_eq!(check_write_permission(&principal_readable, &path, Some(&file_info)), false);
+    #[test]
+    fn test_batch() {
+        let _context = setup();
+
+        let data = "Hello, World!".as_bytes().to_vec();
+        assert!(save("./.test/file.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+        assert!(save("./.test/old.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+
+        // all succeed
+        let result = batch(vec![
+            Op::CreateDirectory { path: "./.test/dir".to_string() },
+            Op::Save { path: "./.test/dir/manifest".to_string(), mimetype: "text/plain".to_string(), data: b"part".to_vec(), overwrite: false },
+            Op::Move { from: "./.test/old.txt".to_string(), to: "./.test/dir/old.txt".to_string() },
+        ]);
+        assert!(result.is_ok());
+        assert_eq!(load("./.test/dir/manifest".to_string(), 0, None).unwrap().chunk, b"part");
+        assert_eq!(load("./.test/dir/old.txt".to_string(), 0, None).unwrap().chunk, data);
+
+        // the last one fails, so all are reverted
+        let result = batch(vec![
+            Op::CreateDirectory { path: "./.test/dir2".to_string() },
+            Op::Save { path: "./.test/file.txt".to_string(), mimetype: "text/html".to_string(), data: b"new".to_vec(), overwrite: true },
+            Op::Save { path: "./.test/file.txt".to_string(), mimetype: "text/html".to_string(), data: b"newer".to_vec(), overwrite: true },
+            Op::Delete { path: "./.test/dir/manifest".to_string() },
+            Op::Move { from: "./.test/dir/old.txt".to_string(), to: "./.test/old.txt".to_string() },
+            Op::Delete { path: "./.test/none.txt".to_string() },
+        ]);
+        let error = result.unwrap_err();
+        assert_eq!(error.code, ERROR_NOT_FOUND);
+        assert!(error.message.starts_with("ops[5]"));
+        assert!(get_file_info(&"./.test/dir2".to_string()).is_none());
+        assert_eq!(load("./.test/file.txt".to_string(), 0, None).unwrap().chunk, data);
+        assert_eq!(get_info("./.test/file.txt".to_string()).unwrap().mimetype, "text/plain");
+        assert_eq!(load("./.test/dir/manifest".to_string(), 0, None).unwrap().chunk, b"part");
+        assert_eq!(load("./.test/dir/old.txt".to_string(), 0, None).unwrap().chunk, data);
+        assert_eq!(list_files("./.test".to_string()).unwrap(), vec!["dir/", "file.txt"]);
+        assert_eq!(list_files("./.test/dir".to_string()).unwrap(), vec!["manifest", "old.txt"]);
+
+        // rejected before anything is applied
+        let result = batch(vec![
+            Op::CreateDirectory { path: "./.test/dir3".to_string() },
+            Op::Save { path: "./.test/dir3/a.exe".to_string(), mimetype: "".to_string(), data: b"new".to_vec(), overwrite: false },
+        ]);
+        let error = result.unwrap_err();
+        assert_eq!(error.code, ERROR_INVALID_MIMETYPE);
+        assert!(error.message.starts_with("ops[1]"));
+        assert!(get_file_info(&"./.test/dir3".to_string()).is_none());
+        let result = batch(vec![
+            Op::Delete { path: "./.test/file.txt".to_string() },
+            Op::Move { from: "./.test/dir".to_string(), to: "./.test/dir/sub".to_string() },
+        ]);
+        assert_eq!(result.unwrap_err().code, ERROR_INVALID_PATH);
+        assert_eq!(load("./.test/file.txt".to_string(), 0, None).unwrap().chunk, data);
+
+        // too many operations
+        let result = batch(vec![Op::Delete { path: "./.test/none.txt".to_string() }; MAX_BATCH_SIZE + 1]);
+        assert_eq!(result.unwrap_err().code, ERROR_INVALID_SIZE);
+    }
+
+    #[test]
+    fn test_batch_reverts_canonical_paths() {
+        let _context = setup();
+
+        // relative paths
+        let data = "Hello, World!".as_bytes().to_vec();
+        assert!(save("./.test/a.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+        assert!(save("./.test/b.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+        let result = batch(vec![
+            Op::Save { path: "a.txt".to_string(), mimetype: "text/plain".to_string(), data: b"new".to_vec(), overwrite: true },
+            Op::Delete { path: "b.txt".to_string() },
+            Op::CreateDirectory { path: "./.test/a.txt/x".to_string() },
+        ]);
+        assert!(result.unwrap_err().message.starts_with("ops[2]"));
+        assert_eq!(load("./.test/a.txt".to_string(), 0, None).unwrap().chunk, data);
+        assert_eq!(load("./.test/b.txt".to_string(), 0, None).unwrap().chunk, data);
+
+        // mixed case
+        assert!(delete("./.test/a.txt".to_string()).is_ok());
+        assert!(delete("./.test/b.txt".to_string()).is_ok());
+        set_caller(Principal::from_text("zebsi-6birt-enaic-v4hbv-zffiv-ft53g-u4gi3-og45y-tskzf-m6jus-xqe").unwrap()); // goddess x 12
+        assert!(set_case_insensitive(true).is_ok());
+        assert!(save("./.test/A.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+        assert!(save("./.test/B.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+        let result = batch(vec![
+            Op::Save { path: "./.test/a.TXT".to_string(), mimetype: "text/plain".to_string(), data: b"new".to_vec(), overwrite: true },
+            Op::Delete { path: "B.TXT".to_string() },
+            Op::Move { from: "./.test/A.TXT".to_string(), to: "./.test/C.txt".to_string() },
+            Op::CreateDirectory { path: "./.test/c.txt/x".to_string() },
+        ]);
+        assert!(result.unwrap_err().message.starts_with("ops[3]"));
+        assert_eq!(load("./.test/a.txt".to_string(), 0, None).unwrap().chunk, data);
+        assert_eq!(load("./.test/b.txt".to_string(), 0, None).unwrap().chunk, data);
+        assert!(get_file_info(&"./.test/c.txt".to_string()).is_none());
+        assert_eq!(list_files("./.test".to_string()).unwrap(), vec!["A.txt", "B.txt"]);
     }
 
     #[test]
@@ -1378,6 +7858,34 @@ mod tests {
         assert!(result.is_ok());
         let list = result.unwrap();
         assert_eq!(list.len(), 2);
+
+        // not a directory
+        assert_eq!(list_files("./.test/file".to_string()).unwrap_err().code, ERROR_NOT_A_DIRECTORY);
+        assert_eq!(list_files("./.test/none".to_string()).unwrap_err().code, ERROR_NOT_FOUND);
+    }
+
+    #[test]
+    fn test_get_infos() {
+        let _context = setup();
+
+        let data = "Hello, World!".as_bytes().to_vec();
+        let result = save("./.test/file.txt".to_string(), "text/plain".to_string(), data.clone(), false);
+        assert!(result.is_ok());
+
+        let results = get_infos(vec![
+            "./.test/file.txt".to_string(),
+            "./.test/none.txt".to_string(),
+            "./.test/in`valid".to_string(),
+        ]);
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].as_ref().unwrap().size, data.len() as u64);
+        assert_eq!(results[1].as_ref().unwrap_err().code, ERROR_NOT_FOUND);
+        assert_eq!(results[2].as_ref().unwrap_err().code, ERROR_INVALID_PATH);
+
+        // too many paths
+        let results = get_infos(vec!["./.test/file.txt".to_string(); MAX_BATCH_SIZE + 1]);
+        assert!(results[MAX_BATCH_SIZE - 1].is_ok());
+        assert_eq!(results[MAX_BATCH_SIZE].as_ref().unwrap_err().code, ERROR_INVALID_SIZE);
     }
 
     #[test]
@@ -1394,17 +7902,17 @@ mod tests {
         assert!(result.is_ok());
         set_caller(user);
         let permission = has_permission(ROOT.to_string()).unwrap();
-        assert_eq!(permission.manageable, true);
-        assert_eq!(permission.readable, false);
-        assert_eq!(permission.writable, false);
+        assert!(permission.manageable);
+        assert!(!permission.readable);
+        assert!(!permission.writable);
         set_caller(owner);
         let result = remove_permission(ROOT.to_string(), user, true, false, false);
         assert!(result.is_ok());
         set_caller(user);
         let permission = has_permission(ROOT.to_string()).unwrap();
-        assert_eq!(permission.manageable, false);
-        assert_eq!(permission.readable, false);
-        assert_eq!(permission.writable, false);
+        assert!(!permission.manageable);
+        assert!(!permission.readable);
+        assert!(!permission.writable);
 
         // readable
         set_caller(owner);
@@ -1412,18 +7920,18 @@ mod tests {
         assert!(result.is_ok());
         set_caller(user);
         let permission = has_permission(ROOT.to_string()).unwrap();
-        assert_eq!(permission.manageable, false);
-        assert_eq!(permission.readable, true);
-        assert_eq!(permission.writable, false);
+        assert!(!permission.manageable);
+        assert!(permission.readable);
+        assert!(!permission.writable);
 
         set_caller(owner);
         let result = remove_permission(ROOT.to_string(), user, true, true, false);
         assert!(result.is_ok());
         set_caller(user);
         let permission = has_permission(ROOT.to_string()).unwrap();
-        assert_eq!(permission.manageable, false);
-        assert_eq!(permission.readable, false);
-        assert_eq!(permission.writable, false);
+        assert!(!permission.manageable);
+        assert!(!permission.readable);
+        assert!(!permission.writable);
 
         // writable
         set_caller(owner);
@@ -1431,18 +7939,18 @@ mod tests {
         assert!(result.is_ok());
         set_caller(user);
         let permission = has_permission(ROOT.to_string()).unwrap();
-        assert_eq!(permission.manageable, false);
-        assert_eq!(permission.readable, false);
-        assert_eq!(permission.writable, true);
+        assert!(!permission.manageable);
+        assert!(!permission.readable);
+        assert!(permission.writable);
 
         set_caller(owner);
         let result = remove_permission(ROOT.to_string(), user, true, false, true);
         assert!(result.is_ok());
         set_caller(user);
         let permission = has_permission(ROOT.to_string()).unwrap();
-        assert_eq!(permission.manageable, false);
-        assert_eq!(permission.readable, false);
-        assert_eq!(permission.writable, false);
+        assert!(!permission.manageable);
+        assert!(!permission.readable);
+        assert!(!permission.writable);
 
         // all
         set_caller(owner);
@@ -1450,9 +7958,9 @@ mod tests {
         assert!(result.is_ok());
         set_caller(user);
         let permission = has_permission(ROOT.to_string()).unwrap();
-        assert_eq!(permission.manageable, true);
-        assert_eq!(permission.readable, true);
-        assert_eq!(permission.writable, true);
+        assert!(permission.manageable);
+        assert!(permission.readable);
+        assert!(permission.writable);
 
         // no remove
         set_caller(owner);
@@ -1460,9 +7968,9 @@ mod tests {
         assert!(result.is_ok());
         set_caller(user);
         let permission = has_permission(ROOT.to_string()).unwrap();
-        assert_eq!(permission.manageable, true);
-        assert_eq!(permission.readable, true);
-        assert_eq!(permission.writable, true);
+        assert!(permission.manageable);
+        assert!(permission.readable);
+        assert!(permission.writable);
 
         // remove
         set_caller(owner);
@@ -1470,9 +7978,245 @@ mod tests {
         assert!(result.is_ok());
         set_caller(user);
         let permission = has_permission(ROOT.to_string()).unwrap();
-        assert_eq!(permission.manageable, false);
-        assert_eq!(permission.readable, false);
-        assert_eq!(permission.writable, false);
+        assert!(!permission.manageable);
+        assert!(!permission.readable);
+        assert!(!permission.writable);
+    }
+
+    #[test]
+    fn test_copy_permissions() {
+        let _context = setup();
+        let owner = caller();
+        let user = Principal::from_text("aaikz-lv7jd-phj2u-t6r4n-6gne4-3rv3x-jus4j-zbiaz-llnsl-jvk5j-iqe").unwrap(); // actor x 12
+
+        assert!(create_directory("./.test/from".to_string()).is_ok());
+        assert!(create_directory("./.test/to".to_string()).is_ok());
+        assert!(create_directory("./.test/to/dir".to_string()).is_ok());
+        let data = "Hello, World!".as_bytes().to_vec();
+        assert!(save("./.test/to/dir/file.txt".to_string(), "text/plain".to_string(), data, false).is_ok());
+        assert!(add_permission("./.test/from".to_string(), user, false, true, false).is_ok());
+
+        // not recursive
+        assert!(copy_permissions("./.test/from".to_string(), "./.test/to".to_string(), false).is_ok());
+        let info = get_file_info(&"./.test/to".to_string()).unwrap();
+        assert_eq!(info.readable, vec![user]);
+        let info = get_file_info(&"./.test/to/dir".to_string()).unwrap();
+        assert!(info.readable.is_empty());
+
+        // denials are copied too
+        assert!(deny_permission("./.test/from".to_string(), user, false, true).is_ok());
+        assert!(copy_permissions("./.test/from".to_string(), "./.test/to".to_string(), false).is_ok());
+        assert_eq!(get_file_info(&"./.test/to".to_string()).unwrap().denied_writable, vec![user]);
+        assert!(allow_permission("./.test/from".to_string(), user, false, true).is_ok());
+        assert!(copy_permissions("./.test/from".to_string(), "./.test/to".to_string(), false).is_ok());
+        assert!(get_file_info(&"./.test/to".to_string()).unwrap().denied_writable.is_empty());
+
+        // recursive
+        assert!(copy_permissions("./.test/from".to_string(), "./.test/to".to_string(), true).is_ok());
+        let info = get_file_info(&"./.test/to/dir/file.txt".to_string()).unwrap();
+        assert_eq!(info.readable, vec![user]);
+
+        // no manager would remain
+        let result = copy_permissions("./.test/from".to_string(), ROOT.to_string(), false);
+        assert_eq!(result.unwrap_err().code, ERROR_LAST_MANAGER);
+        assert_eq!(get_file_info(&ROOT.to_string()).unwrap().manageable, vec![owner]);
+        assert!(add_permission("./.test/from".to_string(), owner, true, true, true).is_ok());
+        assert!(copy_permissions("./.test/from".to_string(), ROOT.to_string(), true).is_ok());
+        assert_eq!(get_file_info(&"./.test/to/dir/file.txt".to_string()).unwrap().manageable, vec![owner]);
+
+        // too many paths
+        for i in 0..MAX_PERMISSION_TARGETS {
+            assert!(create_directory(format!("./.test/to/dir/{}", i)).is_ok());
+        }
+        let result = copy_permissions("./.test/from".to_string(), "./.test/to".to_string(), true);
+        assert_eq!(result.unwrap_err().code, ERROR_INVALID_SIZE);
+        assert!(copy_permissions("./.test/from".to_string(), "./.test/to/dir/0".to_string(), true).is_ok());
+
+        // no manage permission
+        set_caller(user);
+        let result = copy_permissions("./.test/from".to_string(), "./.test/to".to_string(), false);
+        assert_eq!(result.unwrap_err().code, ERROR_PERMISSION_DENIED);
+        set_caller(owner);
+
+        // not found
+        let result = copy_permissions("./.test/none".to_string(), "./.test/to".to_string(), false);
+        assert_eq!(result.unwrap_err().code, ERROR_NOT_FOUND);
+    }
+
+    #[test]
+    fn test_audit_principal() {
+        let _context = setup();
+        let owner = caller();
+        let user = Principal::from_text("aaikz-lv7jd-phj2u-t6r4n-6gne4-3rv3x-jus4j-zbiaz-llnsl-jvk5j-iqe").unwrap(); // actor x 12
+
+        assert!(create_directory("./.test/dir".to_string()).is_ok());
+        assert!(create_directory("./.test/dir/sub".to_string()).is_ok());
+        let data = "Hello, World!".as_bytes().to_vec();
+        assert!(save("./.test/dir/sub/file.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+        assert!(save("./.test/other.txt".to_string(), "text/plain".to_string(), data, false).is_ok());
+        assert!(add_permission("./.test/dir".to_string(), user, false, true, false).is_ok());
+        assert!(add_permission("./.test/dir/sub/file.txt".to_string(), user, true, false, true).is_ok());
+        assert!(add_permission("./.test/other.txt".to_string(), user, false, true, false).is_ok());
+
+        // only explicit grants (dir/sub inherits read permission)
+        let grants = audit_principal("./.test/dir".to_string(), user).unwrap();
+        assert_eq!(grants.len(), 2);
+        assert_eq!(grants[0].0, "./.test/dir");
+        assert!(!grants[0].1.manageable && grants[0].1.readable && !grants[0].1.writable);
+        assert_eq!(grants[1].0, "./.test/dir/sub/file.txt");
+        assert!(grants[1].1.manageable && !grants[1].1.readable && grants[1].1.writable);
+        assert_eq!(audit_principal(ROOT.to_string(), user).unwrap().len(), 3);
+        assert!(audit_principal("./.test/dir/sub".to_string(), Principal::anonymous()).unwrap().is_empty());
+
+        // manage permission required
+        set_caller(user);
+        assert_eq!(audit_principal("./.test/dir".to_string(), user).unwrap_err().code, ERROR_PERMISSION_DENIED);
+        assert_eq!(audit_principal("./.test/dir/sub/file.txt".to_string(), user).unwrap().len(), 1);
+        set_caller(owner);
+
+        // not found
+        assert_eq!(audit_principal("./.test/none".to_string(), user).unwrap_err().code, ERROR_NOT_FOUND);
+
+        // a sibling sharing the name as a prefix isn't under the root
+        assert!(create_directory("./.test/dir-x".to_string()).is_ok());
+        assert!(add_permission("./.test/dir-x".to_string(), user, false, true, false).is_ok());
+        assert_eq!(audit_principal("./.test/dir".to_string(), user).unwrap().len(), 2);
+
+        // too many
+        for i in 0..MAX_AUDIT_SIZE {
+            set_file_info(&format!("./.test/dir/{:04}", i), &FileInfo {
+                readable: vec![user],
+                ..FileInfo::new(owner, 0, "text/plain".to_string())
+            }).unwrap();
+        }
+        assert_eq!(audit_principal("./.test/dir".to_string(), user).unwrap_err().code, ERROR_INVALID_SIZE);
+    }
+
+    #[test]
+    fn test_find_modified() {
+        let _context = setup();
+        let owner = caller();
+        let user = Principal::from_text("aaikz-lv7jd-phj2u-t6r4n-6gne4-3rv3x-jus4j-zbiaz-llnsl-jvk5j-iqe").unwrap(); // actor x 12
+
+        assert!(create_directory("./.test/dir".to_string()).is_ok());
+        let data = "Hello, World!".as_bytes().to_vec();
+        assert!(save("./.test/dir/owner.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+        assert!(add_permission("./.test/dir".to_string(), user, false, true, true).is_ok());
+        set_caller(user);
+        assert!(save("./.test/dir/user.txt".to_string(), "text/plain".to_string(), data, false).is_ok());
+        set_caller(owner);
+
+        assert_eq!(find_modified("./.test/dir".to_string(), None, 0).unwrap(),
+            vec!["./.test/dir", "./.test/dir/owner.txt", "./.test/dir/user.txt"]);
+        assert_eq!(find_modified("./.test/dir".to_string(), Some(user), 0).unwrap(), vec!["./.test/dir/user.txt"]);
+        assert_eq!(find_modified(ROOT.to_string(), Some(owner), 0).unwrap(),
+            vec![ROOT, "./.test/dir", "./.test/dir/owner.txt"]);
+
+        // a sibling sharing the name isn't under the directory
+        assert!(create_directory("./.test/dir-x".to_string()).is_ok());
+        assert_eq!(find_modified("./.test/dir".to_string(), Some(owner), 0).unwrap(), vec!["./.test/dir", "./.test/dir/owner.txt"]);
+        assert!(delete_directory("./.test/dir-x".to_string(), false).is_ok());
+
+        // since
+        let updated_at = get_info("./.test/dir/user.txt".to_string()).unwrap().updated_at;
+        assert!(find_modified("./.test/dir".to_string(), None, updated_at).unwrap().contains(&"./.test/dir/user.txt".to_string()));
+        assert!(find_modified("./.test/dir".to_string(), None, updated_at + 1).unwrap().is_empty());
+
+        // manage permission required
+        set_caller(user);
+        assert_eq!(find_modified("./.test/dir".to_string(), None, 0).unwrap_err().code, ERROR_PERMISSION_DENIED);
+        set_caller(owner);
+
+        // not found
+        assert_eq!(find_modified("./.test/none".to_string(), None, 0).unwrap_err().code, ERROR_NOT_FOUND);
+    }
+
+    #[test]
+    fn test_manifest() {
+        let _context = setup();
+        let owner = caller();
+        let user = Principal::from_text("aaikz-lv7jd-phj2u-t6r4n-6gne4-3rv3x-jus4j-zbiaz-llnsl-jvk5j-iqe").unwrap(); // actor x 12
+
+        assert!(create_directory("./.test/dir".to_string()).is_ok());
+        assert!(create_directory("./.test/dir/sub".to_string()).is_ok());
+        let data = "Hello, World!".as_bytes().to_vec();
+        assert!(save("./.test/dir/a.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+        assert!(save("./.test/dir/sub/b.txt".to_string(), "text/plain".to_string(), vec![], false).is_ok());
+
+        let entries = manifest("./.test/dir".to_string(), None).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].path, "./.test/dir/a.txt");
+        assert_eq!(entries[0].size, data.len() as u64);
+        assert_eq!(entries[0].sha256, Some(Sha256::digest(&data).into()));
+        assert_eq!(entries[1].path, "./.test/dir/sub/b.txt");
+        assert_eq!(entries[1].size, 0);
+
+        // a file as root
+        assert_eq!(manifest("./.test/dir/a.txt".to_string(), None).unwrap().len(), 1);
+
+        // a sibling sharing the name isn't under the directory
+        assert!(save("./.test/dir-x.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+        assert_eq!(manifest("./.test/dir".to_string(), None).unwrap().len(), 2);
+        assert!(delete("./.test/dir-x.txt".to_string()).is_ok());
+
+        // since
+        let updated_at = entries[1].updated_at;
+        assert!(manifest("./.test/dir".to_string(), Some(updated_at)).unwrap().iter().any(|entry| entry.path == "./.test/dir/sub/b.txt"));
+        assert!(manifest("./.test/dir".to_string(), Some(updated_at + 1)).unwrap().is_empty());
+
+        // unreadable files are skipped
+        assert!(add_permission("./.test/dir".to_string(), user, false, true, false).is_ok());
+        assert!(deny_permission("./.test/dir/sub".to_string(), user, true, false).is_ok());
+        set_caller(user);
+        let entries = manifest("./.test/dir".to_string(), None).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].path, "./.test/dir/a.txt");
+        assert_eq!(manifest("./.test/dir/sub".to_string(), None).unwrap_err().code, ERROR_PERMISSION_DENIED);
+        set_caller(owner);
+
+        // not found
+        assert_eq!(manifest("./.test/none".to_string(), None).unwrap_err().code, ERROR_NOT_FOUND);
+    }
+
+    #[test]
+    fn test_set_acl() {
+        let _context = setup();
+        let owner = caller();
+        let user = Principal::from_text("aaikz-lv7jd-phj2u-t6r4n-6gne4-3rv3x-jus4j-zbiaz-llnsl-jvk5j-iqe").unwrap(); // actor x 12
+
+        assert!(create_directory("./.test/dir".to_string()).is_ok());
+
+        // dedup and sort
+        let result = set_acl("./.test/dir".to_string(), vec![], vec![user, owner, user], vec![user]);
+        assert!(result.is_ok());
+        let info = get_file_info(&"./.test/dir".to_string()).unwrap();
+        let mut expected = vec![user, owner];
+        expected.sort();
+        assert_eq!(info.readable, expected);
+        assert_eq!(info.writable, vec![user]);
+        assert!(info.manageable.is_empty());
+
+        // overwrite (denials are cleared)
+        assert!(deny_permission("./.test/dir".to_string(), user, true, true).is_ok());
+        let result = set_acl("./.test/dir".to_string(), vec![user], vec![], vec![]);
+        assert!(result.is_ok());
+        let info = get_file_info(&"./.test/dir".to_string()).unwrap();
+        assert_eq!(info.manageable, vec![user]);
+        assert!(info.readable.is_empty());
+        assert!(info.writable.is_empty());
+        assert!(info.denied_readable.is_empty());
+        assert!(info.denied_writable.is_empty());
+
+        // no manager would remain
+        let result = set_acl(ROOT.to_string(), vec![], vec![owner], vec![owner]);
+        assert_eq!(result.unwrap_err().code, ERROR_LAST_MANAGER);
+        let info = get_file_info(&ROOT.to_string()).unwrap();
+        assert_eq!(info.manageable, vec![owner]);
+
+        // no manage permission
+        set_caller(user);
+        let result = set_acl(ROOT.to_string(), vec![user], vec![user], vec![user]);
+        assert_eq!(result.unwrap_err().code, ERROR_PERMISSION_DENIED);
     }
 
     #[test]
@@ -1480,19 +8224,305 @@ mod tests {
         // test on test_add_permission()
     }
 
+    #[test]
+    fn test_remove_last_manager() {
+        let _context = setup();
+        let owner = caller();
+        let user = Principal::from_text("aaikz-lv7jd-phj2u-t6r4n-6gne4-3rv3x-jus4j-zbiaz-llnsl-jvk5j-iqe").unwrap(); // actor x 12
+
+        // sole manager
+        let result = remove_permission(ROOT.to_string(), owner, true, false, false);
+        assert_eq!(result.unwrap_err().code, ERROR_LAST_MANAGER);
+        assert!(has_permission(ROOT.to_string()).unwrap().manageable);
+
+        // non-last manager
+        assert!(add_permission(ROOT.to_string(), user, true, false, false).is_ok());
+        let result = remove_permission(ROOT.to_string(), owner, true, false, false);
+        assert!(result.is_ok());
+        set_caller(user);
+        let result = remove_permission(ROOT.to_string(), user, true, false, false);
+        assert_eq!(result.unwrap_err().code, ERROR_LAST_MANAGER);
+    }
+
     #[test]
     fn test_has_permission() {
         // test on test_add_permission()
     }
 
+    #[test]
+    fn test_get_raw_info() {
+        let _context = setup();
+        let owner = caller();
+        let user = Principal::from_text("aaikz-lv7jd-phj2u-t6r4n-6gne4-3rv3x-jus4j-zbiaz-llnsl-jvk5j-iqe").unwrap(); // actor x 12
+        let data = "Hello, World!".as_bytes().to_vec();
+        assert!(save("./.test/file.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+        assert!(set_signature("./.test/file.txt".to_string(), vec![1, 2, 3]).is_ok());
+        assert!(add_permission("./.test/file.txt".to_string(), user, false, true, true).is_ok());
+
+        let info = get_raw_info("./.test/file.txt".to_string()).unwrap();
+        assert_eq!(info.creator, owner);
+        assert_eq!(info.size, data.len() as u64);
+        assert_eq!(info.sha256, Some(Sha256::digest(&data).into()));
+        assert_eq!(info.signature, Some(vec![1, 2, 3]));
+        assert_eq!(info.readable, vec![user]);
+        assert!(get_raw_info(ROOT.to_string()).unwrap().is_dir());
+        assert_eq!(get_raw_info("./.test/none.txt".to_string()).unwrap_err().code, ERROR_NOT_FOUND);
+
+        // read and write permissions aren't enough
+        set_caller(user);
+        assert!(get_info("./.test/file.txt".to_string()).is_ok());
+        assert_eq!(get_raw_info("./.test/file.txt".to_string()).unwrap_err().code, ERROR_PERMISSION_DENIED);
+        assert_eq!(get_raw_info("./.test/none.txt".to_string()).unwrap_err().code, ERROR_PERMISSION_DENIED);
+        set_caller(Principal::anonymous());
+        assert_eq!(get_raw_info("./.test/file.txt".to_string()).unwrap_err().code, ERROR_PERMISSION_DENIED);
+    }
+
+    #[test]
+    fn test_listable() {
+        let _context = setup();
+        let owner = caller();
+        let user = Principal::from_text("aaikz-lv7jd-phj2u-t6r4n-6gne4-3rv3x-jus4j-zbiaz-llnsl-jvk5j-iqe").unwrap(); // actor x 12
+        let data = "Hello, World!".as_bytes().to_vec();
+        assert!(create_directory("./.test/dir".to_string()).is_ok());
+        assert!(create_directory("./.test/dir/sub".to_string()).is_ok());
+        assert!(save("./.test/dir/file.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+
+        set_caller(user);
+        assert_eq!(list_files("./.test/dir".to_string()).unwrap_err().code, ERROR_PERMISSION_DENIED);
+        assert_eq!(set_listable("./.test/dir".to_string(), vec![user]).unwrap_err().code, ERROR_PERMISSION_DENIED);
+        set_caller(owner);
+        assert_eq!(set_listable("./.test/dir/file.txt".to_string(), vec![user]).unwrap_err().code, ERROR_NOT_A_DIRECTORY);
+        assert_eq!(set_listable("./.test/none".to_string(), vec![user]).unwrap_err().code, ERROR_NOT_FOUND);
+        assert!(set_listable("./.test/dir".to_string(), vec![user, user]).is_ok());
+        assert_eq!(get_info("./.test/dir".to_string()).unwrap().acl.unwrap().listable, vec![user]);
+
+        // names are listed, contents aren't readable
+        set_caller(user);
+        assert_eq!(list_files("./.test/dir".to_string()).unwrap(), vec!["file.txt", "sub/"]);
+        assert_eq!(list_entries("./.test/dir".to_string()).unwrap().len(), 2);
+        assert_eq!(list_files_sorted("./.test/dir".to_string(), SortKey::Name, false, false).unwrap().len(), 2);
+        assert!(list_files("./.test/dir/sub".to_string()).unwrap().is_empty()); // inherited
+        assert_eq!(load("./.test/dir/file.txt".to_string(), 0, None).unwrap_err().code, ERROR_PERMISSION_DENIED);
+        let permission = has_permission("./.test/dir".to_string()).unwrap();
+        assert!(permission.listable);
+        assert!(!permission.readable);
+
+        // a denial of read permission denies listing
+        set_caller(owner);
+        assert!(deny_permission("./.test/dir/sub".to_string(), user, true, false).is_ok());
+        set_caller(user);
+        assert_eq!(list_files("./.test/dir/sub".to_string()).unwrap_err().code, ERROR_PERMISSION_DENIED);
+
+        // readable without listable (backward compatible)
+        set_caller(owner);
+        assert!(set_listable("./.test/dir".to_string(), vec![]).is_ok());
+        assert!(add_permission("./.test/dir".to_string(), user, false, true, false).is_ok());
+        set_caller(user);
+        assert_eq!(list_files("./.test/dir".to_string()).unwrap(), vec!["file.txt", "sub/"]);
+        assert!(has_permission("./.test/dir".to_string()).unwrap().listable);
+    }
+
+    #[test]
+    fn test_can_write_here() {
+        let _context = setup();
+        let user = Principal::from_text("aaikz-lv7jd-phj2u-t6r4n-6gne4-3rv3x-jus4j-zbiaz-llnsl-jvk5j-iqe").unwrap(); // actor x 12
+        let data = "Hello, World!".as_bytes().to_vec();
+        assert!(create_directory("./.test/dir".to_string()).is_ok());
+        assert!(create_directory("./.test/other".to_string()).is_ok());
+        assert!(save("./.test/dir/file.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+        assert!(add_permission("./.test/dir".to_string(), user, false, false, true).is_ok());
+
+        set_caller(user);
+        assert_eq!(has_permission("./.test/dir/new.txt".to_string()).unwrap_err().code, ERROR_NOT_FOUND);
+        assert!(can_write_here("./.test/dir/new.txt".to_string()).unwrap());
+        assert!(can_write_here("./.test/dir/sub/new.txt".to_string()).unwrap());
+        assert!(can_write_here("./.test/dir/file.txt".to_string()).unwrap());
+        assert!(!can_write_here("./.test/other/new.txt".to_string()).unwrap());
+        assert!(!can_write_here("./.test/new.txt".to_string()).unwrap());
+        assert_eq!(can_write_here("./.test/`new.txt".to_string()).unwrap_err().code, ERROR_INVALID_PATH);
+
+        // agrees with save
+        assert!(save("./.test/dir/new.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+        assert_eq!(save("./.test/other/new.txt".to_string(), "text/plain".to_string(), data, false).unwrap_err().code, ERROR_PERMISSION_DENIED);
+    }
+
+    #[test]
+    fn test_read_only() {
+        let _context = setup();
+        let user = Principal::from_text("aaikz-lv7jd-phj2u-t6r4n-6gne4-3rv3x-jus4j-zbiaz-llnsl-jvk5j-iqe").unwrap(); // actor x 12
+
+        let data = "Hello, World!".as_bytes().to_vec();
+        assert!(save("./.test/file.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+
+        // admin only
+        let owner = caller();
+        set_caller(user);
+        assert_eq!(set_read_only(true).unwrap_err().code, ERROR_PERMISSION_DENIED);
+        set_caller(owner);
+
+        assert!(set_read_only(true).is_ok());
+        assert!(is_read_only());
+        let result = save("./.test/file.txt".to_string(), "text/plain".to_string(), data.clone(), true);
+        assert_eq!(result.unwrap_err().code, ERROR_READ_ONLY);
+        let result = delete("./.test/file.txt".to_string());
+        assert_eq!(result.unwrap_err().code, ERROR_READ_ONLY);
+        let result = create_directory("./.test/dir".to_string());
+        assert_eq!(result.unwrap_err().code, ERROR_READ_ONLY);
+        let result = begin_upload("./.test/large.bin".to_string(), "application/octet-stream".to_string(), false, 0);
+        assert_eq!(result.unwrap_err().code, ERROR_READ_ONLY);
+        let result = add_permission(ROOT.to_string(), user, false, true, false);
+        assert_eq!(result.unwrap_err().code, ERROR_READ_ONLY);
+
+        // queries are unaffected
+        assert_eq!(load("./.test/file.txt".to_string(), 0, None).unwrap().chunk, data);
+        assert_eq!(list_files(ROOT.to_string()).unwrap().len(), 1);
+
+        assert!(set_read_only(false).is_ok());
+        assert!(!is_read_only());
+        assert!(delete("./.test/file.txt".to_string()).is_ok());
+    }
+
+    #[test]
+    fn test_health() {
+        let _context = setup();
+
+        set_cycle_balance(1_000_000);
+        set_stable_size(16);
+        let health = health();
+        assert_eq!(health.cycles, 1_000_000);
+        assert_eq!(health.stable_memory_pages, 16);
+        assert_eq!(health.remaining_bytes, MAX_STABLE_MEMORY - 16 * WASM_PAGE_SIZE);
+        assert_eq!(health.min_cycles, 0);
+
+        // writes are refused below the minimum
+        let data = "Hello, World!".as_bytes().to_vec();
+        assert!(set_min_cycles(2_000_000).is_ok());
+        let result = save("./.test/file.txt".to_string(), "text/plain".to_string(), data.clone(), false);
+        assert_eq!(result.unwrap_err().code, ERROR_LOW_CYCLES);
+        assert!(set_min_cycles(500_000).is_ok());
+        assert!(save("./.test/file.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+
+        // admin only
+        set_caller(Principal::from_text("aaikz-lv7jd-phj2u-t6r4n-6gne4-3rv3x-jus4j-zbiaz-llnsl-jvk5j-iqe").unwrap()); // actor x 12
+        assert_eq!(set_min_cycles(0).unwrap_err().code, ERROR_PERMISSION_DENIED);
+    }
+
+    #[test]
+    fn test_out_of_space() {
+        let _context = setup();
+
+        let data = "Hello, World!".as_bytes().to_vec();
+        assert!(set_max_storage(STORAGE_MARGIN + 1024 * 1024).is_ok());
+        set_stable_size(8); // 512KiB used
+        assert_eq!(health().remaining_bytes, STORAGE_MARGIN + 512 * 1024);
+
+        assert!(save("./.test/file.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+        let large = vec![0u8; 600 * 1024];
+        let result = save("./.test/large.bin".to_string(), "application/octet-stream".to_string(), large.clone(), false);
+        assert_eq!(result.unwrap_err().code, ERROR_OUT_OF_SPACE);
+        assert!(get_file_info(&"./.test/large.bin".to_string()).is_none());
+        assert!(fs::metadata(temp_path(&"./.test/large.bin".to_string())).is_err());
+        let result = begin_upload("./.test/large.bin".to_string(), "application/octet-stream".to_string(), false, large.len() as u64);
+        assert_eq!(result.unwrap_err().code, ERROR_OUT_OF_SPACE);
+
+        // the space is used up during uploading (retryable)
+        set_stable_size(0);
+        assert!(begin_upload("./.test/large.bin".to_string(), "application/octet-stream".to_string(), false, large.len() as u64).is_ok());
+        assert!(send_data("./.test/large.bin".to_string(), 0, large.clone()).is_ok());
+        set_stable_size(8);
+        let result = commit_upload("./.test/large.bin".to_string(), large.len() as u64, None);
+        assert_eq!(result.unwrap_err().code, ERROR_OUT_OF_SPACE);
+        set_stable_size(0);
+        assert!(commit_upload("./.test/large.bin".to_string(), large.len() as u64, None).is_ok());
+    }
+
+    #[test]
+    fn test_lock() {
+        let _context = setup();
+        let owner = caller();
+        let user = Principal::from_text("aaikz-lv7jd-phj2u-t6r4n-6gne4-3rv3x-jus4j-zbiaz-llnsl-jvk5j-iqe").unwrap(); // actor x 12
+        assert!(add_permission(ROOT.to_string(), user, false, true, true).is_ok());
+
+        let path = "./.test/file.txt".to_string();
+        let data = "Hello, World!".as_bytes().to_vec();
+        assert!(save(path.clone(), "text/plain".to_string(), data.clone(), false).is_ok());
+
+        // lock by user
+        set_caller(user);
+        assert!(lock(path.clone()).is_ok());
+        assert_eq!(who_holds_lock(path.clone()).unwrap(), Some(user));
+        assert!(save(path.clone(), "text/plain".to_string(), data.clone(), true).is_ok());
+
+        // others can't write
+        set_caller(owner);
+        let result = save(path.clone(), "text/plain".to_string(), data.clone(), true);
+        assert_eq!(result.unwrap_err().code, ERROR_LOCKED);
+        let result = delete(path.clone());
+        assert_eq!(result.unwrap_err().code, ERROR_LOCKED);
+        let result = lock(path.clone());
+        assert_eq!(result.unwrap_err().code, ERROR_LOCKED);
+
+        // unlock
+        set_caller(user);
+        assert!(unlock(path.clone()).is_ok());
+        assert_eq!(who_holds_lock(path.clone()).unwrap(), None);
+        set_caller(owner);
+        assert!(save(path.clone(), "text/plain".to_string(), data.clone(), true).is_ok());
+
+        // directories holding a locked path
+        assert!(create_directory("./.test/dir".to_string()).is_ok());
+        assert!(save("./.test/dir/file.txt".to_string(), "text/plain".to_string(), data.clone(), false).is_ok());
+        set_caller(user);
+        assert!(lock("./.test/dir/file.txt".to_string()).is_ok());
+        set_caller(owner);
+        assert_eq!(move_path("./.test/dir".to_string(), "./.test/moved".to_string()).unwrap_err().code, ERROR_LOCKED);
+        assert_eq!(move_directory("./.test/dir".to_string(), "./.test/moved".to_string(), None).unwrap_err().code, ERROR_LOCKED);
+        assert!(get_file_info(&"./.test/moved".to_string()).is_none());
+        assert!(delete_directory("./.test/dir".to_string(), true).is_err());
+        assert_eq!(load("./.test/dir/file.txt".to_string(), 0, None).unwrap().chunk, data);
+        set_caller(user);
+        assert!(unlock("./.test/dir/file.txt".to_string()).is_ok());
+        assert!(lock("./.test/dir".to_string()).is_ok());
+        set_caller(owner);
+        assert_eq!(delete_directory("./.test/dir".to_string(), true).unwrap_err().code, ERROR_LOCKED);
+        set_caller(user);
+        assert!(unlock("./.test/dir".to_string()).is_ok());
+        set_caller(owner);
+        assert!(move_directory("./.test/dir".to_string(), "./.test/moved".to_string(), None).unwrap().done);
+        assert!(delete_directory("./.test/moved".to_string(), true).is_ok());
+
+        // expired
+        set_caller(user);
+        assert!(lock(path.clone()).is_ok());
+        set_caller(owner);
+        assert!(set_lock_ttl(0).is_ok());
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        assert_eq!(who_holds_lock(path.clone()).unwrap(), None);
+        assert!(delete(path.clone()).is_ok());
+        assert!(LOCKS.with(|locks| locks.borrow().is_empty())); // removed when checked
+
+        // expired locks are purged by the next lock
+        set_caller(user);
+        assert!(lock("./.test/a.txt".to_string()).is_ok());
+        assert!(lock("./.test/b.txt".to_string()).is_ok());
+        set_caller(owner);
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        assert!(lock("./.test/c.txt".to_string()).is_ok());
+        assert_eq!(LOCKS.with(|locks| locks.borrow().len()), 1);
+    }
+
     #[test]
     fn test_upload() {
         let _context = setup();
         let path = "./.test/file.txt".to_string();
-        let result = begin_upload(path.clone(), "text/plain".to_string(), false);
+        let result = begin_upload(path.clone(), "text/plain".to_string(), false, 12);
         assert!(result.is_ok());
+        let upload = result.unwrap();
+        assert_eq!(upload.size, 12);
+        assert_eq!(upload.chunk_size, CHUNK_SIZE);
+        assert_eq!(upload.chunk_count, 1);
 
-        let mut index = 0 as u64;
+        let mut index = 0u64;
         let data = "AAA".as_bytes().to_vec();
         let result = send_data(path.clone(), index, data.clone());
         index += data.len() as u64;
@@ -1516,11 +8546,292 @@ mod tests {
         let result = commit_upload(path.clone(), index, Some(Sha256::digest(expected).into()));
         assert!(result.is_ok());
 
-        let result = load(path.clone(), 0);
+        let result = load(path.clone(), 0, None);
         assert!(result.is_ok());
         assert_eq!(result.unwrap().chunk, expected);
     }
 
+    #[test]
+    fn test_commit_upload_missing_chunk() {
+        let _context = setup();
+        let path = "./.test/file.bin".to_string();
+        assert!(begin_upload(path.clone(), "application/octet-stream".to_string(), false, 20).is_ok());
+        assert!(send_data(path.clone(), 0, vec![1; 5]).is_ok());
+        assert!(send_data(path.clone(), 10, vec![3; 5]).is_ok());
+
+        // gap in the middle
+        let error = commit_upload(path.clone(), 20, None).unwrap_err();
+        assert_eq!((error.code, error.message), (ERROR_INVALID_SEQUENCE, "Missing chunk at 5".to_string()));
+
+        // gap at the end
+        assert!(send_data(path.clone(), 5, vec![2; 5]).is_ok());
+        let error = commit_upload(path.clone(), 20, None).unwrap_err();
+        assert_eq!((error.code, error.message), (ERROR_INVALID_SEQUENCE, "Missing chunk at 15".to_string()));
+
+        assert!(send_data(path.clone(), 15, vec![4; 5]).is_ok());
+        assert!(commit_upload(path.clone(), 20, None).is_ok());
+        assert_eq!(load(path, 0, None).unwrap().chunk, [vec![1; 5], vec![2; 5], vec![3; 5], vec![4; 5]].concat());
+    }
+
+    #[test]
+    fn test_commit_upload_wrong_size() {
+        let _context = setup();
+        let path = "./.test/file.bin".to_string();
+        assert!(begin_upload(path.clone(), "application/octet-stream".to_string(), false, 10).is_ok());
+        assert!(send_data(path.clone(), 0, vec![1; 10]).is_ok());
+
+        for size in [9, 11] {
+            assert_eq!(commit_upload(path.clone(), size, None).unwrap_err().code, ERROR_INVALID_SIZE);
+        }
+        // the session is kept
+        assert!(commit_upload(path, 10, None).is_ok());
+
+        // overlapping chunks
+        assert_eq!(check_chunks(&BTreeMap::from([(0, vec![1; 6]), (5, vec![2; 5])]), 11).unwrap_err().code, ERROR_INVALID_SIZE);
+        assert_eq!(check_chunks(&BTreeMap::from([(0, vec![1; 6])]), 5).unwrap_err().code, ERROR_INVALID_SIZE);
+        assert!(check_chunks(&BTreeMap::from([(0, vec![1; 5]), (5, vec![2; 5])]), 10).is_ok());
+    }
+
+    #[test]
+    fn test_save_after_expired_upload() {
+        let _context = setup();
+        let path = "./.test/file.txt".to_string();
+        let data = "Hello, World!".as_bytes().to_vec();
+
+        // an active session blocks save
+        assert!(begin_upload(path.clone(), "text/plain".to_string(), false, 12).is_ok());
+        let result = save(path.clone(), "text/plain".to_string(), data.clone(), false);
+        assert_eq!(result.unwrap_err().code, ERROR_ALREADY_EXISTS);
+
+        // an expired one doesn't, and is evicted
+        UPLOADING.with(|uploading| {
+            uploading.borrow_mut().get_mut(&path).unwrap().updated_at = time() - UPLOAD_TIMEOUT - 1;
+        });
+        assert!(save(path.clone(), "text/plain".to_string(), data.clone(), false).is_ok());
+        assert!(UPLOADING.with(|uploading| uploading.borrow().get(&path).is_none()));
+        assert_eq!(load(path.clone(), 0, None).unwrap().chunk, data);
+
+        // begin_upload too
+        assert!(begin_upload(path.clone(), "text/plain".to_string(), true, 12).is_ok());
+        UPLOADING.with(|uploading| {
+            uploading.borrow_mut().get_mut(&path).unwrap().updated_at = time() - UPLOAD_TIMEOUT - 1;
+        });
+        assert!(begin_upload(path.clone(), "text/plain".to_string(), true, 12).is_ok());
+    }
+
+    #[test]
+    fn test_cancel_upload() {
+        let _context = setup();
+        let path = "./.test/file.txt".to_string();
+
+        assert!(begin_upload(path.clone(), "text/plain".to_string(), false, 6).is_ok());
+        assert!(send_data(path.clone(), 0, b"AAA".to_vec()).is_ok());
+        fs::write(temp_path(&path), b"AAA").unwrap(); // as if commit partially ran
+        assert!(cancel_upload(path.clone()).is_ok());
+
+        // no session, temp or metadata remains
+        assert!(UPLOADING.with(|uploading| uploading.borrow().get(&path).is_none()));
+        let entries:Vec<String> = fs::read_dir(ROOT).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .filter(|name| name.contains("file.txt"))
+            .collect();
+        assert!(entries.is_empty(), "{:?}", entries);
+        assert_eq!(cancel_upload(path.clone()).unwrap_err().code, ERROR_INVALID_SEQUENCE);
+    }
+
+    #[test]
+    fn test_send_data_limit() {
+        let _context = setup();
+        let path = "./.test/file.bin".to_string();
+        let size = CHUNK_SIZE * 2;
+        assert!(begin_upload(path.clone(), "application/octet-stream".to_string(), false, size).is_ok());
+
+        // oversized chunk
+        let result = send_data(path.clone(), 0, vec![0u8; CHUNK_SIZE as usize + 1]);
+        assert_eq!(result.unwrap_err().code, ERROR_INVALID_SIZE);
+
+        // beyond the expected size
+        let result = send_data(path.clone(), CHUNK_SIZE + 1, vec![0u8; CHUNK_SIZE as usize]);
+        assert_eq!(result.unwrap_err().code, ERROR_INVALID_SIZE);
+
+        assert_eq!(send_data(path.clone(), 0, vec![0u8; CHUNK_SIZE as usize]).unwrap(), CHUNK_SIZE);
+        assert_eq!(send_data(path.clone(), CHUNK_SIZE, vec![0u8; CHUNK_SIZE as usize]).unwrap(), size);
+        assert!(commit_upload(path.clone(), size, None).is_ok());
+    }
+
+    #[test]
+    fn test_commit_upload_created_meanwhile() {
+        let _context = setup();
+        let data = "Hello, World!".as_bytes().to_vec();
+
+        // created by someone else after beginUpload without overwrite
+        let path = "./.test/file.txt".to_string();
+        assert!(begin_upload(path.clone(), "text/plain".to_string(), false, data.len() as u64).is_ok());
+        assert!(send_data(path.clone(), 0, data.clone()).is_ok());
+        copy_other(&path);
+        assert_eq!(commit_upload(path.clone(), data.len() as u64, None).unwrap_err().code, ERROR_ALREADY_EXISTS);
+        assert_eq!(load(path.clone(), 0, None).unwrap().chunk, "Other".as_bytes());
+        assert_eq!(commit_upload(path.clone(), data.len() as u64, None).unwrap_err().code, ERROR_INVALID_SEQUENCE); // session removed
+
+        // with overwrite
+        let path = "./.test/file2.txt".to_string();
+        assert!(begin_upload(path.clone(), "text/plain".to_string(), true, data.len() as u64).is_ok());
+        assert!(send_data(path.clone(), 0, data.clone()).is_ok());
+        copy_other(&path);
+        assert!(commit_upload(path.clone(), data.len() as u64, None).is_ok());
+        assert_eq!(load(path, 0, None).unwrap().chunk, data);
+
+        // directory created meanwhile
+        let path = "./.test/dir".to_string();
+        assert!(begin_upload(path.clone(), "text/plain".to_string(), true, data.len() as u64).is_ok());
+        assert!(send_data(path.clone(), 0, data.clone()).is_ok());
+        assert!(create_directory(path.clone()).is_ok());
+        assert_eq!(commit_upload(path, data.len() as u64, None).unwrap_err().code, ERROR_IS_DIRECTORY);
+    }
+
+    /// copies a file to the path as copyDirectory does, regardless of upload sessions
+    fn copy_other(path:&String) {
+        let other = "./.test/other.txt".to_string();
+        if get_file_info(&other).is_none() {
+            assert!(save(other.clone(), "text/plain".to_string(), "Other".as_bytes().to_vec(), false).is_ok());
+        }
+        assert!(copy_entry(&other, path, &get_file_info(&other).unwrap(), &caller()).is_ok());
+    }
+
+    #[test]
+    fn test_commit_upload_rules_changed() {
+        let _context = setup();
+        let owner = caller();
+        let user = Principal::from_text("aaikz-lv7jd-phj2u-t6r4n-6gne4-3rv3x-jus4j-zbiaz-llnsl-jvk5j-iqe").unwrap(); // actor x 12
+        let data = "Hello, World!".as_bytes().to_vec();
+
+        // write permission revoked
+        assert!(create_directory("./.test/dir".to_string()).is_ok());
+        assert!(add_permission("./.test/dir".to_string(), user, false, false, true).is_ok());
+        set_caller(user);
+        assert!(begin_upload("./.test/dir/file.txt".to_string(), "text/plain".to_string(), false, data.len() as u64).is_ok());
+        assert!(send_data("./.test/dir/file.txt".to_string(), 0, data.clone()).is_ok());
+        set_caller(owner);
+        assert!(remove_permission("./.test/dir".to_string(), user, false, false, true).is_ok());
+        set_caller(user);
+        assert_eq!(commit_upload("./.test/dir/file.txt".to_string(), data.len() as u64, None).unwrap_err().code, ERROR_PERMISSION_DENIED);
+        set_caller(owner);
+        assert_eq!(get_info("./.test/dir/file.txt".to_string()).unwrap_err().code, ERROR_NOT_FOUND);
+
+        // mimetype denied
+        assert!(begin_upload("./.test/a.exe".to_string(), "application/x-msdownload".to_string(), false, data.len() as u64).is_ok());
+        assert!(send_data("./.test/a.exe".to_string(), 0, data.clone()).is_ok());
+        assert!(set_mimetype_policy(MimetypePolicy { allow: vec![], deny: vec!["application/x-msdownload".to_string()] }).is_ok());
+        let result = commit_upload("./.test/a.exe".to_string(), data.len() as u64, None);
+        assert!(set_mimetype_policy(MimetypePolicy { allow: vec![], deny: vec![] }).is_ok());
+        assert_eq!(result.unwrap_err().code, ERROR_INVALID_MIMETYPE);
+
+        // parent directory deleted
+        assert!(create_directory("./.test/tmp".to_string()).is_ok());
+        assert!(begin_upload("./.test/tmp/file.txt".to_string(), "text/plain".to_string(), false, data.len() as u64).is_ok());
+        assert!(send_data("./.test/tmp/file.txt".to_string(), 0, data.clone()).is_ok());
+        assert!(delete_directory("./.test/tmp".to_string(), false).is_ok());
+        assert_eq!(commit_upload("./.test/tmp/file.txt".to_string(), data.len() as u64, None).unwrap_err().code, ERROR_NOT_FOUND);
+        assert!(!fs::exists("./.test/tmp").unwrap());
+    }
+
+    #[test]
+    fn test_commit_upload_failure() {
+        let _context = setup();
+
+        // hash mismatch (retryable)
+        let path = "./.test/file.txt".to_string();
+        let data = "Hello, World!".as_bytes().to_vec();
+        assert!(begin_upload(path.clone(), "text/plain".to_string(), false, data.len() as u64).is_ok());
+        assert!(send_data(path.clone(), 0, data.clone()).is_ok());
+        let result = commit_upload(path.clone(), data.len() as u64, Some([0u8; 32]));
+        assert_eq!(result.unwrap_err().code, ERROR_INVALID_HASH);
+        let result = commit_upload(path.clone(), data.len() as u64, Some(Sha256::digest(&data).into()));
+        let info = result.unwrap();
+        assert_eq!(info.size, data.len() as u64);
+        assert_eq!(info.sha256, Some(Sha256::digest(&data).into()));
+        assert_eq!(info.mimetype, "text/plain");
+        assert!(!info.is_dir);
+        assert_eq!(load(path.clone(), 0, None).unwrap().chunk, data);
+
+        // size mismatch (retryable)
+        let path = "./.test/file2.txt".to_string();
+        assert!(begin_upload(path.clone(), "text/plain".to_string(), false, data.len() as u64 * 2).is_ok());
+        assert!(send_data(path.clone(), 0, data.clone()).is_ok());
+        let result = commit_upload(path.clone(), data.len() as u64, None);
+        assert_eq!(result.unwrap_err().code, ERROR_INVALID_SIZE);
+        let result = commit_upload(path.clone(), data.len() as u64 * 2, None);
+        assert_eq!(result.unwrap_err().code, ERROR_INVALID_SEQUENCE);
+        assert!(send_data(path.clone(), data.len() as u64, data.clone()).is_ok());
+        let result = commit_upload(path.clone(), data.len() as u64 * 2, None);
+        assert!(result.is_ok());
+
+        // gap (retryable)
+        let path = "./.test/file3.txt".to_string();
+        assert!(begin_upload(path.clone(), "text/plain".to_string(), false, 10).is_ok());
+        assert!(send_data(path.clone(), 0, b"AAA".to_vec()).is_ok());
+        assert!(send_data(path.clone(), 4, b"BBB".to_vec()).is_ok());
+        assert!(send_data(path.clone(), 6, b"CCCC".to_vec()).is_ok());
+        let result = commit_upload(path.clone(), 10, None);
+        assert_eq!(result.unwrap_err().code, ERROR_INVALID_SEQUENCE);
+        assert!(send_data(path.clone(), 0, b"AAAA".to_vec()).is_ok());
+        assert!(send_data(path.clone(), 4, b"BB".to_vec()).is_ok());
+        assert!(commit_upload(path.clone(), 10, None).is_ok());
+        assert_eq!(load(path.clone(), 0, None).unwrap().chunk, b"AAAABBCCCC");
+
+        // overlap (retryable)
+        let path = "./.test/file4.txt".to_string();
+        assert!(begin_upload(path.clone(), "text/plain".to_string(), false, 10).is_ok());
+        assert!(send_data(path.clone(), 0, b"AAAAAA".to_vec()).is_ok());
+        assert!(send_data(path.clone(), 4, b"BBBB".to_vec()).is_ok());
+        let result = commit_upload(path.clone(), 10, None);
+        assert_eq!(result.unwrap_err().code, ERROR_INVALID_SIZE);
+        assert!(send_data(path.clone(), 0, b"AAAA".to_vec()).is_ok());
+        assert!(send_data(path.clone(), 8, b"CC".to_vec()).is_ok());
+        assert!(commit_upload(path.clone(), 10, None).is_ok());
+        assert_eq!(load(path.clone(), 0, None).unwrap().chunk, b"AAAABBBBCC");
+
+        // IO error (not retryable)
+        assert!(create_directory("./.test/dir".to_string()).is_ok());
+        let path = "./.test/dir/file.txt".to_string();
+        assert!(begin_upload(path.clone(), "text/plain".to_string(), false, data.len() as u64).is_ok());
+        assert!(send_data(path.clone(), 0, data.clone()).is_ok());
+        assert!(fs::remove_dir("./.test/dir").is_ok()); // the file info is left
+        let result = commit_upload(path.clone(), data.len() as u64, None);
+        assert_eq!(result.unwrap_err().code, ERROR_UNKNOWN);
+        let result = commit_upload(path.clone(), data.len() as u64, None);
+        assert_eq!(result.unwrap_err().code, ERROR_INVALID_SEQUENCE);
+        assert!(!fs::exists(temp_path(&path)).unwrap());
+    }
+
+    #[test]
+    fn test_empty_file() {
+        let _context = setup();
+        let empty_sha256:[u8; 32] = Sha256::digest([]).into();
+
+        // save
+        let path = "./.test/empty.txt".to_string();
+        assert!(save(path.clone(), "text/plain".to_string(), vec![], false).is_ok());
+        let download = load(path.clone(), 0, None).unwrap();
+        assert!(download.chunk.is_empty());
+        assert_eq!(download.size, 0);
+        assert_eq!(download.downloaded_at, 0);
+        assert_eq!(download.sha256, Some(empty_sha256));
+
+        // upload without chunks
+        let path = "./.test/empty.bin".to_string();
+        let upload = begin_upload(path.clone(), "application/octet-stream".to_string(), false, 0).unwrap();
+        assert_eq!(upload.chunk_count, 0);
+        assert!(commit_upload(path.clone(), 0, Some(empty_sha256)).is_ok());
+        let info = get_info(path.clone()).unwrap();
+        assert_eq!(info.size, 0);
+        assert_eq!(info.sha256, Some(empty_sha256));
+        let download = load(path.clone(), 0, None).unwrap();
+        assert!(download.chunk.is_empty());
+        assert_eq!(download.downloaded_at, 0);
+        assert_eq!(download.sha256, Some(empty_sha256));
+    }
+
     #[test]
     fn test_load_save_large_file() {
         let _context = setup();
@@ -1529,11 +8840,17 @@ mod tests {
         let path = "./.test/learge_file.bin".to_string();
 
         // Begin
-        let result = begin_upload(path.clone(), "application/octet-stream".to_string(), false);
+        let size = (MAX_READ_SIZE * "Hello, world".len()) as u64;
+        let result = begin_upload(path.clone(), "application/octet-stream".to_string(), false, size);
         assert!(result.is_ok());
+        assert_eq!(result.unwrap().chunk_count, "Hello, world".len() as u64);
+
+        // Too large
+        let result = begin_upload("./.test/too_large.bin".to_string(), "application/octet-stream".to_string(), false, MAX_FILE_SIZE + 1);
+        assert_eq!(result.unwrap_err().code, ERROR_INVALID_SIZE);
 
         // Send
-        let mut index = 0 as u64;
+        let mut index = 0u64;
         let mut hasher = Sha256::new();
         for i in "Hello, world".chars() {
             let buffer = vec![i as u8; MAX_READ_SIZE];
@@ -1556,7 +8873,7 @@ mod tests {
         let mut start_at = 0;
         let mut hasher = Sha256::new();
         let download = loop {
-            let result = load(path.clone(), start_at);
+            let result = load(path.clone(), start_at, None);
             assert!(result.is_ok());
             let download = result.unwrap();
             start_at = download.downloaded_at;
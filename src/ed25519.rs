@@ -0,0 +1,395 @@
+/// Canistorage
+///
+/// Copyright© 2025 toshio
+///
+/// Minimal ed25519 (RFC 8032) signature verifier (variable time; verification only)
+use sha2::{Digest, Sha512};
+
+const MASK:u64 = (1 << 51) - 1;
+
+/// p - 2 (little endian) for inversion
+const P_MINUS_2:[u8; 32] = [0xeb, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f];
+/// (p - 5) / 8 (little endian) for square root
+const P_MINUS_5_DIV_8:[u8; 32] = [0xfd, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x0f];
+/// group order L = 2^252 + 27742317777372353535851937790883648493
+const L:[u64; 4] = [0x5812_631a_5cf5_d3ed, 0x14de_f9de_a2f7_9cd6, 0, 0x1000_0000_0000_0000];
+/// encoded base point
+const BASE:[u8; 32] = [0x58, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66];
+
+/// verifies an ed25519 signature
+///
+/// # Arguments
+///
+/// * `public_key` - encoded public key (32 bytes)
+/// * `message` - signed message
+/// * `signature` - R || S (64 bytes)
+pub fn verify(public_key:&[u8], message:&[u8], signature:&[u8]) -> bool {
+    let (Ok(public_key), Ok(signature)) = (<[u8; 32]>::try_from(public_key), <[u8; 64]>::try_from(signature)) else {
+        return false;
+    };
+    let Some(a) = Point::decompress(&public_key) else {
+        return false;
+    };
+    let r:[u8; 32] = signature[0..32].try_into().unwrap();
+    let s:[u8; 32] = signature[32..64].try_into().unwrap();
+    let s_limbs = scalar_limbs(&s);
+    if !less_than(&s_limbs, &L) {
+        return false; // non-canonical S
+    }
+
+    let mut hasher = Sha512::new();
+    hasher.update(r);
+    hasher.update(public_key);
+    hasher.update(message);
+    let k = reduce_scalar(&hasher.finalize());
+
+    // R == [S]B - [k]A
+    let base = Point::decompress(&BASE).unwrap();
+    let check = base.multiply(&s_limbs).add(&a.negate().multiply(&k));
+    check.compress() == r
+}
+
+/// field element of GF(2^255 - 19) in radix 2^51
+#[derive(Clone, Copy)]
+struct Fe([u64; 5]);
+
+impl Fe {
+    const ZERO:Fe = Fe([0, 0, 0, 0, 0]);
+    const ONE:Fe = Fe([1, 0, 0, 0, 0]);
+
+    /// loads 255 bits (the top bit is ignored)
+    fn from_bytes(bytes:&[u8; 32]) -> Fe {
+        let load = |i:usize| u64::from_le_bytes(bytes[i..i + 8].try_into().unwrap());
+        Fe([
+            load(0) & MASK,
+            (load(6) >> 3) & MASK,
+            (load(12) >> 6) & MASK,
+            (load(19) >> 1) & MASK,
+            (load(24) >> 12) & MASK,
+        ])
+    }
+
+    /// returns the canonical encoding
+    fn to_bytes(self) -> [u8; 32] {
+        let mut limbs = Fe::reduce(self.0).0;
+
+        // subtract p if the value is p or more
+        let mut q = (limbs[0] + 19) >> 51;
+        for limb in limbs.iter().skip(1) {
+            q = (limb + q) >> 51;
+        }
+        limbs[0] += 19 * q;
+        for i in 0..4 {
+            limbs[i + 1] += limbs[i] >> 51;
+            limbs[i] &= MASK;
+        }
+        limbs[4] &= MASK;
+
+        let mut bytes = [0u8; 32];
+        let mut buffer:u128 = 0;
+        let mut bits = 0;
+        let mut index = 0;
+        for limb in limbs {
+            buffer |= (limb as u128) << bits;
+            bits += 51;
+            while bits >= 8 {
+                bytes[index] = buffer as u8;
+                buffer >>= 8;
+                bits -= 8;
+                index += 1;
+            }
+        }
+        bytes[index] = buffer as u8;
+        bytes
+    }
+
+    /// propagates carries so that every limb fits in 51 bits (limb 0 may slightly exceed)
+    fn reduce(mut limbs:[u64; 5]) -> Fe {
+        let carry:[u64; 5] = [limbs[0] >> 51, limbs[1] >> 51, limbs[2] >> 51, limbs[3] >> 51, limbs[4] >> 51];
+        for limb in limbs.iter_mut() {
+            *limb &= MASK;
+        }
+        limbs[0] += carry[4] * 19;
+        limbs[1] += carry[0];
+        limbs[2] += carry[1];
+        limbs[3] += carry[2];
+        limbs[4] += carry[3];
+        Fe(limbs)
+    }
+
+    fn add(&self, rhs:&Fe) -> Fe {
+        let mut limbs = self.0;
+        for (limb, r) in limbs.iter_mut().zip(rhs.0) {
+            *limb += r;
+        }
+        Fe::reduce(limbs)
+    }
+
+    fn sub(&self, rhs:&Fe) -> Fe {
+        // add 16p to avoid underflow
+        let rhs = Fe::reduce(rhs.0).0;
+        Fe::reduce([
+            (self.0[0] + 36_028_797_018_963_664) - rhs[0],
+            (self.0[1] + 36_028_797_018_963_952) - rhs[1],
+            (self.0[2] + 36_028_797_018_963_952) - rhs[2],
+            (self.0[3] + 36_028_797_018_963_952) - rhs[3],
+            (self.0[4] + 36_028_797_018_963_952) - rhs[4],
+        ])
+    }
+
+    fn negate(&self) -> Fe {
+        Fe::ZERO.sub(self)
+    }
+
+    fn mul(&self, rhs:&Fe) -> Fe {
+        let a = self.0;
+        let b = rhs.0;
+        let m = |x:u64, y:u64| (x as u128) * (y as u128);
+        let b1 = b[1] * 19;
+        let b2 = b[2] * 19;
+        let b3 = b[3] * 19;
+        let b4 = b[4] * 19;
+
+        let c0 = m(a[0], b[0]) + m(a[4], b1) + m(a[3], b2) + m(a[2], b3) + m(a[1], b4);
+        let mut c1 = m(a[1], b[0]) + m(a[0], b[1]) + m(a[4], b2) + m(a[3], b3) + m(a[2], b4);
+        let mut c2 = m(a[2], b[0]) + m(a[1], b[1]) + m(a[0], b[2]) + m(a[4], b3) + m(a[3], b4);
+        let mut c3 = m(a[3], b[0]) + m(a[2], b[1]) + m(a[1], b[2]) + m(a[0], b[3]) + m(a[4], b4);
+        let mut c4 = m(a[4], b[0]) + m(a[3], b[1]) + m(a[2], b[2]) + m(a[1], b[3]) + m(a[0], b[4]);
+
+        c1 += c0 >> 51;
+        c2 += c1 >> 51;
+        c3 += c2 >> 51;
+        c4 += c3 >> 51;
+        let carry = (c4 >> 51) as u64;
+        let mut limbs = [
+            (c0 as u64) & MASK,
+            (c1 as u64) & MASK,
+            (c2 as u64) & MASK,
+            (c3 as u64) & MASK,
+            (c4 as u64) & MASK,
+        ];
+        limbs[0] += carry * 19;
+        limbs[1] += limbs[0] >> 51;
+        limbs[0] &= MASK;
+        Fe(limbs)
+    }
+
+    fn square(&self) -> Fe {
+        self.mul(self)
+    }
+
+    /// raises to the power of the little endian exponent
+    fn pow(&self, exponent:&[u8; 32]) -> Fe {
+        let mut result = Fe::ONE;
+        for byte in exponent.iter().rev() {
+            for bit in (0..8).rev() {
+                result = result.square();
+                if (byte >> bit) & 1 == 1 {
+                    result = result.mul(self);
+                }
+            }
+        }
+        result
+    }
+
+    fn invert(&self) -> Fe {
+        self.pow(&P_MINUS_2)
+    }
+
+    fn is_negative(&self) -> bool {
+        self.to_bytes()[0] & 1 == 1
+    }
+
+    fn equals(&self, rhs:&Fe) -> bool {
+        self.to_bytes() == rhs.to_bytes()
+    }
+}
+
+/// d = -121665 / 121666
+const D:Fe = Fe([0x3_4dca_1359_78a3, 0x1_a828_3b15_6ebd, 0x5_e7a2_6001_c029, 0x7_39c6_63a0_3cbb, 0x5_2036_cee2_b6ff]);
+/// 2d
+const D2:Fe = Fe([0x6_9b94_26b2_f159, 0x3_5050_762a_dd7a, 0x3_cf44_c003_8052, 0x6_738c_c740_7977, 0x2_406d_9dc5_6dff]);
+/// sqrt(-1) = 2^((p - 1) / 4)
+const SQRT_M1:Fe = Fe([0x6_1b27_4a0e_a0b0, 0x0_d5a5_fc8f_189d, 0x7_ef5e_9cbd_0c60, 0x7_8595_a680_4c9e, 0x2_b832_4804_fc1d]);
+
+/// point of edwards25519 in extended coordinates (X:Y:Z:T)
+#[derive(Clone, Copy)]
+struct Point {
+    x: Fe,
+    y: Fe,
+    z: Fe,
+    t: Fe,
+}
+
+impl Point {
+    const IDENTITY:Point = Point { x: Fe::ZERO, y: Fe::ONE, z: Fe::ONE, t: Fe::ZERO };
+
+    fn decompress(bytes:&[u8; 32]) -> Option<Point> {
+        let y = Fe::from_bytes(bytes);
+        let mut canonical = *bytes;
+        canonical[31] &= 0x7f;
+        if y.to_bytes() != canonical {
+            return None; // y is p or more
+        }
+        let sign = bytes[31] >> 7 == 1;
+
+        // x^2 = (y^2 - 1) / (d y^2 + 1)
+        let y2 = y.square();
+        let u = y2.sub(&Fe::ONE);
+        let v = D.mul(&y2).add(&Fe::ONE);
+        let v3 = v.square().mul(&v);
+        let v7 = v3.square().mul(&v);
+        let mut x = u.mul(&v3).mul(&u.mul(&v7).pow(&P_MINUS_5_DIV_8));
+        let vx2 = v.mul(&x.square());
+        if !vx2.equals(&u) {
+            if vx2.equals(&u.negate()) {
+                x = x.mul(&SQRT_M1);
+            } else {
+                return None;
+            }
+        }
+        if x.equals(&Fe::ZERO) && sign {
+            return None;
+        }
+        if x.is_negative() != sign {
+            x = x.negate();
+        }
+        Some(Point { x, y, z: Fe::ONE, t: x.mul(&y) })
+    }
+
+    fn compress(&self) -> [u8; 32] {
+        let z_inv = self.z.invert();
+        let x = self.x.mul(&z_inv);
+        let y = self.y.mul(&z_inv);
+        let mut bytes = y.to_bytes();
+        bytes[31] |= (x.is_negative() as u8) << 7;
+        bytes
+    }
+
+    fn negate(&self) -> Point {
+        Point { x: self.x.negate(), y: self.y, z: self.z, t: self.t.negate() }
+    }
+
+    /// unified addition (also works for doubling)
+    fn add(&self, rhs:&Point) -> Point {
+        let a = self.y.sub(&self.x).mul(&rhs.y.sub(&rhs.x));
+        let b = self.y.add(&self.x).mul(&rhs.y.add(&rhs.x));
+        let c = self.t.mul(&D2).mul(&rhs.t);
+        let d = self.z.add(&self.z).mul(&rhs.z);
+        let e = b.sub(&a);
+        let f = d.sub(&c);
+        let g = d.add(&c);
+        let h = b.add(&a);
+        Point { x: e.mul(&f), y: g.mul(&h), z: f.mul(&g), t: e.mul(&h) }
+    }
+
+    /// multiplies by the scalar (little endian 64-bit limbs)
+    fn multiply(&self, scalar:&[u64; 4]) -> Point {
+        let mut result = Point::IDENTITY;
+        for limb in scalar.iter().rev() {
+            for bit in (0..64).rev() {
+                result = result.add(&result);
+                if (limb >> bit) & 1 == 1 {
+                    result = result.add(self);
+                }
+            }
+        }
+        result
+    }
+}
+
+fn scalar_limbs(bytes:&[u8; 32]) -> [u64; 4] {
+    let load = |i:usize| u64::from_le_bytes(bytes[i * 8..i * 8 + 8].try_into().unwrap());
+    [load(0), load(1), load(2), load(3)]
+}
+
+fn less_than(a:&[u64; 4], b:&[u64; 4]) -> bool {
+    for i in (0..4).rev() {
+        if a[i] != b[i] {
+            return a[i] < b[i];
+        }
+    }
+    false
+}
+
+/// reduces a little endian number modulo L
+fn reduce_scalar(bytes:&[u8]) -> [u64; 4] {
+    let mut result = [0u64; 4];
+    for byte in bytes.iter().rev() {
+        for bit in (0..8).rev() {
+            // result = result * 2 + bit (result < L < 2^253, so no overflow)
+            for i in (1..4).rev() {
+                result[i] = (result[i] << 1) | (result[i - 1] >> 63);
+            }
+            result[0] = (result[0] << 1) | ((byte >> bit) & 1) as u64;
+            if !less_than(&result, &L) {
+                let mut borrow = 0u64;
+                for i in 0..4 {
+                    let (value, b1) = result[i].overflowing_sub(L[i]);
+                    let (value, b2) = value.overflowing_sub(borrow);
+                    result[i] = value;
+                    borrow = (b1 || b2) as u64;
+                }
+            }
+        }
+    }
+    result
+}
+
+/////////////////////////////////////////////////////////////////////////////
+// Unit Test
+/////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_hex(hex:&str) -> Vec<u8> {
+        (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_verify() {
+        // RFC 8032 7.1 TEST 1
+        let public_key = from_hex("d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a");
+        let signature = from_hex("e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e065224901555fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b");
+        assert!(verify(&public_key, b"", &signature));
+        assert!(!verify(&public_key, b"x", &signature));
+
+        // RFC 8032 7.1 TEST 2
+        let public_key = from_hex("3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c");
+        let signature = from_hex("92a009a9f0d4cab8720e820b5f642540a2b27b5416503f8fb3762223ebdb69da085ac1e43e15996e458f3613d0f11d8c387b2eaeb4302aeeb00d291612bb0c00");
+        assert!(verify(&public_key, &[0x72], &signature));
+
+        // tampered signature
+        let mut tampered = signature.clone();
+        tampered[0] ^= 1;
+        assert!(!verify(&public_key, &[0x72], &tampered));
+
+        // invalid lengths
+        assert!(!verify(&public_key[0..31], &[0x72], &signature));
+        assert!(!verify(&public_key, &[0x72], &signature[0..63]));
+    }
+
+    #[test]
+    fn test_constants() {
+        let d = Fe([121665, 0, 0, 0, 0]).negate().mul(&Fe([121666, 0, 0, 0, 0]).invert());
+        assert!(D.equals(&d));
+        assert!(D2.equals(&d.add(&d)));
+        assert!(SQRT_M1.square().equals(&Fe::ONE.negate()));
+    }
+
+    #[test]
+    fn test_non_canonical_s() {
+        // S + L must be rejected
+        let public_key = from_hex("3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c");
+        let mut signature = from_hex("92a009a9f0d4cab8720e820b5f642540a2b27b5416503f8fb3762223ebdb69da085ac1e43e15996e458f3613d0f11d8c387b2eaeb4302aeeb00d291612bb0c00");
+        let mut carry = 0u16;
+        for i in 0..32 {
+            let l = (L[i / 8] >> ((i % 8) * 8)) as u8;
+            let sum = signature[32 + i] as u16 + l as u16 + carry;
+            signature[32 + i] = sum as u8;
+            carry = sum >> 8;
+        }
+        assert!(!verify(&public_key, &[0x72], &signature));
+    }
+}
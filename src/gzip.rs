@@ -0,0 +1,269 @@
+/// Canistorage
+///
+/// Copyright© 2025 toshio
+///
+/// Minimal gzip (RFC 1952) encoder using a single fixed-Huffman deflate block (RFC 1951)
+use std::cmp;
+
+const WINDOW_SIZE:usize = 32 * 1024;
+const MIN_MATCH:usize = 3;
+const MAX_MATCH:usize = 258;
+const HASH_BITS:usize = 15;
+const MAX_CHAIN:usize = 32;
+
+const LENGTH_BASE:[u16; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
+const LENGTH_EXTRA:[u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASE:[u16; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577];
+const DISTANCE_EXTRA:[u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+
+/// compresses the data in gzip format
+pub fn compress(data:&[u8]) -> Vec<u8> {
+    let mut writer = BitWriter::new();
+
+    // Header (no file name, no mtime, unknown OS)
+    writer.bytes.extend_from_slice(&[0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff]);
+
+    // A single final block with fixed Huffman codes
+    writer.write_bits(1, 1); // BFINAL
+    writer.write_bits(1, 2); // BTYPE=01
+    deflate(data, &mut writer);
+    writer.write_symbol(256); // end of block
+    writer.flush();
+
+    // Trailer
+    writer.bytes.extend_from_slice(&crc32(data).to_le_bytes());
+    writer.bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    writer.bytes
+}
+
+/// returns CRC-32 (ISO 3309) of the data
+pub fn crc32(data:&[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+/// LZ77 with hash chains
+fn deflate(data:&[u8], writer:&mut BitWriter) {
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut prev = vec![usize::MAX; WINDOW_SIZE];
+
+    let mut pos = 0;
+    while pos < data.len() {
+        // find the longest match
+        let mut best_length = 0;
+        let mut best_distance = 0;
+        if pos + MIN_MATCH <= data.len() {
+            let max_length = cmp::min(MAX_MATCH, data.len() - pos);
+            let mut candidate = head[hash(data, pos)];
+            let mut chain = 0;
+            while candidate != usize::MAX && pos - candidate <= WINDOW_SIZE && chain < MAX_CHAIN {
+                let mut length = 0;
+                while length < max_length && data[candidate + length] == data[pos + length] {
+                    length += 1;
+                }
+                if length > best_length {
+                    best_length = length;
+                    best_distance = pos - candidate;
+                    if length == max_length {
+                        break;
+                    }
+                }
+                let next = prev[candidate % WINDOW_SIZE];
+                if next == usize::MAX || next >= candidate {
+                    break;
+                }
+                candidate = next;
+                chain += 1;
+            }
+        }
+
+        let length = if best_length >= MIN_MATCH {
+            writer.write_length(best_length);
+            writer.write_distance(best_distance);
+            best_length
+        } else {
+            writer.write_symbol(data[pos] as u16);
+            1
+        };
+        for i in pos..pos + length {
+            if i + MIN_MATCH <= data.len() {
+                let h = hash(data, i);
+                prev[i % WINDOW_SIZE] = head[h];
+                head[h] = i;
+            }
+        }
+        pos += length;
+    }
+}
+
+/// hashes 3 bytes from the position
+fn hash(data:&[u8], pos:usize) -> usize {
+    let value = ((data[pos] as usize) << 16) | ((data[pos + 1] as usize) << 8) | data[pos + 2] as usize;
+    (value.wrapping_mul(2654435761) >> 8) & ((1 << HASH_BITS) - 1)
+}
+
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u32,
+    count: u8,
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        BitWriter {
+            bytes: Vec::new(),
+            buffer: 0,
+            count: 0,
+        }
+    }
+
+    /// writes bits LSB-first
+    fn write_bits(&mut self, value:u32, bits:u8) {
+        self.buffer |= value << self.count;
+        self.count += bits;
+        while self.count >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// writes a Huffman code MSB-first
+    fn write_code(&mut self, code:u32, bits:u8) {
+        let mut reversed = 0;
+        for i in 0..bits {
+            reversed |= ((code >> i) & 1) << (bits - 1 - i);
+        }
+        self.write_bits(reversed, bits);
+    }
+
+    /// writes a literal/length symbol with the fixed Huffman code
+    fn write_symbol(&mut self, symbol:u16) {
+        let symbol = symbol as u32;
+        match symbol {
+            0..=143 => self.write_code(0x30 + symbol, 8),
+            144..=255 => self.write_code(0x190 + symbol - 144, 9),
+            256..=279 => self.write_code(symbol - 256, 7),
+            _ => self.write_code(0xc0 + symbol - 280, 8),
+        }
+    }
+
+    fn write_length(&mut self, length:usize) {
+        let index = LENGTH_BASE.iter().rposition(|base| *base as usize <= length).unwrap();
+        self.write_symbol(257 + index as u16);
+        self.write_bits((length - LENGTH_BASE[index] as usize) as u32, LENGTH_EXTRA[index]);
+    }
+
+    fn write_distance(&mut self, distance:usize) {
+        let index = DISTANCE_BASE.iter().rposition(|base| *base as usize <= distance).unwrap();
+        self.write_code(index as u32, 5);
+        self.write_bits((distance - DISTANCE_BASE[index] as usize) as u32, DISTANCE_EXTRA[index]);
+    }
+
+    fn flush(&mut self) {
+        if self.count > 0 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer = 0;
+            self.count = 0;
+        }
+    }
+}
+
+/////////////////////////////////////////////////////////////////////////////
+// Unit Test
+/////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Decompresses gzip with a single fixed-Huffman block (enough to verify compress())
+    fn decompress(gzip:&[u8]) -> Vec<u8> {
+        assert_eq!(&gzip[0..3], &[0x1f, 0x8b, 0x08]);
+        let body = &gzip[10..gzip.len() - 8];
+        let mut position = 0usize; // bit position
+        let mut read_bits = |bits:u8| -> u32 {
+            let mut value = 0;
+            for i in 0..bits {
+                let bit = (body[position / 8] >> (position % 8)) & 1;
+                value |= (bit as u32) << i;
+                position += 1;
+            }
+            value
+        };
+        assert_eq!(read_bits(1), 1); // BFINAL
+        assert_eq!(read_bits(2), 1); // BTYPE=01
+
+        let mut output:Vec<u8> = Vec::new();
+        loop {
+            // read a fixed Huffman literal/length symbol MSB-first
+            let mut code = 0;
+            for _ in 0..7 {
+                code = (code << 1) | read_bits(1);
+            }
+            let symbol = if code <= 0x17 {
+                256 + code
+            } else {
+                code = (code << 1) | read_bits(1);
+                if (0x30..=0xbf).contains(&code) {
+                    code - 0x30
+                } else if (0xc0..=0xc7).contains(&code) {
+                    280 + code - 0xc0
+                } else {
+                    code = (code << 1) | read_bits(1);
+                    144 + code - 0x190
+                }
+            };
+            match symbol {
+                0..=255 => output.push(symbol as u8),
+                256 => break,
+                _ => {
+                    let index = (symbol - 257) as usize;
+                    let length = LENGTH_BASE[index] as usize + read_bits(LENGTH_EXTRA[index]) as usize;
+                    let mut code = 0;
+                    for _ in 0..5 {
+                        code = (code << 1) | read_bits(1);
+                    }
+                    let index = code as usize;
+                    let distance = DISTANCE_BASE[index] as usize + read_bits(DISTANCE_EXTRA[index]) as usize;
+                    let start = output.len() - distance;
+                    for i in 0..length {
+                        output.push(output[start + i]);
+                    }
+                }
+            }
+        }
+
+        let trailer = &gzip[gzip.len() - 8..];
+        assert_eq!(u32::from_le_bytes(trailer[0..4].try_into().unwrap()), crc32(&output));
+        assert_eq!(u32::from_le_bytes(trailer[4..8].try_into().unwrap()), output.len() as u32);
+        output
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn test_compress() {
+        // empty
+        assert_eq!(decompress(&compress(b"")), b"");
+
+        // compressible
+        let data = "Hello, World! ".repeat(1000).into_bytes();
+        let compressed = compress(&data);
+        assert!(compressed.len() < data.len() / 10);
+        assert_eq!(decompress(&compressed), data);
+
+        // not compressible
+        let data:Vec<u8> = (0..100_000u32).map(|i| (i.wrapping_mul(2654435761) >> 13) as u8).collect();
+        assert_eq!(decompress(&compress(&data)), data);
+    }
+}
@@ -3,19 +3,54 @@
 /// Copyright© 2025 toshio
 ///
 use std::cell::RefCell;
-use candid::Principal;
+use candid::{CandidType, Principal};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
 use ic_stable_structures::{memory_manager::{MemoryId, MemoryManager}, DefaultMemoryImpl};
 pub mod canistorage;
+mod base64;
+mod ed25519;
+mod gzip;
 use crate::canistorage::{
     Error,
     Permission,
     Info,
+    FileInfo,
+    Upload,
+    UploadStatus,
     Download,
+    Health,
+    Limits,
+    CopyProgress,
+    MoveProgress,
+    MimetypePolicy,
+    Op,
+    DirEntry,
+    ManifestEntry,
+    SortKey,
+    ParentUpdate,
+    HttpRequest,
+    HttpResponse,
+    MerkleProof,
     FileInfoForPoC, // for PoC
 }; // for export_candid!()
 
 /// wasi2ic
 const WASI_MEMORY_ID: MemoryId = MemoryId::new(0);
+/// canistorage settings
+const CONFIG_MEMORY_ID: MemoryId = MemoryId::new(1);
+/// advisory locks
+const LOCK_MEMORY_ID: MemoryId = MemoryId::new(2);
+/// reference counts of content-addressed blobs
+const BLOB_MEMORY_ID: MemoryId = MemoryId::new(3);
+/// metadata of files/directories
+const FILE_INFO_MEMORY_ID: MemoryId = MemoryId::new(4);
+/// quotas of principals
+const QUOTA_MEMORY_ID: MemoryId = MemoryId::new(5);
+/// bytes used by principals
+const USAGE_MEMORY_ID: MemoryId = MemoryId::new(6);
+/// bytes under directories with a quota
+const SUBTREE_SIZE_MEMORY_ID: MemoryId = MemoryId::new(7);
 
 thread_local! {
     static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> =
@@ -28,10 +63,31 @@ fn init() {
     ic_wasi_polyfill::init_with_memory(&[0u8; 32], &[], wasi_memory);
 }
 
+#[ic_cdk::pre_upgrade]
+fn pre_upgrade() {
+    // upload sessions are kept in heap memory
+    let count = canistorage::active_upload_count();
+    if count > 0 {
+        ic_cdk::println!("Discarding {} active upload session(s)", count);
+    }
+}
+
 #[ic_cdk::post_upgrade]
 fn post_upgrade() {
     let wasi_memory = MEMORY_MANAGER.with(|m| m.borrow().get(WASI_MEMORY_ID));
     ic_wasi_polyfill::init_with_memory(&[0u8; 32], &[], wasi_memory);    
+    canistorage::migrate_file_infos();
+    canistorage::build_usage();
+}
+
+/// build information of the deployed canister
+#[derive(CandidType, Deserialize, Debug, Clone)]
+struct BuildInfo {
+    version: String,
+    git_hash: String, // "unknown" if built outside of a git repository
+    rustc_version: String,
+    ic_cdk_version: String,
+    candid_hash: String, // sha256 of the candid interface in lowercase hex
 }
 
 #[ic_cdk::query]
@@ -39,6 +95,18 @@ fn version() -> String {
     format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))
 }
 
+/// returns the version with the information embedded at build time
+#[ic_cdk::query(name="buildInfo")]
+fn build_info() -> BuildInfo {
+    BuildInfo {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        git_hash: env!("CANISTORAGE_GIT_HASH").to_string(),
+        rustc_version: env!("CANISTORAGE_RUSTC_VERSION").to_string(),
+        ic_cdk_version: env!("CANISTORAGE_IC_CDK_VERSION").to_string(),
+        candid_hash: canistorage::to_hex(&Sha256::digest(__export_service())),
+    }
+}
+
 // Enable Candid export
 ic_cdk_macros::export_candid!();
 
@@ -51,4 +119,15 @@ mod tests {
     fn test_version() {
         assert_eq!(version(), format!("canistorage {}", env!("CARGO_PKG_VERSION")));
     }
+
+    #[test]
+    fn test_build_info() {
+        let info = build_info();
+        assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+        assert!(!info.git_hash.is_empty());
+        assert!(info.rustc_version.starts_with("rustc "));
+        assert!(!info.ic_cdk_version.is_empty());
+        assert_eq!(info.candid_hash.len(), 64);
+        assert_eq!(build_info().candid_hash, info.candid_hash);
+    }
 }